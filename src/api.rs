@@ -1,6 +1,9 @@
 pub mod admission_review;
 mod api_error;
+pub(crate) mod authorization;
+pub(crate) mod bearer_token;
 pub(crate) mod handlers;
 mod raw_review;
+pub(crate) mod request_metrics;
 mod service;
 pub(crate) mod state;
@@ -1,6 +1,8 @@
 use policy_evaluator::admission_request::AdmissionRequest;
 use policy_evaluator::admission_response::AdmissionResponse;
 
+use crate::tracing::policy_logs::PolicyLogEntry;
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AdmissionReviewRequest {
@@ -23,14 +25,19 @@ pub struct AdmissionReviewResponse {
     pub api_version: Option<String>,
 
     pub response: AdmissionResponse,
+
+    /// The log messages emitted while evaluating the policy in debug mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_logs: Option<Vec<PolicyLogEntry>>,
 }
 
 impl AdmissionReviewResponse {
-    pub fn new(response: AdmissionResponse) -> Self {
+    pub fn new(response: AdmissionResponse, policy_logs: Option<Vec<PolicyLogEntry>>) -> Self {
         AdmissionReviewResponse {
             api_version: Some(String::from("admission.k8s.io/v1")),
             kind: Some(String::from("AdmissionReview")),
             response,
+            policy_logs,
         }
     }
 }
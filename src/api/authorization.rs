@@ -0,0 +1,544 @@
+use std::{collections::HashSet, sync::Arc};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    extract::{Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rustls_pki_types::CertificateDer;
+use serde::Deserialize;
+use tracing::{debug, warn};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+use crate::{
+    api::api_error::ApiError,
+    config::{ClientAuthorizationConfig, EndpointKind},
+    metrics,
+    pattern::{Pattern, compile_patterns},
+};
+
+/// The identity of a client authenticated via mTLS, as found inside of its certificate
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ClientIdentity {
+    /// The common names of the certificate subject
+    pub(crate) common_names: Vec<String>,
+    /// The DNS names, URIs and email addresses of the subject alternative names
+    pub(crate) sans: Vec<String>,
+}
+
+impl ClientIdentity {
+    pub(crate) fn from_certificate(cert: &CertificateDer<'_>) -> Result<Self> {
+        let (_, cert) = X509Certificate::from_der(cert)
+            .map_err(|e| anyhow!("Cannot parse client certificate: {e}"))?;
+
+        let common_names = cert
+            .subject()
+            .iter_common_name()
+            .filter_map(|common_name| common_name.as_str().ok())
+            .map(|common_name| common_name.to_owned())
+            .collect();
+        let sans = cert
+            .subject_alternative_name()
+            .map_err(|e| anyhow!("Cannot parse subject alternative names: {e}"))?
+            .map(|san| {
+                san.value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match name {
+                        GeneralName::DNSName(name)
+                        | GeneralName::URI(name)
+                        | GeneralName::RFC822Name(name) => Some(name.to_string()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self { common_names, sans })
+    }
+
+    /// A human readable name of the client, used inside of the logs
+    fn name(&self) -> &str {
+        self.common_names
+            .first()
+            .or(self.sans.first())
+            .map_or("unknown", |name| name.as_str())
+    }
+}
+
+struct Rule {
+    subjects: Vec<Pattern>,
+    sans: Vec<Pattern>,
+    endpoints: Vec<EndpointKind>,
+    policies: Vec<Pattern>,
+    debug: bool,
+}
+
+impl Rule {
+    fn matches_client(&self, identity: &ClientIdentity) -> bool {
+        identity.common_names.iter().any(|common_name| {
+            self.subjects
+                .iter()
+                .any(|pattern| pattern.matches(common_name))
+        }) || identity
+            .sans
+            .iter()
+            .any(|san| self.sans.iter().any(|pattern| pattern.matches(san)))
+    }
+
+    fn matches_request(&self, endpoint: EndpointKind, policy_id: Option<&str>) -> bool {
+        if !self.endpoints.contains(&endpoint) {
+            return false;
+        }
+        match policy_id {
+            Some(policy_id) if !self.policies.is_empty() => self
+                .policies
+                .iter()
+                .any(|pattern| pattern.matches(policy_id)),
+            _ => true,
+        }
+    }
+}
+
+/// Decides which endpoints and policies the mTLS clients can use
+pub(crate) struct ClientAuthorizer {
+    rules: Vec<Rule>,
+    /// The policies setting `debug: true`, they are evaluated in debug mode for all the
+    /// clients allowed to use it, even when they don't request it
+    debug_policies: HashSet<String>,
+}
+
+impl ClientAuthorizer {
+    pub(crate) fn new(
+        config: &ClientAuthorizationConfig,
+        debug_policies: HashSet<String>,
+    ) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                if rule.subjects.is_empty() && rule.sans.is_empty() {
+                    return Err(anyhow!(
+                        "client authorization rule #{index} must have at least one subject or SAN pattern"
+                    ));
+                }
+                Ok(Rule {
+                    subjects: compile_patterns(&rule.subjects)?,
+                    sans: compile_patterns(&rule.sans)?,
+                    endpoints: rule.endpoints.clone(),
+                    policies: compile_patterns(&rule.policies)?,
+                    debug: rule.debug,
+                })
+            })
+            .collect::<Result<Vec<Rule>>>()?;
+
+        Ok(Self {
+            rules,
+            debug_policies,
+        })
+    }
+
+    pub(crate) fn is_allowed(
+        &self,
+        identity: &ClientIdentity,
+        endpoint: EndpointKind,
+        policy_id: Option<&str>,
+    ) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.matches_client(identity) && rule.matches_request(endpoint, policy_id))
+    }
+
+    /// Whether the client can evaluate the given policy in debug mode
+    pub(crate) fn is_debug_allowed(
+        &self,
+        identity: &ClientIdentity,
+        endpoint: EndpointKind,
+        policy_id: Option<&str>,
+    ) -> bool {
+        self.rules.iter().any(|rule| {
+            rule.debug && rule.matches_client(identity) && rule.matches_request(endpoint, policy_id)
+        })
+    }
+}
+
+/// Added to the requests of the clients evaluating a policy in debug mode, as requested
+/// by the `debug=true` query parameter or by the policy. Only the authorized clients can
+/// do that, the logs of the policies are returned only when this is set
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DebugMode;
+
+#[derive(Deserialize)]
+struct DebugParams {
+    #[serde(default)]
+    debug: bool,
+}
+
+/// The endpoint targeted by the request path, together with the policy id, if any
+pub(crate) fn requested_endpoint(path: &str) -> Option<(EndpointKind, Option<&str>)> {
+    if path.starts_with("/debug/pprof/") {
+        return Some((EndpointKind::Pprof, None));
+    }
+    if path == "/debug/log_level" {
+        return Some((EndpointKind::LogLevel, None));
+    }
+
+    let (prefix, policy_id) = path.strip_prefix('/')?.split_once('/')?;
+    let endpoint = match prefix {
+        "validate" => EndpointKind::Validate,
+        "validate_raw" => EndpointKind::ValidateRaw,
+        "audit" => EndpointKind::Audit,
+        _ => return None,
+    };
+    Some((endpoint, Some(policy_id)))
+}
+
+/// Middleware rejecting the requests of the mTLS clients that are not allowed to call
+/// the requested endpoint, or to evaluate the requested policy
+pub(crate) async fn authorize_client(
+    State(authorizer): State<Arc<ClientAuthorizer>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some((endpoint, policy_id)) = requested_endpoint(request.uri().path()) else {
+        return next.run(request).await;
+    };
+
+    // Set by the TLS acceptor, missing when TLS is not enabled
+    let identity = request
+        .extensions()
+        .get::<Option<ClientIdentity>>()
+        .cloned()
+        .flatten();
+    let allowed = identity
+        .as_ref()
+        .is_some_and(|identity| authorizer.is_allowed(identity, endpoint, policy_id));
+    let client = identity
+        .as_ref()
+        .map_or("unknown", |identity| identity.name());
+    metrics::add_client_authorization_decision(endpoint.as_str(), allowed);
+
+    if !allowed {
+        warn!(
+            client,
+            endpoint = endpoint.as_str(),
+            policy_id,
+            "client is not authorized"
+        );
+        return ApiError {
+            status: StatusCode::FORBIDDEN,
+            message: format!(
+                "client '{client}' is not authorized to call {}",
+                request.uri().path()
+            ),
+        }
+        .into_response();
+    }
+
+    let debug_requested =
+        Query::<DebugParams>::try_from_uri(request.uri()).is_ok_and(|Query(params)| params.debug);
+    let debug_allowed = identity
+        .as_ref()
+        .is_some_and(|identity| authorizer.is_debug_allowed(identity, endpoint, policy_id));
+    if debug_requested {
+        if !debug_allowed {
+            warn!(
+                client,
+                endpoint = endpoint.as_str(),
+                policy_id,
+                "client is not authorized to use the debug mode"
+            );
+            return ApiError {
+                status: StatusCode::FORBIDDEN,
+                message: format!("client '{client}' is not authorized to use the debug mode"),
+            }
+            .into_response();
+        }
+    }
+
+    let debug_mode = debug_allowed
+        && (debug_requested
+            || policy_id.is_some_and(|policy_id| authorizer.debug_policies.contains(policy_id)));
+    debug!(
+        client,
+        endpoint = endpoint.as_str(),
+        policy_id,
+        debug_mode,
+        "client authorized"
+    );
+    if debug_mode {
+        request.extensions_mut().insert(DebugMode);
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Extension, Router, body::Body, middleware::from_fn_with_state, routing::post};
+    use rcgen::{CertificateParams, DnType, KeyPair, SanType};
+    use rstest::rstest;
+    use tower::ServiceExt;
+
+    fn authorizer() -> ClientAuthorizer {
+        let config: ClientAuthorizationConfig = serde_yaml::from_str(
+            r#"
+rules:
+- subjects: ["audit-scanner"]
+  endpoints: ["audit"]
+- sans: ["spiffe://cluster.local/ns/ci/*"]
+  endpoints: ["validate", "validateRaw"]
+  policies: ["ci-*"]
+  debug: true
+"#,
+        )
+        .unwrap();
+        ClientAuthorizer::new(&config, HashSet::from(["ci-debug-policy".to_owned()])).unwrap()
+    }
+
+    fn identity(common_name: &str, san: &str) -> ClientIdentity {
+        ClientIdentity {
+            common_names: vec![common_name.to_owned()],
+            sans: vec![san.to_owned()],
+        }
+    }
+
+    #[rstest]
+    #[case::audit_scanner_audit(
+        "audit-scanner",
+        "",
+        EndpointKind::Audit,
+        Some("privileged-pods"),
+        true
+    )]
+    #[case::audit_scanner_validate(
+        "audit-scanner",
+        "",
+        EndpointKind::Validate,
+        Some("privileged-pods"),
+        false
+    )]
+    #[case::audit_scanner_pprof("audit-scanner", "", EndpointKind::Pprof, None, false)]
+    #[case::ci_allowed_policy(
+        "pipeline",
+        "spiffe://cluster.local/ns/ci/sa/runner",
+        EndpointKind::ValidateRaw,
+        Some("ci-image-signatures"),
+        true
+    )]
+    #[case::ci_other_policy(
+        "pipeline",
+        "spiffe://cluster.local/ns/ci/sa/runner",
+        EndpointKind::ValidateRaw,
+        Some("privileged-pods"),
+        false
+    )]
+    #[case::unknown_client(
+        "someone",
+        "spiffe://cluster.local/ns/default/sa/default",
+        EndpointKind::Validate,
+        Some("ci-image-signatures"),
+        false
+    )]
+    fn authorize_requests(
+        #[case] common_name: &str,
+        #[case] san: &str,
+        #[case] endpoint: EndpointKind,
+        #[case] policy_id: Option<&str>,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            expected,
+            authorizer().is_allowed(&identity(common_name, san), endpoint, policy_id)
+        );
+    }
+
+    #[rstest]
+    #[case::audit_scanner(
+        "audit-scanner",
+        "",
+        EndpointKind::Audit,
+        Some("privileged-pods"),
+        false
+    )]
+    #[case::ci_allowed_policy(
+        "pipeline",
+        "spiffe://cluster.local/ns/ci/sa/runner",
+        EndpointKind::ValidateRaw,
+        Some("ci-image-signatures"),
+        true
+    )]
+    #[case::ci_other_policy(
+        "pipeline",
+        "spiffe://cluster.local/ns/ci/sa/runner",
+        EndpointKind::ValidateRaw,
+        Some("privileged-pods"),
+        false
+    )]
+    fn authorize_debug_mode(
+        #[case] common_name: &str,
+        #[case] san: &str,
+        #[case] endpoint: EndpointKind,
+        #[case] policy_id: Option<&str>,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            expected,
+            authorizer().is_debug_allowed(&identity(common_name, san), endpoint, policy_id)
+        );
+    }
+
+    #[test]
+    fn reject_rules_without_clients() {
+        let config: ClientAuthorizationConfig = serde_yaml::from_str(
+            r#"
+rules:
+- endpoints: ["audit"]
+"#,
+        )
+        .unwrap();
+        assert!(ClientAuthorizer::new(&config, HashSet::new()).is_err());
+    }
+
+    #[rstest]
+    #[case("/validate/pod-privileged", Some((EndpointKind::Validate, Some("pod-privileged"))))]
+    #[case("/validate_raw/pod-privileged", Some((EndpointKind::ValidateRaw, Some("pod-privileged"))))]
+    #[case("/audit/pod-privileged", Some((EndpointKind::Audit, Some("pod-privileged"))))]
+    #[case("/debug/pprof/cpu", Some((EndpointKind::Pprof, None)))]
+    #[case("/debug/log_level", Some((EndpointKind::LogLevel, None)))]
+    #[case("/readiness", None)]
+    fn endpoint_from_path(
+        #[case] path: &str,
+        #[case] expected: Option<(EndpointKind, Option<&str>)>,
+    ) {
+        assert_eq!(expected, requested_endpoint(path));
+    }
+
+    #[test]
+    fn identity_from_certificate() {
+        let mut params =
+            CertificateParams::new(vec!["audit-scanner.kubewarden.svc".to_owned()]).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "audit-scanner");
+        params.subject_alt_names.push(SanType::URI(
+            "spiffe://cluster.local/ns/kubewarden/sa/audit-scanner"
+                .try_into()
+                .unwrap(),
+        ));
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+
+        let identity = ClientIdentity::from_certificate(cert.der()).unwrap();
+        assert_eq!(identity.common_names, vec!["audit-scanner"]);
+        assert_eq!(
+            identity.sans,
+            vec![
+                "audit-scanner.kubewarden.svc",
+                "spiffe://cluster.local/ns/kubewarden/sa/audit-scanner"
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::authorized(
+        Some(identity("audit-scanner", "")),
+        "/audit/pod-privileged",
+        StatusCode::OK
+    )]
+    #[case::not_authorized(
+        Some(identity("audit-scanner", "")),
+        "/validate/pod-privileged",
+        StatusCode::FORBIDDEN
+    )]
+    #[case::no_client_certificate(None, "/audit/pod-privileged", StatusCode::FORBIDDEN)]
+    #[case::debug_not_authorized(
+        Some(identity("audit-scanner", "")),
+        "/audit/pod-privileged?debug=true",
+        StatusCode::FORBIDDEN
+    )]
+    #[tokio::test]
+    async fn forbid_unauthorized_requests(
+        #[case] identity: Option<ClientIdentity>,
+        #[case] path: &str,
+        #[case] expected_status: StatusCode,
+    ) {
+        let mut router = Router::new()
+            .route("/audit/{policy_id}", post(|| async { "ok" }))
+            .route("/validate/{policy_id}", post(|| async { "ok" }))
+            .route_layer(from_fn_with_state(Arc::new(authorizer()), authorize_client));
+        if identity.is_some() {
+            router = router.layer(Extension(identity));
+        }
+
+        let request = Request::builder()
+            .method("POST")
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+
+        assert_eq!(expected_status, response.status());
+    }
+
+    #[rstest]
+    #[case::requested("/validate/ci-image-signatures?debug=true", true)]
+    #[case::not_requested("/validate/ci-image-signatures", false)]
+    #[case::debug_policy("/validate/ci-debug-policy", true)]
+    #[tokio::test]
+    async fn debug_mode_of_allowed_clients(#[case] path: &str, #[case] expected: bool) {
+        let router = Router::new()
+            .route(
+                "/validate/{policy_id}",
+                post(|debug_mode: Option<Extension<DebugMode>>| async move {
+                    debug_mode.is_some().to_string()
+                }),
+            )
+            .route_layer(from_fn_with_state(Arc::new(authorizer()), authorize_client))
+            .layer(Extension(Some(identity(
+                "pipeline",
+                "spiffe://cluster.local/ns/ci/sa/runner",
+            ))));
+
+        let request = Request::builder()
+            .method("POST")
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        assert_eq!(expected.to_string().as_bytes(), &body[..]);
+    }
+
+    #[tokio::test]
+    async fn debug_policy_of_clients_not_allowed_to_debug() {
+        let router = Router::new()
+            .route(
+                "/audit/{policy_id}",
+                post(|debug_mode: Option<Extension<DebugMode>>| async move {
+                    debug_mode.is_some().to_string()
+                }),
+            )
+            .route_layer(from_fn_with_state(Arc::new(authorizer()), authorize_client))
+            .layer(Extension(Some(identity("audit-scanner", ""))));
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/audit/ci-debug-policy")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        assert_eq!(b"false".as_slice(), &body[..]);
+    }
+}
@@ -0,0 +1,842 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ring::signature::{
+    ECDSA_P256_SHA256_FIXED, RSA_PKCS1_2048_8192_SHA256, RsaPublicKeyComponents, UnparsedPublicKey,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, info, warn};
+
+#[cfg(target_os = "linux")]
+use crate::certs::{files_digest, watch_files_and_parent_dirs};
+use crate::{
+    api::{
+        api_error::ApiError,
+        authorization::{ClientIdentity, requested_endpoint},
+    },
+    config::{BearerTokenAuthConfig, EndpointKind, ServiceAccountTokensConfig},
+    metrics,
+    pattern::{Pattern, compile_patterns},
+};
+
+/// A token defined inside of the static tokens file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+struct StaticToken {
+    /// The name of the client using the token, used inside of the logs
+    name: String,
+    token: String,
+    /// Patterns of the policy ids the client can evaluate. Any policy is allowed when empty
+    #[serde(default)]
+    policies: Vec<String>,
+}
+
+/// What an authenticated client can do
+#[derive(Clone, Debug)]
+struct Grant {
+    /// The name of the client
+    name: String,
+    /// Patterns of the policy ids the client can evaluate. Any policy is allowed when empty
+    policies: Vec<Pattern>,
+}
+
+impl Grant {
+    fn allows(&self, policy_id: Option<&str>) -> bool {
+        match policy_id {
+            Some(policy_id) if !self.policies.is_empty() => self
+                .policies
+                .iter()
+                .any(|pattern| pattern.matches(policy_id)),
+            _ => true,
+        }
+    }
+}
+
+/// The static tokens are indexed by their sha256 digest, this prevents the lookup from
+/// leaking the tokens via timing attacks
+type StaticTokens = HashMap<[u8; 32], Grant>;
+
+fn token_digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+async fn load_static_tokens(path: &Path) -> Result<StaticTokens> {
+    let contents = tokio::fs::read(path)
+        .await
+        .map_err(|e| anyhow!("Cannot read static tokens file {}: {e}", path.display()))?;
+    let tokens: Vec<StaticToken> = serde_yaml::from_slice(&contents)
+        .map_err(|e| anyhow!("Cannot parse static tokens file {}: {e}", path.display()))?;
+
+    let mut static_tokens = HashMap::new();
+    for token in tokens {
+        if token.token.is_empty() {
+            return Err(anyhow!("the static token of '{}' is empty", token.name));
+        }
+        let grant = Grant {
+            name: token.name,
+            policies: compile_patterns(&token.policies)?,
+        };
+        if let Some(previous) = static_tokens.insert(token_digest(&token.token), grant) {
+            return Err(anyhow!(
+                "the static token of '{}' is used by another client too",
+                previous.name
+            ));
+        }
+    }
+
+    Ok(static_tokens)
+}
+
+/// A key of a JSON Web Key Set. Only RSA and P-256 keys are supported
+#[derive(Deserialize, Debug, Clone)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    // RSA keys
+    n: Option<String>,
+    e: Option<String>,
+    // EC keys
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+impl Jwk {
+    /// Verify the signature of a JWT. Returns false when the key cannot be used with
+    /// the given algorithm
+    fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> bool {
+        let decode = |value: &Option<String>| {
+            value
+                .as_ref()
+                .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+        };
+
+        match (alg, self.kty.as_str()) {
+            ("RS256", "RSA") => {
+                let (Some(n), Some(e)) = (decode(&self.n), decode(&self.e)) else {
+                    return false;
+                };
+                RsaPublicKeyComponents { n, e }
+                    .verify(&RSA_PKCS1_2048_8192_SHA256, message, signature)
+                    .is_ok()
+            }
+            ("ES256", "EC") if self.crv.as_deref() == Some("P-256") => {
+                let (Some(x), Some(y)) = (decode(&self.x), decode(&self.y)) else {
+                    return false;
+                };
+                // uncompressed point
+                let mut public_key = vec![0x04];
+                public_key.extend(x);
+                public_key.extend(y);
+                UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, public_key)
+                    .verify(message, signature)
+                    .is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+async fn load_jwks(path: &Path) -> Result<Vec<Jwk>> {
+    let contents = tokio::fs::read(path)
+        .await
+        .map_err(|e| anyhow!("Cannot read JWKS file {}: {e}", path.display()))?;
+    let jwks: Jwks = serde_json::from_slice(&contents)
+        .map_err(|e| anyhow!("Cannot parse JWKS file {}: {e}", path.display()))?;
+    Ok(jwks.keys)
+}
+
+#[derive(Deserialize, Debug)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains_any(&self, audiences: &[String]) -> bool {
+        match self {
+            Audience::One(audience) => audiences.contains(audience),
+            Audience::Many(many) => many.iter().any(|audience| audiences.contains(audience)),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct JwtClaims {
+    iss: Option<String>,
+    sub: Option<String>,
+    aud: Option<Audience>,
+    exp: Option<u64>,
+    nbf: Option<u64>,
+}
+
+/// Validates the Kubernetes ServiceAccount tokens
+struct ServiceAccountTokens {
+    config: ServiceAccountTokensConfig,
+    keys: RwLock<Vec<Jwk>>,
+    grants: Vec<(Pattern, Vec<Pattern>)>,
+}
+
+impl ServiceAccountTokens {
+    async fn new(config: ServiceAccountTokensConfig) -> Result<Self> {
+        let keys = load_jwks(&config.jwks_file).await?;
+        let grants = config
+            .service_accounts
+            .iter()
+            .map(|grant| {
+                Ok((
+                    Pattern::new(&grant.subject)?,
+                    compile_patterns(&grant.policies)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            config,
+            keys: RwLock::new(keys),
+            grants,
+        })
+    }
+
+    /// Validate the token and return the grant of the service account
+    fn authenticate(&self, token: &str) -> Result<Grant> {
+        let claims = self.verify(token)?;
+        let subject = claims
+            .sub
+            .ok_or_else(|| anyhow!("the token has no subject"))?;
+
+        let matching_grants: Vec<&Vec<Pattern>> = self
+            .grants
+            .iter()
+            .filter(|(subject_pattern, _)| subject_pattern.matches(&subject))
+            .map(|(_, policies)| policies)
+            .collect();
+        if matching_grants.is_empty() {
+            return Err(anyhow!("service account '{subject}' is not allowed"));
+        }
+        // A grant without policy patterns allows any policy
+        let policies = if matching_grants.iter().any(|policies| policies.is_empty()) {
+            Vec::new()
+        } else {
+            matching_grants.into_iter().flatten().cloned().collect()
+        };
+
+        Ok(Grant {
+            name: subject,
+            policies,
+        })
+    }
+
+    /// Verify the signature and the claims of the token
+    fn verify(&self, token: &str) -> Result<JwtClaims> {
+        let mut parts = token.split('.');
+        let (Some(encoded_header), Some(encoded_payload), Some(encoded_signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(anyhow!("the token is not a JWT"));
+        };
+        let decode = |part: &str| {
+            URL_SAFE_NO_PAD
+                .decode(part)
+                .map_err(|e| anyhow!("cannot decode the token: {e}"))
+        };
+
+        let header: JwtHeader = serde_json::from_slice(&decode(encoded_header)?)
+            .map_err(|e| anyhow!("cannot parse the token header: {e}"))?;
+        let signature = decode(encoded_signature)?;
+        // the signature covers the encoded header and payload
+        let message = &token[..encoded_header.len() + 1 + encoded_payload.len()];
+
+        let keys = self.keys.read().expect("cannot acquire JWKS lock");
+        let verified = keys
+            .iter()
+            .filter(|key| header.kid.is_none() || key.kid == header.kid)
+            .any(|key| key.verify(&header.alg, message.as_bytes(), &signature));
+        if !verified {
+            return Err(anyhow!("the token signature is not valid"));
+        }
+
+        let claims: JwtClaims = serde_json::from_slice(&decode(encoded_payload)?)
+            .map_err(|e| anyhow!("cannot parse the token claims: {e}"))?;
+
+        if claims.iss.as_deref() != Some(self.config.issuer.as_str()) {
+            return Err(anyhow!(
+                "the token has not been issued by the expected issuer"
+            ));
+        }
+        if !claims
+            .aud
+            .as_ref()
+            .is_some_and(|aud| aud.contains_any(&self.config.audiences))
+        {
+            return Err(anyhow!(
+                "the token has not been issued for the expected audience"
+            ));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        match claims.exp {
+            Some(exp) if exp > now => {}
+            Some(_) => return Err(anyhow!("the token is expired")),
+            None => return Err(anyhow!("the token has no expiration time")),
+        }
+        if claims.nbf.is_some_and(|nbf| nbf > now) {
+            return Err(anyhow!("the token is not valid yet"));
+        }
+
+        Ok(claims)
+    }
+
+    async fn reload_keys(&self) -> Result<()> {
+        let keys = load_jwks(&self.config.jwks_file).await?;
+        *self.keys.write().expect("cannot acquire JWKS lock") = keys;
+        Ok(())
+    }
+}
+
+/// Authenticates the clients via bearer tokens, either static ones or Kubernetes
+/// ServiceAccount tokens
+pub(crate) struct BearerTokenAuthenticator {
+    endpoints: Vec<EndpointKind>,
+    static_tokens_file: Option<PathBuf>,
+    static_tokens: RwLock<StaticTokens>,
+    service_account_tokens: Option<ServiceAccountTokens>,
+}
+
+impl BearerTokenAuthenticator {
+    pub(crate) async fn new(config: BearerTokenAuthConfig) -> Result<Self> {
+        let static_tokens = match &config.static_tokens_file {
+            Some(path) => load_static_tokens(path).await?,
+            None => HashMap::new(),
+        };
+        let service_account_tokens = match config.service_account_tokens {
+            Some(service_account_tokens) => {
+                Some(ServiceAccountTokens::new(service_account_tokens).await?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            endpoints: config.endpoints,
+            static_tokens_file: config.static_tokens_file,
+            static_tokens: RwLock::new(static_tokens),
+            service_account_tokens,
+        })
+    }
+
+    fn authenticate(&self, token: &str) -> Result<Grant> {
+        if let Some(grant) = self
+            .static_tokens
+            .read()
+            .expect("cannot acquire static tokens lock")
+            .get(&token_digest(token))
+        {
+            return Ok(grant.clone());
+        }
+
+        match &self.service_account_tokens {
+            Some(service_account_tokens) => service_account_tokens.authenticate(token),
+            None => Err(anyhow!("unknown token")),
+        }
+    }
+
+    async fn reload_static_tokens(&self) -> Result<()> {
+        if let Some(path) = &self.static_tokens_file {
+            let static_tokens = load_static_tokens(path).await?;
+            *self
+                .static_tokens
+                .write()
+                .expect("cannot acquire static tokens lock") = static_tokens;
+        }
+        Ok(())
+    }
+}
+
+/// There's no watching of the token files on non-linux platforms
+/// since we rely on inotify to watch for changes
+#[cfg(not(target_os = "linux"))]
+pub(crate) async fn watch_bearer_token_files(
+    _authenticator: Arc<BearerTokenAuthenticator>,
+) -> Result<()> {
+    Ok(())
+}
+
+/// Reload the static tokens file and the JWKS file when their contents change.
+/// The files and their parent directories are watched, like the TLS files, hence
+/// the updates of the Secret and ConfigMap volumes are detected too.
+/// The current tokens and keys are kept when the new files cannot be loaded.
+///
+/// Relying on inotify is only available on linux
+#[cfg(target_os = "linux")]
+pub(crate) async fn watch_bearer_token_files(
+    authenticator: Arc<BearerTokenAuthenticator>,
+) -> Result<()> {
+    use tokio_stream::StreamExt;
+
+    let static_tokens_file = authenticator.static_tokens_file.clone();
+    let jwks_file = authenticator
+        .service_account_tokens
+        .as_ref()
+        .map(|service_account_tokens| service_account_tokens.config.jwks_file.clone());
+    let paths: Vec<&Path> = static_tokens_file
+        .iter()
+        .chain(jwks_file.iter())
+        .map(PathBuf::as_path)
+        .collect();
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let inotify =
+        inotify::Inotify::init().map_err(|e| anyhow!("Cannot initialize inotify: {e}"))?;
+    watch_files_and_parent_dirs(&inotify, &paths)?;
+
+    // The digests of the files in use, the files are reloaded only when their
+    // contents change
+    let mut static_tokens_digest = match &static_tokens_file {
+        Some(path) => Some(files_digest(&[path.as_path()]).await?),
+        None => None,
+    };
+    let mut jwks_digest = match &jwks_file {
+        Some(path) => Some(files_digest(&[path.as_path()]).await?),
+        None => None,
+    };
+
+    let buffer = [0; 1024];
+    let stream = inotify
+        .into_event_stream(buffer)
+        .map_err(|e| anyhow!("Cannot create inotify event stream: {e}"))?;
+
+    tokio::spawn(async move {
+        tokio::pin!(stream);
+
+        while let Some(event) = stream.next().await {
+            if let Err(e) = event {
+                warn!("Cannot read inotify event: {e}");
+                continue;
+            }
+
+            if let Some(path) = &static_tokens_file
+                && let Ok(digest) = files_digest(&[path.as_path()]).await
+                && static_tokens_digest != Some(digest)
+            {
+                info!("Reloading static tokens");
+                match authenticator.reload_static_tokens().await {
+                    Ok(()) => static_tokens_digest = Some(digest),
+                    Err(e) => error!("Failed to reload static tokens: {e}"),
+                }
+            }
+            if let Some(path) = &jwks_file
+                && let Some(service_account_tokens) = &authenticator.service_account_tokens
+                && let Ok(digest) = files_digest(&[path.as_path()]).await
+                && jwks_digest != Some(digest)
+            {
+                info!("Reloading JWKS");
+                match service_account_tokens.reload_keys().await {
+                    Ok(()) => jwks_digest = Some(digest),
+                    Err(e) => error!("Failed to reload JWKS: {e}"),
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn unauthorized(message: String) -> Response {
+    let mut response = ApiError {
+        status: StatusCode::UNAUTHORIZED,
+        message,
+    }
+    .into_response();
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    response
+}
+
+/// The token of a `Bearer` authorization header. The scheme is case-insensitive,
+/// see RFC 7235
+fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("Bearer").then_some(token)
+}
+
+/// Middleware rejecting the requests that do not have a valid bearer token, or whose
+/// token does not allow the evaluation of the requested policy. Clients authenticated
+/// via mTLS are not required to provide a token.
+pub(crate) async fn authenticate_bearer_token(
+    State(authenticator): State<Arc<BearerTokenAuthenticator>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some((endpoint, policy_id)) = requested_endpoint(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    if !authenticator.endpoints.contains(&endpoint) {
+        return next.run(request).await;
+    }
+    if request
+        .extensions()
+        .get::<Option<ClientIdentity>>()
+        .is_some_and(|identity| identity.is_some())
+    {
+        return next.run(request).await;
+    }
+
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_token);
+    let Some(token) = token else {
+        metrics::add_bearer_token_authentication(endpoint.as_str(), "unauthenticated");
+        return unauthorized("missing bearer token".to_owned());
+    };
+
+    let grant = match authenticator.authenticate(token.trim()) {
+        Ok(grant) => grant,
+        Err(e) => {
+            warn!(
+                endpoint = endpoint.as_str(),
+                policy_id,
+                error = %e,
+                "bearer token rejected"
+            );
+            metrics::add_bearer_token_authentication(endpoint.as_str(), "unauthenticated");
+            return unauthorized("invalid bearer token".to_owned());
+        }
+    };
+
+    if !grant.allows(policy_id) {
+        warn!(
+            client = grant.name.as_str(),
+            endpoint = endpoint.as_str(),
+            policy_id,
+            "client is not authorized"
+        );
+        metrics::add_bearer_token_authentication(endpoint.as_str(), "forbidden");
+        return ApiError {
+            status: StatusCode::FORBIDDEN,
+            message: format!(
+                "client '{}' is not authorized to call {}",
+                grant.name,
+                request.uri().path()
+            ),
+        }
+        .into_response();
+    }
+
+    debug!(
+        client = grant.name.as_str(),
+        endpoint = endpoint.as_str(),
+        policy_id,
+        "client authenticated via bearer token"
+    );
+    metrics::add_bearer_token_authentication(endpoint.as_str(), "authenticated");
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Extension, Router, body::Body, middleware::from_fn_with_state, routing::post};
+    use ring::{
+        rand::SystemRandom,
+        signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair},
+    };
+    use rstest::rstest;
+    use serde_json::json;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    const ISSUER: &str = "https://kubernetes.default.svc.cluster.local";
+
+    struct Signer {
+        key_pair: EcdsaKeyPair,
+        rng: SystemRandom,
+    }
+
+    impl Signer {
+        fn new() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                    .unwrap();
+            Self { key_pair, rng }
+        }
+
+        fn jwks(&self) -> serde_json::Value {
+            let public_key = self.key_pair.public_key().as_ref();
+            json!({
+                "keys": [{
+                    "kty": "EC",
+                    "kid": "test",
+                    "crv": "P-256",
+                    "x": URL_SAFE_NO_PAD.encode(&public_key[1..33]),
+                    "y": URL_SAFE_NO_PAD.encode(&public_key[33..65]),
+                }]
+            })
+        }
+
+        fn token(&self, claims: serde_json::Value) -> String {
+            let header = URL_SAFE_NO_PAD.encode(json!({"alg": "ES256", "kid": "test"}).to_string());
+            let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+            let message = format!("{header}.{payload}");
+            let signature = self.key_pair.sign(&self.rng, message.as_bytes()).unwrap();
+            format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref()))
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn claims(subject: &str, audience: &str, exp: u64) -> serde_json::Value {
+        json!({
+            "iss": ISSUER,
+            "sub": subject,
+            "aud": [audience],
+            "exp": exp,
+            "nbf": now() - 10,
+        })
+    }
+
+    async fn authenticator(dir: &TempDir, signer: &Signer) -> BearerTokenAuthenticator {
+        let static_tokens_file = dir.path().join("tokens.yaml");
+        tokio::fs::write(
+            &static_tokens_file,
+            r#"
+- name: ci-pipeline
+  token: s3cr3t
+  policies: ["ci-*"]
+- name: admin
+  token: 4dm1n
+"#,
+        )
+        .await
+        .unwrap();
+        let jwks_file = dir.path().join("jwks.json");
+        tokio::fs::write(&jwks_file, signer.jwks().to_string())
+            .await
+            .unwrap();
+
+        let config: BearerTokenAuthConfig = serde_yaml::from_str(&format!(
+            r#"
+staticTokensFile: {}
+serviceAccountTokens:
+  jwksFile: {}
+  issuer: {ISSUER}
+  audiences: ["policy-server"]
+  serviceAccounts:
+  - subject: "system:serviceaccount:ci:*"
+    policies: ["ci-*"]
+"#,
+            static_tokens_file.display(),
+            jwks_file.display()
+        ))
+        .unwrap();
+        BearerTokenAuthenticator::new(config).await.unwrap()
+    }
+
+    #[rstest]
+    #[case::static_token("s3cr3t", Some("ci-pipeline"))]
+    #[case::static_token_without_policies("4dm1n", Some("admin"))]
+    #[case::unknown_static_token("wrong", None)]
+    #[tokio::test]
+    async fn authenticate_static_tokens(#[case] token: &str, #[case] expected: Option<&str>) {
+        let dir = TempDir::new().unwrap();
+        let authenticator = authenticator(&dir, &Signer::new()).await;
+
+        let grant = authenticator.authenticate(token).ok();
+        assert_eq!(expected, grant.as_ref().map(|grant| grant.name.as_str()));
+    }
+
+    #[rstest]
+    #[case::valid("system:serviceaccount:ci:runner", "policy-server", now() + 600, true)]
+    #[case::expired("system:serviceaccount:ci:runner", "policy-server", now() - 600, false)]
+    #[case::wrong_audience("system:serviceaccount:ci:runner", "kube-apiserver", now() + 600, false)]
+    #[case::service_account_not_allowed("system:serviceaccount:default:default", "policy-server", now() + 600, false)]
+    #[tokio::test]
+    async fn authenticate_service_account_tokens(
+        #[case] subject: &str,
+        #[case] audience: &str,
+        #[case] exp: u64,
+        #[case] expected: bool,
+    ) {
+        let dir = TempDir::new().unwrap();
+        let signer = Signer::new();
+        let authenticator = authenticator(&dir, &signer).await;
+
+        let token = signer.token(claims(subject, audience, exp));
+        let grant = authenticator.authenticate(&token);
+        assert_eq!(expected, grant.is_ok(), "{grant:?}");
+        if let Ok(grant) = grant {
+            assert!(grant.allows(Some("ci-image-signatures")));
+            assert!(!grant.allows(Some("privileged-pods")));
+        }
+    }
+
+    #[tokio::test]
+    async fn reject_tokens_signed_by_unknown_keys() {
+        let dir = TempDir::new().unwrap();
+        let authenticator = authenticator(&dir, &Signer::new()).await;
+
+        let token = Signer::new().token(claims(
+            "system:serviceaccount:ci:runner",
+            "policy-server",
+            now() + 600,
+        ));
+        assert!(authenticator.authenticate(&token).is_err());
+    }
+
+    #[rstest]
+    #[case::canonical("Bearer s3cr3t", Some("s3cr3t"))]
+    #[case::lowercase("bearer s3cr3t", Some("s3cr3t"))]
+    #[case::uppercase("BEARER s3cr3t", Some("s3cr3t"))]
+    #[case::other_scheme("Basic s3cr3t", None)]
+    #[case::missing_token("Bearer", None)]
+    fn parse_bearer_token(#[case] authorization: &str, #[case] expected: Option<&str>) {
+        assert_eq!(expected, bearer_token(authorization));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn reload_static_tokens_on_symlink_swap() {
+        use std::os::unix::fs::symlink;
+
+        // Kubernetes updates the Secret volumes by swapping the `..data` symlink, the
+        // files themselves are never written
+        let dir = TempDir::new().unwrap();
+        let write_tokens = |version: &str, token: &str| {
+            let data_dir = dir.path().join(format!("..{version}"));
+            std::fs::create_dir(&data_dir).unwrap();
+            std::fs::write(
+                data_dir.join("tokens.yaml"),
+                format!("- name: ci-pipeline\n  token: {token}\n"),
+            )
+            .unwrap();
+            symlink(format!("..{version}"), dir.path().join("..data_tmp")).unwrap();
+            std::fs::rename(dir.path().join("..data_tmp"), dir.path().join("..data")).unwrap();
+        };
+        write_tokens("v1", "0ld");
+        symlink("..data/tokens.yaml", dir.path().join("tokens.yaml")).unwrap();
+
+        let authenticator = Arc::new(
+            BearerTokenAuthenticator::new(BearerTokenAuthConfig {
+                endpoints: vec![EndpointKind::ValidateRaw],
+                static_tokens_file: Some(dir.path().join("tokens.yaml")),
+                service_account_tokens: None,
+            })
+            .await
+            .unwrap(),
+        );
+        watch_bearer_token_files(authenticator.clone())
+            .await
+            .unwrap();
+        assert!(authenticator.authenticate("0ld").is_ok());
+
+        write_tokens("v2", "n3w");
+        for _ in 0..50 {
+            if authenticator.authenticate("n3w").is_ok() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        assert!(authenticator.authenticate("n3w").is_ok());
+        assert!(authenticator.authenticate("0ld").is_err());
+    }
+
+    #[rstest]
+    #[case::missing_token(
+        None,
+        false,
+        "/validate_raw/ci-image-signatures",
+        StatusCode::UNAUTHORIZED
+    )]
+    #[case::invalid_token(
+        Some("wrong"),
+        false,
+        "/validate_raw/ci-image-signatures",
+        StatusCode::UNAUTHORIZED
+    )]
+    #[case::allowed_policy(
+        Some("s3cr3t"),
+        false,
+        "/validate_raw/ci-image-signatures",
+        StatusCode::OK
+    )]
+    #[case::forbidden_policy(
+        Some("s3cr3t"),
+        false,
+        "/validate_raw/privileged-pods",
+        StatusCode::FORBIDDEN
+    )]
+    #[case::mtls_client(None, true, "/validate_raw/privileged-pods", StatusCode::OK)]
+    #[tokio::test]
+    async fn require_bearer_token(
+        #[case] token: Option<&str>,
+        #[case] mtls_client: bool,
+        #[case] path: &str,
+        #[case] expected_status: StatusCode,
+    ) {
+        let dir = TempDir::new().unwrap();
+        let authenticator = authenticator(&dir, &Signer::new()).await;
+
+        let mut router = Router::new()
+            .route("/validate_raw/{policy_id}", post(|| async { "ok" }))
+            .route_layer(from_fn_with_state(
+                Arc::new(authenticator),
+                authenticate_bearer_token,
+            ));
+        if mtls_client {
+            router = router.layer(Extension(Some(ClientIdentity {
+                common_names: vec!["audit-scanner".to_owned()],
+                sans: vec![],
+            })));
+        }
+
+        let mut request = Request::builder().method("POST").uri(path);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let response = router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(expected_status, response.status());
+    }
+}
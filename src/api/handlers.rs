@@ -1,5 +1,5 @@
 use axum::{
-    Json,
+    Extension, Json,
     extract::{self, FromRequest, Query},
     http::{StatusCode, header},
     response::IntoResponse,
@@ -10,20 +10,34 @@ use policy_evaluator::{
 };
 
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::task;
-use tracing::{Span, debug, error};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+use tokio::{sync::SemaphorePermit, task, time::Instant};
+use tracing::{Instrument, Span, debug, error, info_span};
 
 use crate::profiling::ReportGenerationError;
 use crate::{
     api::{
         admission_review::{AdmissionReviewRequest, AdmissionReviewResponse},
         api_error::ApiError,
+        authorization::DebugMode,
         raw_review::{RawReviewRequest, RawReviewResponse},
         service::{RequestOrigin, evaluate},
         state::ApiServerState,
     },
+    integrity_check::{IntegrityStatus, ModuleIntegrityStatus},
+    metrics,
+    policy_downloader::FetchedModule,
     profiling,
+    tracing::{
+        log_level::{LogLevelChange, LogLevelStatus, log_level_controller},
+        policy_logs::{PolicyLogEntry, capture_policy_logs},
+    },
 };
 
 // create an extractor that internally uses `axum::Json` but has a custom rejection
@@ -69,24 +83,26 @@ impl<T: Serialize> IntoResponse for JsonExtractor<T> {
 pub(crate) async fn audit_handler(
     extract::State(state): extract::State<Arc<ApiServerState>>,
     extract::Path(policy_id): extract::Path<String>,
+    debug_mode: Option<Extension<DebugMode>>,
     extract::Json(admission_review): extract::Json<AdmissionReviewRequest>,
 ) -> Result<Json<AdmissionReviewResponse>, (StatusCode, ApiError)> {
     debug!(admission_review = %serde_json::to_string(&admission_review).unwrap().as_str());
 
     populate_span_with_admission_request_data(&admission_review.request);
 
-    let response = acquire_semaphore_and_evaluate(
+    let (response, policy_logs) = acquire_semaphore_and_evaluate(
         state,
         policy_id,
         ValidateRequest::AdmissionRequest(Box::new(admission_review.request)),
         RequestOrigin::Audit,
+        debug_mode.is_some(),
     )
     .await
     .map_err(handle_evaluation_error)?;
 
     populate_span_with_policy_evaluation_results(&response);
 
-    Ok(Json(AdmissionReviewResponse::new(response)))
+    Ok(Json(AdmissionReviewResponse::new(response, policy_logs)))
 }
 
 // note about tracing: we are manually adding the `policy_id` field
@@ -120,24 +136,26 @@ pub(crate) async fn audit_handler(
 pub(crate) async fn validate_handler(
     extract::State(state): extract::State<Arc<ApiServerState>>,
     extract::Path(policy_id): extract::Path<String>,
+    debug_mode: Option<Extension<DebugMode>>,
     JsonExtractor(admission_review): JsonExtractor<AdmissionReviewRequest>,
 ) -> Result<Json<AdmissionReviewResponse>, (StatusCode, ApiError)> {
     debug!(admission_review = %serde_json::to_string(&admission_review).unwrap().as_str());
 
     populate_span_with_admission_request_data(&admission_review.request);
 
-    let response = acquire_semaphore_and_evaluate(
+    let (response, policy_logs) = acquire_semaphore_and_evaluate(
         state,
         policy_id,
         ValidateRequest::AdmissionRequest(Box::new(admission_review.request)),
         RequestOrigin::Validate,
+        debug_mode.is_some(),
     )
     .await
     .map_err(handle_evaluation_error)?;
 
     populate_span_with_policy_evaluation_results(&response);
 
-    Ok(Json(AdmissionReviewResponse::new(response)))
+    Ok(Json(AdmissionReviewResponse::new(response, policy_logs)))
 }
 
 #[tracing::instrument(
@@ -155,26 +173,51 @@ pub(crate) async fn validate_handler(
 pub(crate) async fn validate_raw_handler(
     extract::State(state): extract::State<Arc<ApiServerState>>,
     extract::Path(policy_id): extract::Path<String>,
+    debug_mode: Option<Extension<DebugMode>>,
     extract::Json(raw_review): extract::Json<RawReviewRequest>,
 ) -> Result<Json<RawReviewResponse>, (StatusCode, ApiError)> {
     debug!(raw_review = %serde_json::to_string(&raw_review).unwrap().as_str());
 
-    let response = acquire_semaphore_and_evaluate(
+    let (response, policy_logs) = acquire_semaphore_and_evaluate(
         state,
         policy_id,
         ValidateRequest::Raw(raw_review.request),
         RequestOrigin::Validate,
+        debug_mode.is_some(),
     )
     .await
     .map_err(handle_evaluation_error)?;
 
     populate_span_with_policy_evaluation_results(&response);
 
-    Ok(Json(RawReviewResponse::new(response)))
+    Ok(Json(RawReviewResponse::new(response, policy_logs)))
+}
+
+pub(crate) async fn readiness_handler(
+    extract::State(ready): extract::State<Arc<AtomicBool>>,
+) -> StatusCode {
+    if ready.load(Ordering::Relaxed) {
+        StatusCode::OK
+    } else {
+        // The server is shutting down
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+pub(crate) async fn modules_handler(
+    extract::State(modules): extract::State<Arc<Vec<FetchedModule>>>,
+) -> Json<Vec<FetchedModule>> {
+    Json(modules.as_ref().clone())
 }
 
-pub(crate) async fn readiness_handler() -> StatusCode {
-    StatusCode::OK
+pub(crate) async fn integrity_handler(
+    extract::State(status): extract::State<IntegrityStatus>,
+) -> Json<BTreeMap<String, ModuleIntegrityStatus>> {
+    let status = status
+        .read()
+        .expect("cannot acquire integrity status lock")
+        .clone();
+    Json(status)
 }
 
 #[derive(Deserialize)]
@@ -253,36 +296,131 @@ pub(crate) async fn pprof_get_heap()
     Ok((headers, pprof))
 }
 
+/// Return the log level directives in use
+pub(crate) async fn log_level_get_handler() -> Result<Json<LogLevelStatus>, (StatusCode, ApiError)>
+{
+    let controller = log_level_controller().ok_or_else(log_level_not_available)?;
+    Ok(Json(controller.status()))
+}
+
+/// Change the log level, the change is reverted once its duration expires
+pub(crate) async fn log_level_put_handler(
+    JsonExtractor(change): JsonExtractor<LogLevelChange>,
+) -> Result<Json<LogLevelStatus>, (StatusCode, ApiError)> {
+    let controller = log_level_controller().ok_or_else(log_level_not_available)?;
+    controller.change(&change).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            ApiError {
+                status: StatusCode::BAD_REQUEST,
+                message: e.to_string(),
+            },
+        )
+    })?;
+    Ok(Json(controller.status()))
+}
+
+/// Revert the log level to the one set at startup
+pub(crate) async fn log_level_delete_handler()
+-> Result<Json<LogLevelStatus>, (StatusCode, ApiError)> {
+    let controller = log_level_controller().ok_or_else(log_level_not_available)?;
+    controller.reset().map_err(|e| {
+        error!("cannot reset the log level: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                message: "Something went wrong".to_owned(),
+            },
+        )
+    })?;
+    Ok(Json(controller.status()))
+}
+
 async fn acquire_semaphore_and_evaluate(
     state: Arc<ApiServerState>,
     policy_id: String,
     validate_request: ValidateRequest,
     request_origin: RequestOrigin,
-) -> Result<AdmissionResponse, EvaluationError> {
-    let _permit = state
+    debug_mode: bool,
+) -> Result<(AdmissionResponse, Option<Vec<PolicyLogEntry>>), EvaluationError> {
+    let origin = request_origin.to_string();
+    let queue_start_time = Instant::now();
+    let permit = state
         .semaphore
         .acquire()
+        .instrument(info_span!(
+            "acquire_evaluation_permit",
+            request_origin = origin.as_str(),
+        ))
         .await
         .expect("semaphore acquire failed");
+    metrics::record_policy_evaluation_queue_wait(queue_start_time.elapsed(), &origin);
+    let _in_flight = InFlightEvaluation::new(&state, permit, origin);
 
     let state = state.clone();
     let span = Span::current();
-    let response = task::spawn_blocking(move || {
+    let (response, policy_logs) = task::spawn_blocking(move || {
         let _enter = span.enter();
 
-        evaluate(
-            state.evaluation_environment.clone(),
-            &policy_id,
-            &validate_request,
-            request_origin,
-        )
+        let run_evaluation = || {
+            evaluate(
+                state.evaluation_environment.clone(),
+                &policy_id,
+                &validate_request,
+                request_origin,
+            )
+        };
+        // Set only for the clients allowed to use the debug mode, the logs can include
+        // the contents of the evaluated requests
+        if debug_mode {
+            let (response, policy_logs) = capture_policy_logs(run_evaluation);
+            response.map(|response| (response, Some(policy_logs)))
+        } else {
+            run_evaluation().map(|response| (response, None))
+        }
     })
     .await
     .expect("task::spawn_blocking failed")?;
 
     debug!(response =? &response, "policy evaluated");
 
-    Ok(response)
+    Ok((response, policy_logs))
+}
+
+/// Tracks an evaluation holding a semaphore permit. The request future can be dropped
+/// at any time, for example when the client disconnects, hence the permit is released
+/// and the metrics are updated on drop
+struct InFlightEvaluation<'a> {
+    state: &'a ApiServerState,
+    permit: Option<SemaphorePermit<'a>>,
+    request_origin: String,
+}
+
+impl<'a> InFlightEvaluation<'a> {
+    fn new(state: &'a ApiServerState, permit: SemaphorePermit<'a>, request_origin: String) -> Self {
+        metrics::add_policy_evaluations_in_flight(1, &request_origin);
+        metrics::record_policy_evaluation_permits_available(
+            state.semaphore.available_permits(),
+            &request_origin,
+        );
+        Self {
+            state,
+            permit: Some(permit),
+            request_origin,
+        }
+    }
+}
+
+impl Drop for InFlightEvaluation<'_> {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+        metrics::add_policy_evaluations_in_flight(-1, &self.request_origin);
+        metrics::record_policy_evaluation_permits_available(
+            self.state.semaphore.available_permits(),
+            &self.request_origin,
+        );
+    }
 }
 
 fn populate_span_with_admission_request_data(adm_req: &AdmissionRequest) {
@@ -352,3 +490,13 @@ fn handle_pprof_error(error: ReportGenerationError) -> (StatusCode, ApiError) {
         },
     )
 }
+
+fn log_level_not_available() -> (StatusCode, ApiError) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        ApiError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "the log level cannot be changed at runtime".to_owned(),
+        },
+    )
+}
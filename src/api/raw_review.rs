@@ -1,6 +1,8 @@
 use policy_evaluator::admission_response::AdmissionResponse;
 use serde::{Deserialize, Serialize};
 
+use crate::tracing::policy_logs::PolicyLogEntry;
+
 /// A review request that contains a raw json value.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RawReviewRequest {
@@ -9,12 +11,22 @@ pub struct RawReviewRequest {
 
 /// A review response from a raw policy evaluation.
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct RawReviewResponse {
     pub response: AdmissionResponse,
+    /// The log messages emitted while evaluating the policy in debug mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_logs: Option<Vec<PolicyLogEntry>>,
 }
 
 impl RawReviewResponse {
-    pub(crate) fn new(response: AdmissionResponse) -> Self {
-        RawReviewResponse { response }
+    pub(crate) fn new(
+        response: AdmissionResponse,
+        policy_logs: Option<Vec<PolicyLogEntry>>,
+    ) -> Self {
+        RawReviewResponse {
+            response,
+            policy_logs,
+        }
     }
 }
@@ -0,0 +1,22 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+use tokio::time::Instant;
+
+use crate::{api::authorization::requested_endpoint, metrics};
+
+/// Middleware measuring the time spent handling the API requests, including the time
+/// spent by the other middlewares and waiting for a free worker
+pub(crate) async fn record_request_latency(request: Request, next: Next) -> Response {
+    let Some((endpoint, _)) = requested_endpoint(request.uri().path()) else {
+        return next.run(request).await;
+    };
+
+    let start_time = Instant::now();
+    let response = next.run(request).await;
+    metrics::record_http_request_latency(
+        start_time.elapsed(),
+        endpoint.as_str(),
+        response.status().as_u16(),
+    );
+
+    response
+}
@@ -8,6 +8,7 @@ use policy_evaluator::{
     policy_evaluator::ValidateRequest,
 };
 use tokio::time::Instant;
+use tracing::info_span;
 
 use crate::{evaluation::EvaluationEnvironment, metrics};
 
@@ -112,6 +113,12 @@ pub(crate) fn evaluate(
 
     let validation_response = match request_origin {
         RequestOrigin::Validate => {
+            let _span = info_span!(
+                "process_response",
+                policy_id = policy_id.to_string().as_str(),
+                allowed_to_mutate,
+            )
+            .entered();
             admission_response_handler.process_response(vanilla_validation_response)
         }
         RequestOrigin::Audit => vanilla_validation_response,
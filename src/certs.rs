@@ -1,82 +1,150 @@
-use ::tracing::{info, warn};
+use ::tracing::{debug, error, info, warn};
 use anyhow::{Result, anyhow};
-use rustls::{RootCertStore, ServerConfig, server::WebPkiClientVerifier};
+use axum_server::{
+    accept::Accept,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+};
+use rustls::{
+    CertificateError, ConfigBuilder, DigitallySignedStruct, DistinguishedName, RootCertStore,
+    ServerConfig, SignatureScheme, SupportedProtocolVersion, WantsVerifier,
+    client::danger::HandshakeSignatureValid,
+    crypto::CryptoProvider,
+    server::{
+        ClientHello, ResolvesServerCert, WebPkiClientVerifier,
+        danger::{ClientCertVerified, ClientCertVerifier},
+    },
+    sign::CertifiedKey,
+};
 use rustls_pemfile::Item;
-use rustls_pki_types::{CertificateDer, PrivateKeyDer};
-use std::{io::BufReader, path::Path, sync::Arc};
+use rustls_pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer, UnixTime};
+use sha2::{Digest, Sha256};
+use std::{
+    future::Future,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::Mutex,
+    time::{self, MissedTickBehavior},
+};
+use tokio_rustls::server::TlsStream;
+use tower_http::add_extension::AddExtension;
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
 
 // This is required by certificate hot reload when using inotify, which is available only on linux
 #[cfg(target_os = "linux")]
 use tokio_stream::StreamExt;
 
-use crate::config::TlsConfig;
+use crate::{
+    api::authorization::ClientIdentity,
+    config::{CertificateFiles, TlsConfig, TlsVersion},
+    metrics,
+};
 
-/// There's no watching of the certificate files on non-linux platforms
-/// since we rely on inotify to watch for changes
-#[cfg(not(target_os = "linux"))]
-async fn create_tls_config_and_watch_certificate_changes(
+/// Certificates expiring within this amount of time cause a warning to be logged
+const CERTIFICATE_EXPIRY_WARNING: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// How often the expiry of the served certificates is checked
+const CERTIFICATE_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Return the RustlsConfig and reload it when the TLS files change, causing the https
+/// server to use the new certificates. Changes are detected:
+/// * via inotify, on linux only. Both the files and their parent directories are watched,
+///   the latter catches the symlink swaps done by Kubernetes when updating Secret volumes.
+/// * by polling the files every `reload_poll_interval`, when set. This is the only way
+///   to reload the files on the other platforms.
+///
+/// Each certificate, the default one and the SNI ones, is reloaded independently.
+pub(crate) async fn create_tls_config_and_watch_certificate_changes(
     tls_config: TlsConfig,
 ) -> Result<RustlsConfig> {
-    let cfg = RustlsConfig::from_pem_file(tls_config.cert_file, tls_config.key_file).await?;
-    Ok(cfg)
+    let reload_poll_interval = tls_config.reload_poll_interval;
+    let tls_files = TlsFiles::load(tls_config).await?;
+    let rustls_config = tls_files.rustls_config.clone();
+    let tls_files = Arc::new(Mutex::new(tls_files));
+
+    #[cfg(target_os = "linux")]
+    watch_tls_files(tls_files.clone()).await?;
+
+    if let Some(reload_poll_interval) = reload_poll_interval {
+        let tls_files = tls_files.clone();
+        tokio::spawn(async move {
+            let mut interval = time::interval(reload_poll_interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // the first tick completes immediately, the files have just been loaded
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+                tls_files.lock().await.reload_changed_files().await;
+            }
+        });
+    }
+
+    tokio::spawn(async move {
+        let mut interval = time::interval(CERTIFICATE_EXPIRY_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            tls_files.lock().await.check_expiry();
+        }
+    });
+
+    Ok(rustls_config)
 }
 
-/// Return the RustlsConfig and watch for changes in the certificate files
-/// using inotify.
-/// When both the certificate and its key are changed, the RustlsConfig is reloaded,
-/// causing the https server to use the new certificate.
-///
-/// Relying on inotify is only available on linux
+/// Watch the given files and their parent directories using inotify. Watching the
+/// directories catches the symlink swaps done by Kubernetes when updating Secret and
+/// ConfigMap volumes, the files being watched are not changed in that case.
 #[cfg(target_os = "linux")]
-pub(crate) async fn create_tls_config_and_watch_certificate_changes(
-    tls_config: TlsConfig,
-) -> Result<axum_server::tls_rustls::RustlsConfig> {
-    use ::tracing::error;
-    use axum_server::tls_rustls::RustlsConfig;
-    use inotify::WatchDescriptor;
-
-    // Build initial TLS configuration
-    let (mut cert, mut key) =
-        load_server_cert_and_key(&tls_config.cert_file, &tls_config.key_file).await?;
-    let mut client_verifier = if tls_config.client_ca_file.is_empty() {
-        None
-    } else {
-        Some(load_client_ca_certs(tls_config.client_ca_file.clone()).await?)
-    };
-    let initial_config =
-        build_tls_server_config(cert.clone(), key.clone_key(), client_verifier.clone())?;
+pub(crate) fn watch_files_and_parent_dirs(
+    inotify: &inotify::Inotify,
+    paths: &[&Path],
+) -> Result<()> {
+    use inotify::WatchMask;
+    use std::collections::BTreeSet;
 
-    let rust_config = RustlsConfig::from_config(Arc::new(initial_config));
-    let reloadable_rust_config = rust_config.clone();
+    let mut parent_dirs = BTreeSet::new();
+    for path in paths {
+        inotify
+            .watches()
+            .add(path, WatchMask::CLOSE_WRITE)
+            .map_err(|e| anyhow!("Cannot watch file {}: {e}", path.display()))?;
+        if let Some(parent_dir) = path.parent() {
+            let parent_dir = if parent_dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent_dir
+            };
+            parent_dirs.insert(parent_dir.to_path_buf());
+        }
+    }
+    for parent_dir in parent_dirs.iter() {
+        inotify
+            .watches()
+            .add(
+                parent_dir,
+                WatchMask::CLOSE_WRITE | WatchMask::CREATE | WatchMask::MOVED_TO,
+            )
+            .map_err(|e| anyhow!("Cannot watch directory {}: {e}", parent_dir.display()))?;
+    }
 
-    // Init inotify to watch for changes in the certificate files
+    Ok(())
+}
+
+/// Watch the TLS files and their parent directories using inotify
+#[cfg(target_os = "linux")]
+async fn watch_tls_files(tls_files: Arc<Mutex<TlsFiles>>) -> Result<()> {
     let inotify =
         inotify::Inotify::init().map_err(|e| anyhow!("Cannot initialize inotify: {e}"))?;
-    let cert_watch = inotify
-        .watches()
-        .add(
-            tls_config.cert_file.clone(),
-            inotify::WatchMask::CLOSE_WRITE,
-        )
-        .map_err(|e| anyhow!("Cannot watch certificate file: {e}"))?;
-    let key_watch = inotify
-        .watches()
-        .add(tls_config.key_file.clone(), inotify::WatchMask::CLOSE_WRITE)
-        .map_err(|e| anyhow!("Cannot watch key file: {e}"))?;
 
-    let client_ca_watches: Result<Vec<WatchDescriptor>, anyhow::Error> = tls_config
-        .client_ca_file
-        .clone()
-        .into_iter()
-        .map(|path| {
-            inotify
-                .watches()
-                .add(path, inotify::WatchMask::CLOSE_WRITE)
-                .map_err(|e| anyhow!("Cannot watch client CA file: {e}"))
-        })
-        .collect();
-
-    let client_ca_watches = client_ca_watches?;
+    let paths = tls_files.lock().await.paths();
+    watch_files_and_parent_dirs(
+        &inotify,
+        &paths.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+    )?;
 
     let buffer = [0; 1024];
     let stream = inotify
@@ -85,155 +153,603 @@ pub(crate) async fn create_tls_config_and_watch_certificate_changes(
 
     tokio::spawn(async move {
         tokio::pin!(stream);
-        let mut cert_changed = false;
-        let mut key_changed = false;
-        let mut client_ca_changed = false;
 
         while let Some(event) = stream.next().await {
-            let event = match event {
-                Ok(event) => event,
+            if let Err(e) = event {
+                warn!("Cannot read inotify event: {e}");
+                continue;
+            }
+            tls_files.lock().await.reload_changed_files().await;
+        }
+    });
+
+    Ok(())
+}
+
+/// A certificate served by the HTTPS server
+struct ServedCertificate {
+    files: CertificateFiles,
+    certified_key: Arc<CertifiedKey>,
+    /// Digest of the contents of the certificate and key files
+    digest: [u8; 32],
+    /// Unix timestamp of the expiry of the leaf certificate
+    not_after: i64,
+}
+
+impl ServedCertificate {
+    async fn load(files: CertificateFiles) -> Result<Self> {
+        let digest =
+            files_digest(&[&files.cert_file, &files.key_file].map(PathBuf::as_path)).await?;
+        let certified_key = load_server_cert_and_key(&files.cert_file, &files.key_file).await?;
+        let not_after = certified_key
+            .cert
+            .first()
+            .map(certificate_not_after)
+            .transpose()?
+            .unwrap_or_default();
+        metrics::record_tls_certificate_expiration(
+            &files.cert_file.to_string_lossy(),
+            not_after.max(0) as u64,
+        );
+
+        Ok(Self {
+            files,
+            certified_key,
+            digest,
+            not_after,
+        })
+    }
+}
+
+/// The files served by the HTTPS server.
+///
+/// Changes are detected by comparing the digest of the files with the one of the files
+/// that have been loaded, hence spurious or partial change notifications are harmless:
+/// a certificate whose key has not been updated yet fails to load, and it is loaded
+/// again on the next notification.
+struct TlsFiles {
+    tls_config: TlsConfig,
+    protocol_settings: TlsProtocolSettings,
+    /// The first certificate is the default one
+    certificates: Vec<ServedCertificate>,
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    /// Digest of the contents of the client CA and CRL files
+    client_verifier_digest: Option<[u8; 32]>,
+    rustls_config: RustlsConfig,
+}
+
+impl TlsFiles {
+    async fn load(tls_config: TlsConfig) -> Result<Self> {
+        // Validate the TLS protocol settings before anything else, they are reused on each reload
+        let protocol_settings = TlsProtocolSettings::new(&tls_config)?;
+
+        let mut certificates = Vec::new();
+        let all_certificate_files = std::iter::once(CertificateFiles {
+            cert_file: tls_config.cert_file.clone(),
+            key_file: tls_config.key_file.clone(),
+        })
+        .chain(tls_config.sni_certs.iter().cloned());
+        for files in all_certificate_files {
+            certificates.push(ServedCertificate::load(files).await?);
+        }
+
+        let (client_verifier, client_verifier_digest) = if tls_config.client_ca_file.is_empty() {
+            (None, None)
+        } else {
+            let digest = files_digest(&client_verifier_files(&tls_config)).await?;
+            let client_verifier =
+                load_client_verifier(&tls_config.client_ca_file, &tls_config.client_crl_file)
+                    .await?;
+            (Some(client_verifier), Some(digest))
+        };
+
+        let server_config = build_tls_server_config(
+            &protocol_settings,
+            certificates[0].certified_key.clone(),
+            certificates[1..]
+                .iter()
+                .map(|certificate| certificate.certified_key.clone())
+                .collect(),
+            client_verifier.clone(),
+        )?;
+
+        let tls_files = Self {
+            tls_config,
+            protocol_settings,
+            certificates,
+            client_verifier,
+            client_verifier_digest,
+            rustls_config: RustlsConfig::from_config(Arc::new(server_config)),
+        };
+        tls_files.check_expiry();
+
+        Ok(tls_files)
+    }
+
+    /// All the files being served
+    fn paths(&self) -> Vec<PathBuf> {
+        self.certificates
+            .iter()
+            .flat_map(|certificate| {
+                [
+                    certificate.files.cert_file.clone(),
+                    certificate.files.key_file.clone(),
+                ]
+            })
+            .chain(
+                client_verifier_files(&self.tls_config)
+                    .into_iter()
+                    .map(Path::to_path_buf),
+            )
+            .collect()
+    }
+
+    /// Reload the files that have changed, keeping the other ones unchanged
+    async fn reload_changed_files(&mut self) {
+        let mut changed = false;
+
+        for certificate in self.certificates.iter_mut() {
+            let cert_file = certificate.files.cert_file.to_string_lossy().to_string();
+            let digest = match files_digest(
+                &[&certificate.files.cert_file, &certificate.files.key_file].map(PathBuf::as_path),
+            )
+            .await
+            {
+                Ok(digest) => digest,
                 Err(e) => {
-                    warn!("Cannot read inotify event: {e}");
+                    // The files can be missing while they are being replaced
+                    debug!(cert_file, "Cannot read TLS certificate files: {e}");
                     continue;
                 }
             };
-
-            if event.wd == cert_watch {
-                info!("TLS certificate file has been modified");
-                cert_changed = true;
-            }
-            if event.wd == key_watch {
-                info!("TLS key file has been modified");
-                key_changed = true;
+            if digest == certificate.digest {
+                continue;
             }
 
-            for client_ca_watch in client_ca_watches.iter() {
-                if event.wd == *client_ca_watch {
-                    info!("TLS client CA file has been modified");
-                    client_ca_changed = true;
+            info!(cert_file, "Reloading Server TLS certificates");
+            match ServedCertificate::load(certificate.files.clone()).await {
+                Ok(reloaded) => {
+                    *certificate = reloaded;
+                    changed = true;
+                }
+                Err(e) => {
+                    // The certificate might have been updated before its key
+                    warn!(cert_file, "Failed to reload TLS certificates: {e}");
                 }
             }
+        }
 
-            // Reload the client CA certificates if they have changed, keeping the current server certificates unchanged
-            if client_ca_changed {
-                info!("Reloading client CA certificates");
-
-                client_ca_changed = false;
-
-                match load_client_ca_certs(tls_config.client_ca_file.clone()).await {
-                    Ok(cv) => {
-                        client_verifier = Some(cv);
-                    }
-                    Err(e) => {
-                        error!("Failed to reload TLS certificates: {e}");
-                        continue;
+        if let Some(current_digest) = self.client_verifier_digest {
+            match files_digest(&client_verifier_files(&self.tls_config)).await {
+                Ok(digest) if digest != current_digest => {
+                    info!("Reloading client CA certificates and CRLs");
+                    match load_client_verifier(
+                        &self.tls_config.client_ca_file,
+                        &self.tls_config.client_crl_file,
+                    )
+                    .await
+                    {
+                        Ok(client_verifier) => {
+                            self.client_verifier = Some(client_verifier);
+                            self.client_verifier_digest = Some(digest);
+                            changed = true;
+                        }
+                        Err(e) => {
+                            error!("Failed to reload client CA certificates and CRLs: {e}");
+                        }
                     }
                 }
+                Ok(_) => {}
+                Err(e) => {
+                    debug!("Cannot read client CA and CRL files: {e}");
+                }
             }
+        }
 
-            // Reload the server certificates if they have changed keeping the current client CA certificates unchanged
-            if key_changed && cert_changed {
-                info!("Reloading Server TLS certificates");
-
-                cert_changed = false;
-                key_changed = false;
+        if !changed {
+            return;
+        }
 
-                match load_server_cert_and_key(&tls_config.cert_file, &tls_config.key_file).await {
-                    Ok(ck) => {
-                        (cert, key) = ck;
-                    }
-                    Err(e) => {
-                        error!("Failed to reload TLS certificates: {e}");
-                        continue;
-                    }
-                }
+        match build_tls_server_config(
+            &self.protocol_settings,
+            self.certificates[0].certified_key.clone(),
+            self.certificates[1..]
+                .iter()
+                .map(|certificate| certificate.certified_key.clone())
+                .collect(),
+            self.client_verifier.clone(),
+        ) {
+            Ok(server_config) => {
+                self.rustls_config
+                    .reload_from_config(Arc::new(server_config));
+                self.check_expiry();
             }
+            Err(e) => {
+                error!("Failed to reload TLS certificate: {e}");
+            }
+        }
+    }
 
-            match build_tls_server_config(cert.clone(), key.clone_key(), client_verifier.clone()) {
-                Ok(server_config) => {
-                    reloadable_rust_config.reload_from_config(Arc::new(server_config));
-                }
-                Err(e) => {
-                    error!("Failed to reload TLS certificate: {e}");
-                }
+    /// Log the certificates that are expired or about to expire
+    fn check_expiry(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+
+        for certificate in self.certificates.iter() {
+            let cert_file = certificate.files.cert_file.to_str();
+            let expires_in = certificate.not_after - now;
+            if expires_in <= 0 {
+                error!(cert_file, "TLS certificate is expired");
+            } else if expires_in < CERTIFICATE_EXPIRY_WARNING.as_secs() as i64 {
+                warn!(
+                    cert_file,
+                    expires_in_hours = expires_in / 3600,
+                    "TLS certificate is about to expire"
+                );
             }
         }
-    });
+    }
+}
+
+/// The client CA and CRL files
+fn client_verifier_files(tls_config: &TlsConfig) -> Vec<&Path> {
+    tls_config
+        .client_ca_file
+        .iter()
+        .chain(tls_config.client_crl_file.iter())
+        .map(PathBuf::as_path)
+        .collect()
+}
+
+/// The digest of the contents of the given files
+pub(crate) async fn files_digest(paths: &[&Path]) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    for path in paths {
+        let contents = tokio::fs::read(path)
+            .await
+            .map_err(|e| anyhow!("Cannot read {}: {e}", path.display()))?;
+        hasher.update((contents.len() as u64).to_be_bytes());
+        hasher.update(&contents);
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Unix timestamp of the expiry of the certificate
+fn certificate_not_after(cert: &CertificateDer<'_>) -> Result<i64> {
+    let (_, cert) =
+        X509Certificate::from_der(cert).map_err(|e| anyhow!("Cannot parse certificate: {e}"))?;
+    Ok(cert.validity().not_after.timestamp())
+}
+
+/// Performs the TLS handshake, then makes the identity of the client available to the
+/// request handlers as an `Option<ClientIdentity>` extension. The identity is `None` when
+/// the client did not present a certificate.
+#[derive(Clone)]
+pub(crate) struct ClientIdentityAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl ClientIdentityAcceptor {
+    pub(crate) fn new(tls_config: RustlsConfig) -> Self {
+        Self {
+            inner: RustlsAcceptor::new(tls_config),
+        }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientIdentityAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, Option<ClientIdentity>>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.inner.clone();
+
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            let identity = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| {
+                    ClientIdentity::from_certificate(cert)
+                        .inspect_err(|e| warn!("Cannot read the identity of the client: {e}"))
+                        .ok()
+                });
+
+            Ok((stream, AddExtension::new(service, identity)))
+        })
+    }
+}
+
+/// The cipher suites, key exchange groups and protocol versions used by the HTTPS server
+struct TlsProtocolSettings {
+    provider: Arc<CryptoProvider>,
+    versions: Vec<&'static SupportedProtocolVersion>,
+}
+
+impl TlsProtocolSettings {
+    fn new(tls_config: &TlsConfig) -> Result<Self> {
+        let mut provider = rustls::crypto::ring::default_provider();
+        if !tls_config.cipher_suites.is_empty() {
+            provider.cipher_suites = tls_config
+                .cipher_suites
+                .iter()
+                .map(|name| {
+                    rustls::crypto::ring::ALL_CIPHER_SUITES
+                        .iter()
+                        .find(|suite| {
+                            suite
+                                .suite()
+                                .as_str()
+                                .is_some_and(|suite_name| suite_name.eq_ignore_ascii_case(name))
+                        })
+                        .copied()
+                        .ok_or_else(|| anyhow!("unsupported TLS cipher suite: {name}"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+        }
+        if !tls_config.kx_groups.is_empty() {
+            provider.kx_groups = tls_config
+                .kx_groups
+                .iter()
+                .map(|name| {
+                    rustls::crypto::ring::ALL_KX_GROUPS
+                        .iter()
+                        .find(|group| {
+                            group
+                                .name()
+                                .as_str()
+                                .is_some_and(|group_name| group_name.eq_ignore_ascii_case(name))
+                        })
+                        .copied()
+                        .ok_or_else(|| anyhow!("unsupported TLS key exchange group: {name}"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+        }
+
+        let versions: Vec<&'static SupportedProtocolVersion> = match tls_config.min_version {
+            TlsVersion::Tls12 => vec![&rustls::version::TLS12, &rustls::version::TLS13],
+            TlsVersion::Tls13 => vec![&rustls::version::TLS13],
+        };
+
+        let settings = Self {
+            provider: Arc::new(provider),
+            versions,
+        };
+        // Catch the cipher suites that cannot be used with the protocol versions or
+        // with the key exchange groups
+        settings.config_builder()?;
 
-    Ok(rust_config)
+        Ok(settings)
+    }
+
+    fn config_builder(&self) -> Result<ConfigBuilder<ServerConfig, WantsVerifier>> {
+        ServerConfig::builder_with_provider(self.provider.clone())
+            .with_protocol_versions(&self.versions)
+            .map_err(|e| anyhow!("Invalid TLS protocol settings: {e}"))
+    }
 }
 
 // Build the TLS server
 fn build_tls_server_config(
-    cert: Vec<CertificateDer<'static>>,
-    key: PrivateKeyDer<'static>,
-    client_verifier: Option<Arc<dyn rustls::server::danger::ClientCertVerifier>>,
+    protocol_settings: &TlsProtocolSettings,
+    default_cert: Arc<CertifiedKey>,
+    sni_certs: Vec<Arc<CertifiedKey>>,
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
 ) -> Result<rustls::ServerConfig> {
+    let cert_resolver = Arc::new(SniCertResolver::new(default_cert, sni_certs)?);
+    let builder = protocol_settings.config_builder()?;
+
     if let Some(client_verifier) = client_verifier {
-        return Ok(ServerConfig::builder()
+        return Ok(builder
             .with_client_cert_verifier(client_verifier)
-            .with_single_cert(cert, key)?);
+            .with_cert_resolver(cert_resolver));
     }
 
-    Ok(ServerConfig::builder()
+    Ok(builder
         .with_no_client_auth()
-        .with_single_cert(cert, key)?)
+        .with_cert_resolver(cert_resolver))
 }
 
-// Load the server certificate and key
-async fn load_server_cert_and_key(
-    cert_file: &Path,
-    key_file: &Path,
-) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
-    let cert_contents = tokio::fs::read(cert_file).await?;
-    let key_contents = tokio::fs::read(key_file).await?;
+/// Select the certificate to be served using the SNI sent by the client.
+///
+/// The default certificate is served when the client does not send the SNI,
+/// or when none of the SNI certificates is valid for the requested name.
+#[derive(Debug)]
+struct SniCertResolver {
+    default_cert: Arc<CertifiedKey>,
+    /// The SNI certificates, together with the DNS names they are valid for
+    sni_certs: Vec<(Vec<String>, Arc<CertifiedKey>)>,
+}
 
-    let cert_reader = &mut BufReader::new(&cert_contents[..]);
-    let key_reader = &mut BufReader::new(&key_contents[..]);
+impl SniCertResolver {
+    fn new(default_cert: Arc<CertifiedKey>, sni_certs: Vec<Arc<CertifiedKey>>) -> Result<Self> {
+        let sni_certs = sni_certs
+            .into_iter()
+            .map(|certified_key| {
+                let leaf = certified_key
+                    .end_entity_cert()
+                    .map_err(|e| anyhow!("Cannot find SNI leaf certificate: {e}"))?;
+                let dns_names = dns_names(leaf)?;
+                if dns_names.is_empty() {
+                    return Err(anyhow!(
+                        "SNI certificate does not have any DNS subject alternative name"
+                    ));
+                }
+                Ok((dns_names, certified_key))
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-    let cert: Vec<CertificateDer> = rustls_pemfile::certs(cert_reader)
-        .filter_map(|it| {
-            if let Err(ref e) = it {
-                warn!("Cannot parse certificate: {e}");
-                return None;
-            }
-            it.ok()
+        Ok(Self {
+            default_cert,
+            sni_certs,
         })
-        .collect();
-    if cert.len() > 1 {
-        return Err(anyhow!("Multiple certificates provided in cert file"));
-    }
-
-    let mut key_vec: Vec<Vec<u8>> = rustls_pemfile::read_all(key_reader)
-        .filter_map(|i| match i.ok()? {
-            Item::Sec1Key(key) => Some(key.secret_sec1_der().to_vec()),
-            Item::Pkcs1Key(key) => Some(key.secret_pkcs1_der().to_vec()),
-            Item::Pkcs8Key(key) => Some(key.secret_pkcs8_der().to_vec()),
-            _ => {
-                info!("Ignoring non-key item in key file");
-                None
+    }
+
+    fn certified_key_for(&self, server_name: Option<&str>) -> Arc<CertifiedKey> {
+        if let Some(server_name) = server_name {
+            let server_name = server_name.to_ascii_lowercase();
+            if let Some((_, certified_key)) = self.sni_certs.iter().find(|(dns_names, _)| {
+                dns_names
+                    .iter()
+                    .any(|dns_name| dns_name_matches(dns_name, &server_name))
+            }) {
+                return certified_key.clone();
             }
+            debug!(
+                server_name,
+                "no SNI certificate matches, using the default one"
+            );
+        }
+
+        self.default_cert.clone()
+    }
+}
+
+impl ResolvesServerCert for SniCertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key_for(client_hello.server_name()))
+    }
+}
+
+// The DNS subject alternative names of the certificate, lowercased
+fn dns_names(cert: &CertificateDer<'_>) -> Result<Vec<String>> {
+    let (_, cert) =
+        X509Certificate::from_der(cert).map_err(|e| anyhow!("Cannot parse certificate: {e}"))?;
+    let subject_alternative_name = cert
+        .subject_alternative_name()
+        .map_err(|e| anyhow!("Cannot parse subject alternative names: {e}"))?;
+
+    Ok(subject_alternative_name
+        .map(|san| {
+            san.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns_name) => Some(dns_name.to_ascii_lowercase()),
+                    _ => None,
+                })
+                .collect()
         })
-        .collect();
-    if key_vec.is_empty() {
+        .unwrap_or_default())
+}
+
+// Check whether the DNS name of a certificate, which can contain a leading wildcard label,
+// matches the given server name
+fn dns_name_matches(dns_name: &str, server_name: &str) -> bool {
+    match dns_name.strip_prefix("*.") {
+        Some(domain) => server_name
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest == domain),
+        None => dns_name == server_name,
+    }
+}
+
+// Load the server certificate chain and key.
+//
+// The certificate file can contain the full chain, ordered leaf first. The key can be stored
+// either inside of its own file or together with the certificates. In the latter case,
+// `key_file` is the same as `cert_file`.
+async fn load_server_cert_and_key(cert_file: &Path, key_file: &Path) -> Result<Arc<CertifiedKey>> {
+    let cert_contents = tokio::fs::read(cert_file).await?;
+    let (cert, mut keys) = parse_pem_items(&cert_contents);
+
+    if key_file != cert_file {
+        let key_contents = tokio::fs::read(key_file).await?;
+        let (key_file_certs, key_file_keys) = parse_pem_items(&key_contents);
+        if !key_file_certs.is_empty() {
+            info!("Ignoring certificates found in key file");
+        }
+        keys.extend(key_file_keys);
+    }
+
+    if cert.is_empty() {
+        return Err(anyhow!("No certificate provided in cert file"));
+    }
+    if keys.is_empty() {
         return Err(anyhow!("No key provided in key file"));
     }
-    if key_vec.len() > 1 {
+    if keys.len() > 1 {
         return Err(anyhow!("Multiple keys provided in key file"));
     }
-    let key = PrivateKeyDer::try_from(key_vec.pop().unwrap())
-        .map_err(|e| anyhow!("Cannot parse server key: {e}"))?;
+    let key = keys.pop().unwrap();
 
-    Ok((cert, key))
+    validate_cert_chain_order(&cert)?;
+
+    // This ensures the key belongs to the leaf certificate, otherwise all the TLS handshakes
+    // would fail
+    let certified_key =
+        CertifiedKey::from_der(cert, key, &rustls::crypto::ring::default_provider())
+            .map_err(|e| anyhow!("The key does not match the certificate: {e}"))?;
+
+    Ok(Arc::new(certified_key))
+}
+
+// Extract the certificates and the private keys stored inside of a PEM file
+fn parse_pem_items(contents: &[u8]) -> (Vec<CertificateDer<'static>>, Vec<PrivateKeyDer<'static>>) {
+    let reader = &mut BufReader::new(contents);
+
+    let mut certs = Vec::new();
+    let mut keys = Vec::new();
+    for item in rustls_pemfile::read_all(reader) {
+        match item {
+            Ok(Item::X509Certificate(cert)) => certs.push(cert),
+            Ok(Item::Sec1Key(key)) => keys.push(PrivateKeyDer::Sec1(key)),
+            Ok(Item::Pkcs1Key(key)) => keys.push(PrivateKeyDer::Pkcs1(key)),
+            Ok(Item::Pkcs8Key(key)) => keys.push(PrivateKeyDer::Pkcs8(key)),
+            Ok(_) => info!("Ignoring unsupported PEM item"),
+            Err(e) => warn!("Cannot parse PEM item: {e}"),
+        }
+    }
+
+    (certs, keys)
 }
 
-// Load the client CA certificates and build the client verifier
-async fn load_client_ca_certs(
-    client_cas: Vec<std::path::PathBuf>,
-) -> Result<Arc<dyn rustls::server::danger::ClientCertVerifier>> {
+// Ensure the certificate chain starts with the leaf certificate, followed by the
+// certificates of its issuers. Each certificate must be issued by the next one.
+fn validate_cert_chain_order(chain: &[CertificateDer<'_>]) -> Result<()> {
+    let parsed_chain = chain
+        .iter()
+        .enumerate()
+        .map(|(i, cert)| {
+            X509Certificate::from_der(cert)
+                .map(|(_, cert)| cert)
+                .map_err(|e| anyhow!("Cannot parse certificate #{}: {e}", i + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for (i, pair) in parsed_chain.windows(2).enumerate() {
+        let (cert, issuer) = (&pair[0], &pair[1]);
+        if cert.issuer() != issuer.subject() {
+            return Err(anyhow!(
+                "Certificate chain must be ordered leaf first: certificate #{} ({}) is not issued by certificate #{} ({})",
+                i + 1,
+                cert.subject(),
+                i + 2,
+                issuer.subject()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Load the client CA certificates and the CRLs, then build the client verifier
+async fn load_client_verifier(
+    client_cas: &[PathBuf],
+    client_crls: &[PathBuf],
+) -> Result<Arc<dyn ClientCertVerifier>> {
     let mut store = RootCertStore::empty();
     for client_ca_file in client_cas {
-        let client_ca_contents = tokio::fs::read(&client_ca_file).await?;
+        let client_ca_contents = tokio::fs::read(client_ca_file).await?;
         let client_ca_reader = &mut BufReader::new(&client_ca_contents[..]);
 
         let client_ca_certs: Vec<_> = rustls_pemfile::certs(client_ca_reader)
@@ -252,7 +768,446 @@ async fn load_client_ca_certs(
         );
     }
 
-    WebPkiClientVerifier::builder(Arc::new(store))
+    let mut crls = Vec::new();
+    for client_crl_file in client_crls {
+        let file_crls = load_crls(client_crl_file).await?;
+        info!(
+            client_crl_file = client_crl_file.to_str(),
+            client_crls_added = file_crls.len(),
+            "Loaded client certificate revocation lists"
+        );
+        crls.extend(file_crls);
+    }
+
+    let mut builder = WebPkiClientVerifier::builder(Arc::new(store));
+    if !crls.is_empty() {
+        // Client certificates issued by a CA that has no CRL are still accepted
+        builder = builder.with_crls(crls).allow_unknown_revocation_status();
+    }
+    let verifier = builder
         .build()
-        .map_err(|e| anyhow!("Cannot build client verifier: {e}"))
+        .map_err(|e| anyhow!("Cannot build client verifier: {e}"))?;
+
+    Ok(Arc::new(LoggingClientCertVerifier { inner: verifier }))
+}
+
+// Load the CRLs stored inside of a file, either PEM or DER encoded
+async fn load_crls(crl_file: &Path) -> Result<Vec<CertificateRevocationListDer<'static>>> {
+    let contents = tokio::fs::read(crl_file)
+        .await
+        .map_err(|e| anyhow!("Cannot read CRL file {}: {e}", crl_file.display()))?;
+
+    let crls = rustls_pemfile::crls(&mut BufReader::new(&contents[..]))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow!("Cannot parse CRL file {}: {e}", crl_file.display()))?;
+    if crls.is_empty() {
+        // Not a PEM file, assume the CRL is DER encoded
+        return Ok(vec![CertificateRevocationListDer::from(contents)]);
+    }
+
+    Ok(crls)
+}
+
+/// Wraps the client verifier to log and count the client certificates being rejected
+#[derive(Debug)]
+struct LoggingClientCertVerifier {
+    inner: Arc<dyn ClientCertVerifier>,
+}
+
+impl ClientCertVerifier for LoggingClientCertVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.inner.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.inner.client_auth_mandatory()
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.inner.root_hint_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        self.inner
+            .verify_client_cert(end_entity, intermediates, now)
+            .inspect_err(|e| {
+                let reason = rejection_reason(e);
+                warn!(reason, error = %e, "TLS client certificate rejected");
+                metrics::add_tls_client_certificate_rejection(reason);
+            })
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+// The reason why a client certificate has been rejected, used as metric label
+fn rejection_reason(error: &rustls::Error) -> &'static str {
+    match error {
+        rustls::Error::InvalidCertificate(e) => match e {
+            CertificateError::Revoked => "revoked",
+            CertificateError::Expired | CertificateError::ExpiredContext { .. } => "expired",
+            CertificateError::NotValidYet | CertificateError::NotValidYetContext { .. } => {
+                "not_valid_yet"
+            }
+            CertificateError::UnknownIssuer => "unknown_issuer",
+            CertificateError::BadSignature => "bad_signature",
+            CertificateError::UnknownRevocationStatus => "unknown_revocation_status",
+            CertificateError::ExpiredRevocationList
+            | CertificateError::ExpiredRevocationListContext { .. } => "expired_crl",
+            CertificateError::InvalidPurpose | CertificateError::InvalidPurposeContext { .. } => {
+                "invalid_purpose"
+            }
+            _ => "invalid_certificate",
+        },
+        rustls::Error::InvalidCertRevocationList(_) => "invalid_crl",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{
+        BasicConstraints, CertificateParams, CertificateRevocationListParams, DnType, IsCa, Issuer,
+        KeyIdMethod, KeyPair, RevocationReason, RevokedCertParams, SerialNumber, date_time_ymd,
+    };
+    use tempfile::TempDir;
+
+    struct Chain {
+        leaf: String,
+        leaf_key: String,
+        intermediate: String,
+        root: String,
+    }
+
+    fn ca_params(common_name: &str) -> CertificateParams {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        params
+    }
+
+    fn generate_chain() -> Chain {
+        let root_params = ca_params("Root CA");
+        let root_key = KeyPair::generate().unwrap();
+        let root = root_params.self_signed(&root_key).unwrap();
+        let root_issuer = Issuer::new(root_params, root_key);
+
+        let intermediate_params = ca_params("Intermediate CA");
+        let intermediate_key = KeyPair::generate().unwrap();
+        let intermediate = intermediate_params
+            .signed_by(&intermediate_key, &root_issuer)
+            .unwrap();
+        let intermediate_issuer = Issuer::new(intermediate_params, intermediate_key);
+
+        let mut leaf_params = CertificateParams::new(vec!["localhost".to_owned()]).unwrap();
+        leaf_params
+            .distinguished_name
+            .push(DnType::CommonName, "localhost");
+        let leaf_key = KeyPair::generate().unwrap();
+        let leaf = leaf_params
+            .signed_by(&leaf_key, &intermediate_issuer)
+            .unwrap();
+
+        Chain {
+            leaf: leaf.pem(),
+            leaf_key: leaf_key.serialize_pem(),
+            intermediate: intermediate.pem(),
+            root: root.pem(),
+        }
+    }
+
+    async fn load(cert_contents: &str, key_contents: Option<&str>) -> Result<usize> {
+        let dir = TempDir::new().unwrap();
+        let cert_file = dir.path().join("cert.pem");
+        tokio::fs::write(&cert_file, cert_contents).await.unwrap();
+
+        let key_file = match key_contents {
+            Some(key_contents) => {
+                let key_file = dir.path().join("key.pem");
+                tokio::fs::write(&key_file, key_contents).await.unwrap();
+                key_file
+            }
+            None => cert_file.clone(),
+        };
+
+        load_server_cert_and_key(&cert_file, &key_file)
+            .await
+            .map(|certified_key| certified_key.cert.len())
+    }
+
+    #[tokio::test]
+    async fn load_full_chain() {
+        let chain = generate_chain();
+        let cert_contents = format!("{}{}{}", chain.leaf, chain.intermediate, chain.root);
+
+        assert_eq!(
+            3,
+            load(&cert_contents, Some(&chain.leaf_key)).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn load_combined_pem() {
+        let chain = generate_chain();
+        let contents = format!("{}{}{}", chain.leaf, chain.intermediate, chain.leaf_key);
+
+        assert_eq!(2, load(&contents, None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn reject_chain_not_ordered_leaf_first() {
+        let chain = generate_chain();
+        let cert_contents = format!("{}{}", chain.intermediate, chain.leaf);
+
+        let error = load(&cert_contents, Some(&chain.leaf_key))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("ordered leaf first"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn reject_key_not_matching_leaf() {
+        let chain = generate_chain();
+        let other_chain = generate_chain();
+        let cert_contents = format!("{}{}", chain.leaf, chain.intermediate);
+
+        let error = load(&cert_contents, Some(&other_chain.leaf_key))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("does not match"), "{error}");
+    }
+
+    fn self_signed_certified_key(dns_names: &[&str]) -> Arc<CertifiedKey> {
+        let params =
+            CertificateParams::new(dns_names.iter().map(|name| name.to_string()).collect())
+                .unwrap();
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+
+        Arc::new(
+            CertifiedKey::from_der(
+                vec![cert.der().clone()],
+                PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+                &rustls::crypto::ring::default_provider(),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[rstest::rstest]
+    #[case::exact("policy-server.example.com", "policy-server.example.com", true)]
+    #[case::different("policy-server.example.com", "other.example.com", false)]
+    #[case::wildcard("*.example.com", "policy-server.example.com", true)]
+    #[case::wildcard_single_label("*.example.com", "a.b.example.com", false)]
+    #[case::wildcard_apex("*.example.com", "example.com", false)]
+    fn dns_name_matching(
+        #[case] dns_name: &str,
+        #[case] server_name: &str,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(expected, dns_name_matches(dns_name, server_name));
+    }
+
+    #[test]
+    fn select_certificate_by_sni() {
+        let default_cert = self_signed_certified_key(&["policy-server.kubewarden.svc"]);
+        let external_cert = self_signed_certified_key(&["policy-server.example.com"]);
+
+        let resolver =
+            SniCertResolver::new(default_cert.clone(), vec![external_cert.clone()]).unwrap();
+
+        assert!(Arc::ptr_eq(
+            &external_cert,
+            &resolver.certified_key_for(Some("Policy-Server.example.com"))
+        ));
+        assert!(Arc::ptr_eq(
+            &default_cert,
+            &resolver.certified_key_for(Some("unknown.example.com"))
+        ));
+        assert!(Arc::ptr_eq(
+            &default_cert,
+            &resolver.certified_key_for(None)
+        ));
+    }
+
+    #[rstest::rstest]
+    #[case::pem_crl(false)]
+    #[case::der_crl(true)]
+    #[tokio::test]
+    async fn reject_revoked_client_certificates(#[case] der_encoded_crl: bool) {
+        let params = ca_params("Client CA");
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = params.self_signed(&ca_key).unwrap();
+        let ca_issuer = Issuer::new(params, ca_key);
+
+        let client_cert = |serial_number: u64| {
+            let mut params = CertificateParams::new(vec![]).unwrap();
+            params.distinguished_name.push(DnType::CommonName, "client");
+            params.serial_number = Some(SerialNumber::from(serial_number));
+            let key = KeyPair::generate().unwrap();
+            params.signed_by(&key, &ca_issuer).unwrap()
+        };
+        let revoked_cert = client_cert(42);
+        let valid_cert = client_cert(43);
+
+        let crl = CertificateRevocationListParams {
+            this_update: date_time_ymd(2024, 1, 1),
+            next_update: date_time_ymd(2100, 1, 1),
+            crl_number: SerialNumber::from(1),
+            issuing_distribution_point: None,
+            revoked_certs: vec![RevokedCertParams {
+                serial_number: SerialNumber::from(42),
+                revocation_time: date_time_ymd(2024, 1, 1),
+                reason_code: Some(RevocationReason::KeyCompromise),
+                invalidity_date: None,
+            }],
+            key_identifier_method: KeyIdMethod::Sha256,
+        }
+        .signed_by(&ca_issuer)
+        .unwrap();
+
+        let dir = TempDir::new().unwrap();
+        let ca_file = dir.path().join("ca.pem");
+        tokio::fs::write(&ca_file, ca.pem()).await.unwrap();
+        let crl_file = dir.path().join("ca.crl");
+        if der_encoded_crl {
+            tokio::fs::write(&crl_file, crl.der().as_ref())
+                .await
+                .unwrap();
+        } else {
+            tokio::fs::write(&crl_file, crl.pem().unwrap())
+                .await
+                .unwrap();
+        }
+
+        let verifier = load_client_verifier(&[ca_file.clone()], &[crl_file])
+            .await
+            .unwrap();
+        let error = verifier
+            .verify_client_cert(revoked_cert.der(), &[], UnixTime::now())
+            .expect_err("revoked certificate should be rejected");
+        assert_eq!("revoked", rejection_reason(&error));
+        assert!(
+            verifier
+                .verify_client_cert(valid_cert.der(), &[], UnixTime::now())
+                .is_ok()
+        );
+
+        let verifier = load_client_verifier(&[ca_file], &[]).await.unwrap();
+        assert!(
+            verifier
+                .verify_client_cert(revoked_cert.der(), &[], UnixTime::now())
+                .is_ok()
+        );
+    }
+
+    #[rstest::rstest]
+    #[case::defaults(TlsVersion::Tls12, &[], &[], true)]
+    #[case::tls13_only(TlsVersion::Tls13, &["TLS13_AES_256_GCM_SHA384"], &["X25519"], true)]
+    #[case::tls12_suites(TlsVersion::Tls12, &["TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384", "tls_ecdhe_rsa_with_aes_256_gcm_sha384"], &[], true)]
+    #[case::unknown_cipher_suite(TlsVersion::Tls12, &["TLS_RSA_WITH_RC4_128_SHA"], &[], false)]
+    #[case::unknown_kx_group(TlsVersion::Tls12, &[], &["secp521r1"], false)]
+    #[case::tls12_suites_with_tls13_only(TlsVersion::Tls13, &["TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"], &[], false)]
+    fn tls_protocol_settings(
+        #[case] min_version: TlsVersion,
+        #[case] cipher_suites: &[&str],
+        #[case] kx_groups: &[&str],
+        #[case] valid: bool,
+    ) {
+        let tls_config = TlsConfig {
+            cert_file: PathBuf::from("cert.pem"),
+            key_file: PathBuf::from("key.pem"),
+            client_ca_file: vec![],
+            client_crl_file: vec![],
+            sni_certs: vec![],
+            min_version,
+            cipher_suites: cipher_suites.iter().map(|name| name.to_string()).collect(),
+            kx_groups: kx_groups.iter().map(|name| name.to_string()).collect(),
+            reload_poll_interval: None,
+        };
+
+        assert_eq!(valid, TlsProtocolSettings::new(&tls_config).is_ok());
+    }
+
+    #[tokio::test]
+    async fn reload_changed_certificates() {
+        let self_signed = |year: i32| {
+            let mut params = CertificateParams::new(vec!["localhost".to_owned()]).unwrap();
+            params.not_after = date_time_ymd(year, 1, 1);
+            let key = KeyPair::generate().unwrap();
+            let cert = params.self_signed(&key).unwrap();
+            (cert, key)
+        };
+        let (cert, key) = self_signed(2100);
+        let (renewed_cert, renewed_key) = self_signed(2101);
+
+        let dir = TempDir::new().unwrap();
+        let cert_file = dir.path().join("tls.crt");
+        let key_file = dir.path().join("tls.key");
+        tokio::fs::write(&cert_file, cert.pem()).await.unwrap();
+        tokio::fs::write(&key_file, key.serialize_pem())
+            .await
+            .unwrap();
+
+        let mut tls_files = TlsFiles::load(TlsConfig {
+            cert_file: cert_file.clone(),
+            key_file: key_file.clone(),
+            client_ca_file: vec![],
+            client_crl_file: vec![],
+            sni_certs: vec![],
+            min_version: TlsVersion::default(),
+            cipher_suites: vec![],
+            kx_groups: vec![],
+            reload_poll_interval: None,
+        })
+        .await
+        .unwrap();
+        assert_eq!(cert.der(), &tls_files.certificates[0].certified_key.cert[0]);
+        assert_eq!(4102444800, tls_files.certificates[0].not_after);
+
+        // The key has not been updated yet, the current certificate is kept
+        tokio::fs::write(&cert_file, renewed_cert.pem())
+            .await
+            .unwrap();
+        tls_files.reload_changed_files().await;
+        assert_eq!(cert.der(), &tls_files.certificates[0].certified_key.cert[0]);
+
+        tokio::fs::write(&key_file, renewed_key.serialize_pem())
+            .await
+            .unwrap();
+        tls_files.reload_changed_files().await;
+        assert_eq!(
+            renewed_cert.der(),
+            &tls_files.certificates[0].certified_key.cert[0]
+        );
+        assert_eq!(4133980800, tls_files.certificates[0].not_after);
+    }
 }
@@ -4,6 +4,8 @@ use itertools::Itertools;
 use lazy_static::lazy_static;
 use policy_evaluator::burrego;
 
+use crate::config::METRICS_OPTIONAL_LABELS;
+
 lazy_static! {
     static ref VERSION_AND_BUILTINS: String = {
         let builtins: String = burrego::get_builtins()
@@ -46,7 +48,7 @@ pub(crate) fn build_cli() -> Command {
                 PossibleValue::new("json"),
                 PossibleValue::new("otlp"),
             ])
-            .help("Log output format"),
+            .help("Log output format. With otlp, the spans and the log events are exported to the OpenTelemetry collector"),
 
         Arg::new("log-no-color")
             .long("log-no-color")
@@ -54,6 +56,43 @@ pub(crate) fn build_cli() -> Command {
             .action(ArgAction::SetTrue)
             .help("Disable colored output for logs"),
 
+        Arg::new("log-no-stdout")
+            .long("log-no-stdout")
+            .env("KUBEWARDEN_LOG_NO_STDOUT")
+            .action(ArgAction::SetTrue)
+            .help("Do not print the logs on stdout when --log-fmt is otlp, they are only exported to the OpenTelemetry collector"),
+
+        Arg::new("tracing-sampling-ratio")
+            .long("tracing-sampling-ratio")
+            .value_name("RATIO")
+            .env("KUBEWARDEN_TRACING_SAMPLING_RATIO")
+            .default_value("1.0")
+            .help("Fraction of the traces that are sampled, between 0 and 1. Used when --log-fmt is otlp"),
+
+        Arg::new("tracing-parent-based-sampling")
+            .long("tracing-parent-based-sampling")
+            .env("KUBEWARDEN_TRACING_PARENT_BASED_SAMPLING")
+            .action(ArgAction::SetTrue)
+            .help("Follow the sampling decision of the caller when the request continues its trace. The sampling ratio applies to the other traces"),
+
+        Arg::new("tracing-always-sample-errors")
+            .long("tracing-always-sample-errors")
+            .env("KUBEWARDEN_TRACING_ALWAYS_SAMPLE_ERRORS")
+            .action(ArgAction::SetTrue)
+            .help("Export the traces of the rejected or failed evaluations, even when they are not sampled. The spans of the unsampled traces are recorded until the request completes"),
+
+        Arg::new("cluster-name")
+            .long("cluster-name")
+            .value_name("CLUSTER_NAME")
+            .env("KUBEWARDEN_CLUSTER_NAME")
+            .help("Name of the cluster, reported as the k8s.cluster.name resource attribute of the traces. Overrides the value of OTEL_RESOURCE_ATTRIBUTES"),
+
+        Arg::new("pod-name")
+            .long("pod-name")
+            .value_name("POD_NAME")
+            .env("KUBEWARDEN_POD_NAME")
+            .help("Name of the pod, reported as the k8s.pod.name resource attribute of the traces. Overrides the value of OTEL_RESOURCE_ATTRIBUTES"),
+
         Arg::new("address")
             .long("addr")
             .value_name("BIND_ADDRESS")
@@ -68,6 +107,31 @@ pub(crate) fn build_cli() -> Command {
             .env("KUBEWARDEN_PORT")
             .help("Listen on PORT"),
 
+        Arg::new("unix-socket")
+            .long("unix-socket")
+            .value_name("PATH")
+            .env("KUBEWARDEN_UNIX_SOCKET")
+            .value_parser(clap::builder::PathBufValueParser::new())
+            .help("Serve the API on a Unix domain socket too, in addition to the TCP address. Access is controlled by the permissions of the socket file, the client authentication and authorization do not apply"),
+
+        Arg::new("unix-socket-mode")
+            .long("unix-socket-mode")
+            .value_name("MODE")
+            .env("KUBEWARDEN_UNIX_SOCKET_MODE")
+            .help("Octal permissions of the Unix domain socket file, like 0660"),
+
+        Arg::new("unix-socket-owner")
+            .long("unix-socket-owner")
+            .value_name("UID[:GID]")
+            .env("KUBEWARDEN_UNIX_SOCKET_OWNER")
+            .help("Numeric user id and, optionally, group id owning the Unix domain socket file"),
+
+        Arg::new("unix-socket-only")
+            .long("unix-socket-only")
+            .env("KUBEWARDEN_UNIX_SOCKET_ONLY")
+            .action(ArgAction::SetTrue)
+            .help("Serve the API only on the Unix domain socket, the TCP address is not bound. The readiness probe is still served over TCP"),
+
         Arg::new("readiness-probe-port")
             .long("readiness-probe-port")
             .value_name("READINESS_PROBE_PORT")
@@ -75,6 +139,32 @@ pub(crate) fn build_cli() -> Command {
             .env("KUBEWARDEN_READINESS_PROBE_PORT")
             .help("Expose readiness endpoint on READINESS_PROBE_PORT"),
 
+        Arg::new("audit-port")
+            .long("audit-port")
+            .value_name("AUDIT_PORT")
+            .env("KUBEWARDEN_AUDIT_PORT")
+            .help("Serve the audit endpoint on AUDIT_PORT, instead of PORT"),
+
+        Arg::new("audit-client-ca-file")
+            .long("audit-client-ca-file")
+            .value_delimiter(',')
+            .value_name("AUDIT_CLIENT_CA_FILE")
+            .env("KUBEWARDEN_AUDIT_CLIENT_CA_FILE")
+            .value_parser(clap::builder::PathBufValueParser::new())
+            .help("Path to the CA certificates used to authenticate the clients of the audit endpoint. Requires --audit-port. The --client-ca-file certificates are used when not set"),
+
+        Arg::new("audit-workers")
+            .long("audit-workers")
+            .value_name("AUDIT_WORKERS_NUMBER")
+            .env("KUBEWARDEN_AUDIT_WORKERS")
+            .help("Number of audit requests evaluated concurrently. Requires --audit-port. Defaults to the number of workers"),
+
+        Arg::new("pprof-port")
+            .long("pprof-port")
+            .value_name("PPROF_PORT")
+            .env("KUBEWARDEN_PPROF_PORT")
+            .help("Serve the pprof and the log level endpoints over plain HTTP on localhost:PPROF_PORT, instead of PORT. Requires --enable-pprof or --enable-log-level-endpoint"),
+
         Arg::new("workers")
             .long("workers")
             .value_name("WORKERS_NUMBER")
@@ -86,14 +176,14 @@ pub(crate) fn build_cli() -> Command {
             .value_name("CERT_FILE")
             .env("KUBEWARDEN_CERT_FILE")
             .value_parser(clap::builder::PathBufValueParser::new())
-            .help("Path to an X.509 certificate file for HTTPS"),
+            .help("Path to an X.509 certificate file for HTTPS. It can contain the full certificate chain, ordered leaf first, and the key too"),
 
         Arg::new("key-file")
             .long("key-file")
             .value_name("KEY_FILE")
             .env("KUBEWARDEN_KEY_FILE")
             .value_parser(clap::builder::PathBufValueParser::new())
-            .help("Path to an X.509 private key file for HTTPS"),
+            .help("Path to an X.509 private key file for HTTPS. Not required when the key is stored inside of the certificate file"),
 
         Arg::new("client-ca-file")
             .long("client-ca-file")
@@ -103,6 +193,70 @@ pub(crate) fn build_cli() -> Command {
             .value_parser(clap::builder::PathBufValueParser::new())
             .help("Path to an CA certificate file that issued the client certificate. Required to enable mTLS"),
 
+        Arg::new("bearer-token-auth-path")
+            .long("bearer-token-auth-path")
+            .value_name("BEARER_TOKEN_AUTH_CONFIG_PATH")
+            .env("KUBEWARDEN_BEARER_TOKEN_AUTH_CONFIG_PATH")
+            .help("YAML file holding the configuration of the bearer-token authentication: static tokens and Kubernetes ServiceAccount tokens"),
+
+        Arg::new("client-authorization-path")
+            .long("client-authorization-path")
+            .value_name("CLIENT_AUTHORIZATION_CONFIG_PATH")
+            .env("KUBEWARDEN_CLIENT_AUTHORIZATION_CONFIG_PATH")
+            .help("YAML file holding the rules that restrict the endpoints and policies each mTLS client can use. Requires --client-ca-file"),
+
+        Arg::new("client-crl-file")
+            .long("client-crl-file")
+            .value_delimiter(',')
+            .value_name("CLIENT_CRL_FILE")
+            .env("KUBEWARDEN_CLIENT_CRL_FILE")
+            .value_parser(clap::builder::PathBufValueParser::new())
+            .help("Path to a certificate revocation list (CRL) file, PEM or DER encoded. Client certificates revoked by it are rejected. Requires --client-ca-file"),
+
+        Arg::new("tls-min-version")
+            .long("tls-min-version")
+            .value_name("VERSION")
+            .env("KUBEWARDEN_TLS_MIN_VERSION")
+            .value_parser(["1.2", "1.3"])
+            .default_value("1.2")
+            .help("The minimum TLS protocol version accepted by the HTTPS server"),
+
+        Arg::new("tls-cipher-suites")
+            .long("tls-cipher-suites")
+            .value_delimiter(',')
+            .value_name("CIPHER_SUITES")
+            .env("KUBEWARDEN_TLS_CIPHER_SUITES")
+            .help("Comma separated list of the cipher suites enabled by the HTTPS server, like TLS13_AES_128_GCM_SHA256. All the secure cipher suites are enabled when not set"),
+
+        Arg::new("tls-kx-groups")
+            .long("tls-kx-groups")
+            .value_delimiter(',')
+            .value_name("KX_GROUPS")
+            .env("KUBEWARDEN_TLS_KX_GROUPS")
+            .help("Comma separated list of the key exchange groups enabled by the HTTPS server: X25519, secp256r1, secp384r1. All of them are enabled when not set"),
+
+        Arg::new("tls-reload-poll-interval")
+            .long("tls-reload-poll-interval")
+            .env("KUBEWARDEN_TLS_RELOAD_POLL_INTERVAL")
+            .value_name("SECONDS")
+            .help("Periodically check the TLS certificate, key, client CA and CRL files for changes and reload them. Useful when file change notifications are not available or not reliable. Disabled when not set"),
+
+        Arg::new("sni-cert-file")
+            .long("sni-cert-file")
+            .value_delimiter(',')
+            .value_name("SNI_CERT_FILE")
+            .env("KUBEWARDEN_SNI_CERT_FILE")
+            .value_parser(clap::builder::PathBufValueParser::new())
+            .help("Path to an additional X.509 certificate file for HTTPS, served when the SNI sent by the client matches one of its DNS names. Can be repeated, each certificate requires its own --sni-key-file"),
+
+        Arg::new("sni-key-file")
+            .long("sni-key-file")
+            .value_delimiter(',')
+            .value_name("SNI_KEY_FILE")
+            .env("KUBEWARDEN_SNI_KEY_FILE")
+            .value_parser(clap::builder::PathBufValueParser::new())
+            .help("Path to the X.509 private key file of the --sni-cert-file with the same position"),
+
         Arg::new("policies")
             .long("policies")
             .value_name("POLICIES_FILE")
@@ -136,6 +290,12 @@ pub(crate) fn build_cli() -> Command {
             .env("KUBEWARDEN_VERIFICATION_CONFIG_PATH")
             .help("YAML file holding verification information (URIs, keys, annotations...)"),
 
+        Arg::new("attestations-path")
+            .long("attestations-path")
+            .value_name("ATTESTATIONS_CONFIG_PATH")
+            .env("KUBEWARDEN_ATTESTATIONS_CONFIG_PATH")
+            .help("YAML file holding the in-toto attestations (SLSA provenance, SBOM) the modules fetched from OCI registries must have"),
+
         Arg::new("docker-config-json-path")
             .long("docker-config-json-path")
             .value_name("DOCKER_CONFIG")
@@ -148,6 +308,48 @@ pub(crate) fn build_cli() -> Command {
             .action(ArgAction::SetTrue)
             .help("Enable metrics"),
 
+        Arg::new("metrics-histogram-buckets")
+            .long("metrics-histogram-buckets")
+            .value_name("BOUNDARIES")
+            .env("KUBEWARDEN_METRICS_HISTOGRAM_BUCKETS")
+            .help("Comma-separated, increasing bucket boundaries of the latency histograms, expressed in milliseconds, or in seconds with --metrics-latency-seconds. The OpenTelemetry SDK defaults are used when not set"),
+
+        Arg::new("metrics-latency-seconds")
+            .long("metrics-latency-seconds")
+            .env("KUBEWARDEN_METRICS_LATENCY_SECONDS")
+            .action(ArgAction::SetTrue)
+            .help("Record the latencies as floating-point seconds, into histograms named `*_seconds` instead of `*_milliseconds`"),
+
+        Arg::new("metrics-drop-labels")
+            .long("metrics-drop-labels")
+            .value_delimiter(',')
+            .value_name("LABELS")
+            .env("KUBEWARDEN_METRICS_DROP_LABELS")
+            .value_parser(METRICS_OPTIONAL_LABELS)
+            .conflicts_with("metrics-allow-labels")
+            .help("Comma-separated list of the optional labels not attached to the policy evaluation metrics"),
+
+        Arg::new("metrics-allow-labels")
+            .long("metrics-allow-labels")
+            .value_delimiter(',')
+            .value_name("LABELS")
+            .env("KUBEWARDEN_METRICS_ALLOW_LABELS")
+            .value_parser(METRICS_OPTIONAL_LABELS)
+            .help("Comma-separated list of the only optional labels attached to the policy evaluation metrics"),
+
+        Arg::new("metrics-namespace-buckets")
+            .long("metrics-namespace-buckets")
+            .value_delimiter(',')
+            .value_name("NAME=PATTERN")
+            .env("KUBEWARDEN_METRICS_NAMESPACE_BUCKETS")
+            .help("Comma-separated list of buckets the namespaces are reported as, like `system=kube-*`. `*` matches any sequence of characters. The namespaces not matching any bucket are reported as `other`"),
+
+        Arg::new("metrics-max-label-values")
+            .long("metrics-max-label-values")
+            .value_name("NUMBER")
+            .env("KUBEWARDEN_METRICS_MAX_LABEL_VALUES")
+            .help("Maximum number of distinct values of the namespace, kind and operation labels of the policy evaluation metrics. Further values are reported as `__overflow__`"),
+
         Arg::new("always-accept-admission-reviews-on-namespace")
             .long("always-accept-admission-reviews-on-namespace")
             .value_name("NAMESPACE")
@@ -204,6 +406,51 @@ pub(crate) fn build_cli() -> Command {
             .action(ArgAction::SetTrue)
             .help("Enable pprof profiling"),
 
+        Arg::new("enable-log-level-endpoint")
+            .long("enable-log-level-endpoint")
+            .env("KUBEWARDEN_ENABLE_LOG_LEVEL_ENDPOINT")
+            .action(ArgAction::SetTrue)
+            .help("Enable the /debug/log_level endpoint, used to change the log level at runtime. The changes are reverted after a timeout. Requires --pprof-port, unless the clients of the API are authenticated"),
+
+        Arg::new("enable-policies-gc")
+            .long("enable-policies-gc")
+            .env("KUBEWARDEN_ENABLE_POLICIES_GC")
+            .action(ArgAction::SetTrue)
+            .help("Remove the modules of the policies download directory that are no longer referenced by the policies file"),
+
+        Arg::new("policies-gc-min-age")
+            .long("policies-gc-min-age")
+            .env("KUBEWARDEN_POLICIES_GC_MIN_AGE")
+            .value_name("SECONDS")
+            .default_value("86400")
+            .help("Unreferenced modules are removed only when they have not been modified for at least the given time"),
+
+        Arg::new("policies-gc-dry-run")
+            .long("policies-gc-dry-run")
+            .env("KUBEWARDEN_POLICIES_GC_DRY_RUN")
+            .action(ArgAction::SetTrue)
+            .help("List the unreferenced modules of the policies download directory without removing them"),
+
+        Arg::new("policies-integrity-check-interval")
+            .long("policies-integrity-check-interval")
+            .env("KUBEWARDEN_POLICIES_INTEGRITY_CHECK_INTERVAL")
+            .value_name("SECONDS")
+            .help("Periodically ensure the policy modules still resolve to the manifest digests being used and that their signatures are still valid. Disabled when not set"),
+
+        Arg::new("shutdown-delay")
+            .long("shutdown-delay")
+            .env("KUBEWARDEN_SHUTDOWN_DELAY")
+            .value_name("SECONDS")
+            .default_value("5")
+            .help("On SIGTERM or SIGINT, keep serving requests for the given time while reporting not ready, giving the load balancers time to stop sending new requests"),
+
+        Arg::new("shutdown-timeout")
+            .long("shutdown-timeout")
+            .env("KUBEWARDEN_SHUTDOWN_TIMEOUT")
+            .value_name("SECONDS")
+            .default_value("20")
+            .help("Maximum time given to the in-flight requests to complete when shutting down. The remaining connections are closed afterwards"),
+
         Arg::new("continue-on-errors")
             .long("continue-on-errors")
             .env("KUBEWARDEN_CONTINUE_ON_ERRORS")
@@ -231,4 +478,21 @@ pub(crate) fn build_cli() -> Command {
                         .help("path where the documentation file will be stored"),
                 ),
         )
+        .subcommand(
+            Command::new("gc")
+                .about("Removes the modules of the policies download directory that are no longer referenced by the policies file")
+                .arg(
+                    Arg::new("min-age")
+                        .long("min-age")
+                        .value_name("SECONDS")
+                        .default_value("86400")
+                        .help("Remove only the modules that have not been modified for at least the given time"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("List the modules that would be removed, without removing them"),
+                ),
+        )
 }
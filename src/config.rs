@@ -7,17 +7,21 @@ use policy_evaluator::{
     policy_evaluator::PolicySettings,
     policy_fetcher::{
         sources::{Sources, read_sources_file},
-        verify::config::{LatestVerificationConfig, VerificationConfigV1, read_verification_file},
+        verify::config::{
+            AnyOf, LatestVerificationConfig, Signature, VerificationConfigV1,
+            read_verification_file,
+        },
     },
     policy_metadata::ContextAwareResource,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{BTreeSet, HashMap},
     env,
     fs::{self, File},
     net::SocketAddr,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 pub static SERVICE_NAME: &str = "kubewarden-policy-server";
@@ -30,6 +34,13 @@ lazy_static! {
 
 pub struct Config {
     pub addr: SocketAddr,
+    /// Serve the API on a Unix domain socket too
+    pub unix_socket: Option<UnixSocketConfig>,
+    /// Serve the audit endpoint on its own listener, instead of `addr`
+    pub audit_listener: Option<AuditListenerConfig>,
+    /// Serve the pprof and the log level endpoints over plain HTTP on this address, instead of
+    /// `addr`
+    pub pprof_addr: Option<SocketAddr>,
     pub readiness_probe_addr: SocketAddr,
     pub sources: Option<Sources>,
     pub policies: HashMap<String, PolicyOrPolicyGroup>,
@@ -41,23 +52,182 @@ pub struct Config {
     pub tls_config: Option<TlsConfig>,
     pub pool_size: usize,
     pub metrics_enabled: bool,
+    pub metrics_histograms: MetricsHistogramConfig,
+    pub metrics_labels: MetricsLabelsConfig,
     pub sigstore_cache_dir: PathBuf,
     pub verification_config: Option<VerificationConfigV1>,
     pub log_level: String,
     pub log_fmt: String,
     pub log_no_color: bool,
+    /// Do not print the logs on stdout when they are exported through OTLP
+    pub log_no_stdout: bool,
+    pub tracing_config: TracingConfig,
     pub daemon: bool,
     pub enable_pprof: bool,
+    pub enable_log_level_endpoint: bool,
     pub daemon_pid_file: String,
     pub daemon_stdout_file: Option<String>,
     pub daemon_stderr_file: Option<String>,
     pub continue_on_errors: bool,
+    pub policies_gc: Option<PoliciesGcConfig>,
+    /// How often the policy modules are checked for tag drift and signature revocation.
+    /// The check is disabled when not set.
+    pub policies_integrity_check_interval: Option<Duration>,
+    /// The attestations all the modules fetched from OCI registries must have
+    pub attestation_requirements: Option<AttestationRequirements>,
+    /// Restricts the endpoints and policies each mTLS client can use. All the clients
+    /// presenting a trusted certificate can use everything when not set
+    pub client_authorization: Option<ClientAuthorizationConfig>,
+    /// Requires the clients to authenticate with a bearer token
+    pub bearer_token_auth: Option<BearerTokenAuthConfig>,
+    /// How long the server keeps accepting requests after receiving a termination signal,
+    /// while reporting itself as not ready
+    pub shutdown_delay: Duration,
+    /// How long the in-flight requests are given to complete once the server stops
+    /// accepting new ones
+    pub shutdown_timeout: Duration,
 }
 
+/// How the latency histograms are recorded
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricsHistogramConfig {
+    /// The explicit bucket boundaries of the latency histograms, expressed in the unit
+    /// of the histograms. The SDK default boundaries are used when empty
+    pub buckets: Vec<f64>,
+    /// Record the latencies as floating-point seconds, into histograms whose name ends
+    /// with `_seconds`, instead of whole milliseconds
+    pub latency_seconds: bool,
+}
+
+/// The optional labels of the policy evaluation metrics, whose values depend on the
+/// requests being evaluated
+pub const METRICS_OPTIONAL_LABELS: [&str; 4] = [
+    "resource_namespace",
+    "resource_kind",
+    "resource_request_operation",
+    "error_code",
+];
+
+/// Limits the number of series created by the policy evaluation metrics
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricsLabelsConfig {
+    /// The optional labels that are not attached to the metrics
+    pub dropped_labels: BTreeSet<String>,
+    /// Report the namespaces as the name of the first bucket matching them, or as
+    /// `other`. The namespaces are reported as they are when empty
+    pub namespace_buckets: Vec<NamespaceBucket>,
+    /// The maximum number of distinct values of the namespace, kind and operation
+    /// labels. The values seen once the limit is reached are reported as `__overflow__`
+    pub max_label_values: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamespaceBucket {
+    pub name: String,
+    /// A pattern where `*` matches any sequence of characters
+    pub pattern: String,
+}
+
+/// How the traces are sampled, and the resource attributes describing the
+/// policy server inside of them
+#[derive(Clone, Debug, PartialEq)]
+pub struct TracingConfig {
+    /// The fraction of the traces that are sampled, between 0 and 1
+    pub sampling_ratio: f64,
+    /// Follow the sampling decision of the caller when the request continues its trace
+    pub parent_based_sampling: bool,
+    /// Export the traces of the rejected or failed evaluations, even when they are
+    /// not sampled
+    pub always_sample_errors: bool,
+    pub cluster_name: Option<String>,
+    pub pod_name: Option<String>,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            sampling_ratio: 1.0,
+            parent_based_sampling: false,
+            always_sample_errors: false,
+            cluster_name: None,
+            pod_name: None,
+        }
+    }
+}
+
+/// A listener dedicated to the audit endpoint, so that audit scans do not affect
+/// the latency of admission requests
+pub struct AuditListenerConfig {
+    pub addr: SocketAddr,
+    /// The CA certificates trusted to authenticate the audit clients. The client CA
+    /// certificates of the API listener are used when empty
+    pub client_ca_file: Vec<PathBuf>,
+    /// Number of audit requests evaluated concurrently
+    pub pool_size: usize,
+}
+
+/// A Unix domain socket serving the API
+pub struct UnixSocketConfig {
+    pub path: PathBuf,
+    /// The permissions of the socket file, like `0o660`. The process umask
+    /// applies when not set
+    pub mode: Option<u32>,
+    /// The user owning the socket file
+    pub owner: Option<u32>,
+    /// The group owning the socket file
+    pub group: Option<u32>,
+    /// Do not serve the API on the TCP address
+    pub exclusive: bool,
+}
+
+#[derive(Clone)]
 pub struct TlsConfig {
+    /// The certificate chain, ordered leaf first. It can contain the key too
     pub cert_file: PathBuf,
+    /// The key of the certificate. Same as `cert_file` when the key is stored
+    /// together with the certificate chain
     pub key_file: PathBuf,
     pub client_ca_file: Vec<PathBuf>,
+    /// Certificate revocation lists used to reject revoked client certificates
+    pub client_crl_file: Vec<PathBuf>,
+    /// Additional certificates, selected when the SNI sent by the client matches
+    /// one of their DNS names. The `cert_file` certificate is used otherwise
+    pub sni_certs: Vec<CertificateFiles>,
+    /// The minimum TLS protocol version accepted
+    pub min_version: TlsVersion,
+    /// The names of the cipher suites to enable, like `TLS13_AES_128_GCM_SHA256`.
+    /// The rustls defaults are used when empty
+    pub cipher_suites: Vec<String>,
+    /// The names of the key exchange groups to enable, like `X25519`.
+    /// The rustls defaults are used when empty
+    pub kx_groups: Vec<String>,
+    /// How often the TLS files are checked for changes, in addition to the inotify
+    /// notifications available on linux. Polling is disabled when not set
+    pub reload_poll_interval: Option<Duration>,
+}
+
+/// A TLS protocol version
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TlsVersion {
+    #[default]
+    Tls12,
+    Tls13,
+}
+
+/// A certificate chain and its key
+#[derive(Clone)]
+pub struct CertificateFiles {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+}
+
+/// Garbage collection of the modules stored inside of the policies download directory
+pub struct PoliciesGcConfig {
+    /// Unreferenced modules are removed only when they have not been modified for at least
+    /// this amount of time
+    pub min_age: Duration,
+    /// List the modules that would be removed, without removing them
+    pub dry_run: bool,
 }
 
 impl Config {
@@ -99,11 +269,14 @@ impl Config {
             .get_one::<bool>("enable-metrics")
             .expect("clap should have set a default value")
             .to_owned();
+        let metrics_histograms = metrics_histogram_config(matches)?;
+        let metrics_labels = metrics_labels_config(matches)?;
         let ignore_kubernetes_connection_failure = matches
             .get_one::<bool>("ignore-kubernetes-connection-failure")
             .expect("clap should have set a default value")
             .to_owned();
         let verification_config = verification_config(matches)?;
+        let attestation_requirements = attestation_requirements(matches)?;
         let sigstore_cache_dir = matches
             .get_one::<String>("sigstore-cache-dir")
             .map(PathBuf::from)
@@ -132,21 +305,110 @@ impl Config {
             .get_one::<bool>("log-no-color")
             .expect("clap should have assigned a default value")
             .to_owned();
+        let log_no_stdout = matches
+            .get_one::<bool>("log-no-stdout")
+            .expect("clap should have assigned a default value")
+            .to_owned();
+
+        let tracing_config = tracing_config(matches)?;
 
         let tls_config = build_tls_config(matches)?;
+        let client_authorization = client_authorization(matches)?;
+        let bearer_token_auth = bearer_token_auth(matches)?;
+        let unix_socket = unix_socket(matches)?;
+        let audit_listener = audit_listener(matches, pool_size)?;
+        if audit_listener
+            .as_ref()
+            .is_some_and(|audit_listener| !audit_listener.client_ca_file.is_empty())
+            && tls_config.is_none()
+        {
+            return Err(anyhow!(
+                "audit client CA certificate requires server certificate and key to be specified"
+            ));
+        }
+        if unix_socket
+            .as_ref()
+            .is_some_and(|unix_socket| unix_socket.exclusive)
+            && tls_config.is_some()
+        {
+            return Err(anyhow!(
+                "TLS cannot be used when the API is served only on the Unix socket"
+            ));
+        }
+        if client_authorization.is_some()
+            && tls_config
+                .as_ref()
+                .is_none_or(|tls_config| tls_config.client_ca_file.is_empty())
+        {
+            return Err(anyhow!(
+                "client authorization requires mTLS, the client CA certificate must be specified"
+            ));
+        }
 
         let enable_pprof = matches
             .get_one::<bool>("enable-pprof")
             .expect("clap should have assigned a default value")
             .to_owned();
+        let pprof_addr = pprof_bind_address(matches)?;
+        let enable_log_level_endpoint = matches
+            .get_one::<bool>("enable-log-level-endpoint")
+            .expect("clap should have assigned a default value")
+            .to_owned();
+        if pprof_addr.is_some() && !enable_pprof && !enable_log_level_endpoint {
+            return Err(anyhow!(
+                "pprof-port requires enable-pprof or enable-log-level-endpoint to be set"
+            ));
+        }
+        // Changing the log level affects the whole process, anonymous clients must not be able
+        // to reach the endpoint
+        if enable_log_level_endpoint
+            && pprof_addr.is_none()
+            && client_authorization.is_none()
+            && bearer_token_auth.is_none()
+            && unix_socket
+                .as_ref()
+                .is_none_or(|unix_socket| !unix_socket.exclusive)
+        {
+            return Err(anyhow!(
+                "the log level endpoint must be served on pprof-port when the clients of the API are not authenticated"
+            ));
+        }
 
         let continue_on_errors = matches
             .get_one::<bool>("continue-on-errors")
             .expect("clap should have assigned a default value")
             .to_owned();
 
+        let policies_gc = policies_gc_config(matches)?;
+
+        let policies_integrity_check_interval = matches
+            .get_one::<String>("policies-integrity-check-interval")
+            .map(|interval| {
+                interval
+                    .parse::<u64>()
+                    .map(Duration::from_secs)
+                    .map_err(|e| anyhow!("error parsing policies-integrity-check-interval: {}", e))
+            })
+            .transpose()?;
+
+        let shutdown_delay = matches
+            .get_one::<String>("shutdown-delay")
+            .expect("clap should have assigned a default value")
+            .parse::<u64>()
+            .map(Duration::from_secs)
+            .map_err(|e| anyhow!("error parsing shutdown-delay: {}", e))?;
+        let shutdown_timeout = matches
+            .get_one::<String>("shutdown-timeout")
+            .expect("clap should have assigned a default value")
+            .parse::<u64>()
+            .map(Duration::from_secs)
+            .map_err(|e| anyhow!("error parsing shutdown-timeout: {}", e))?;
+
         Ok(Self {
             addr,
+            unix_socket,
+            audit_listener,
+            pprof_addr,
             readiness_probe_addr,
             sources,
             policies,
@@ -157,17 +419,73 @@ impl Config {
             policy_evaluation_limit_seconds,
             pool_size,
             metrics_enabled,
+            metrics_histograms,
+            metrics_labels,
             sigstore_cache_dir,
             verification_config,
             log_level,
             log_fmt,
             log_no_color,
+            log_no_stdout,
+            tracing_config,
             daemon,
             daemon_pid_file,
             daemon_stdout_file,
             daemon_stderr_file,
             enable_pprof,
+            enable_log_level_endpoint,
             continue_on_errors,
+            policies_gc,
+            policies_integrity_check_interval,
+            attestation_requirements,
+            client_authorization,
+            bearer_token_auth,
+            shutdown_delay,
+            shutdown_timeout,
+        })
+    }
+
+    /// Returns `true` when some evaluations can run in debug mode: a client authorization
+    /// rule allows the clients to use it
+    pub fn debug_mode_enabled(&self) -> bool {
+        self.client_authorization
+            .as_ref()
+            .is_some_and(|client_authorization| {
+                client_authorization.rules.iter().any(|rule| rule.debug)
+            })
+    }
+}
+
+/// The configuration of the `gc` subcommand. Only the flags it uses are parsed, running it
+/// doesn't require the configuration of the server
+pub struct GcConfig {
+    pub policies: HashMap<String, PolicyOrPolicyGroup>,
+    pub policies_download_dir: PathBuf,
+    pub log_level: String,
+    pub log_no_color: bool,
+}
+
+impl GcConfig {
+    pub fn from_args(matches: &ArgMatches) -> Result<Self> {
+        let policies = policies(matches)?;
+        let policies_download_dir = matches
+            .get_one::<String>("policies-download-dir")
+            .map(PathBuf::from)
+            .expect("This should not happen, there's a default value for policies-download-dir");
+        let log_level = matches
+            .get_one::<String>("log-level")
+            .expect("This should not happen, there's a default value for log-level")
+            .to_owned();
+        let log_no_color = matches
+            .get_one::<bool>("log-no-color")
+            .expect("clap should have assigned a default value")
+            .to_owned();
+
+        Ok(Self {
+            policies,
+            policies_download_dir,
+            log_level,
+            log_no_color,
         })
     }
 }
@@ -192,31 +510,364 @@ fn readiness_probe_bind_address(matches: &clap::ArgMatches) -> Result<SocketAddr
     .map_err(|e| anyhow!("error parsing arguments: {}", e))
 }
 
+fn audit_listener(
+    matches: &clap::ArgMatches,
+    default_pool_size: usize,
+) -> Result<Option<AuditListenerConfig>> {
+    let client_ca_file: Vec<PathBuf> = matches
+        .get_many::<PathBuf>("audit-client-ca-file")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let pool_size = matches
+        .get_one::<String>("audit-workers")
+        .map(|workers| {
+            workers
+                .parse::<usize>()
+                .map_err(|e| anyhow!("error parsing audit-workers: {}", e))
+        })
+        .transpose()?;
+
+    let Some(port) = matches.get_one::<String>("audit-port") else {
+        if !client_ca_file.is_empty() || pool_size.is_some() {
+            return Err(anyhow!(
+                "audit-client-ca-file and audit-workers require audit-port to be specified"
+            ));
+        }
+        return Ok(None);
+    };
+    let addr = format!("{}:{}", matches.get_one::<String>("address").unwrap(), port)
+        .parse()
+        .map_err(|e| anyhow!("error parsing arguments: {}", e))?;
+
+    Ok(Some(AuditListenerConfig {
+        addr,
+        client_ca_file,
+        pool_size: pool_size.unwrap_or(default_pool_size),
+    }))
+}
+
+/// pprof and the log level endpoint are always served on localhost, they must not be reachable
+/// from the network
+fn pprof_bind_address(matches: &clap::ArgMatches) -> Result<Option<SocketAddr>> {
+    matches
+        .get_one::<String>("pprof-port")
+        .map(|port| {
+            port.parse::<u16>()
+                .map(|port| SocketAddr::from(([127, 0, 0, 1], port)))
+                .map_err(|e| anyhow!("error parsing pprof-port: {}", e))
+        })
+        .transpose()
+}
+
+fn unix_socket(matches: &clap::ArgMatches) -> Result<Option<UnixSocketConfig>> {
+    let exclusive = matches
+        .get_one::<bool>("unix-socket-only")
+        .expect("clap should have assigned a default value")
+        .to_owned();
+    let path = match matches.get_one::<PathBuf>("unix-socket") {
+        Some(path) => path.to_owned(),
+        None => {
+            if exclusive {
+                return Err(anyhow!(
+                    "unix-socket-only requires unix-socket to be specified"
+                ));
+            }
+            return Ok(None);
+        }
+    };
+
+    let mode = matches
+        .get_one::<String>("unix-socket-mode")
+        .map(|mode| {
+            u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                .map_err(|e| anyhow!("error parsing unix-socket-mode: {}", e))
+        })
+        .transpose()?;
+
+    let (owner, group) = match matches.get_one::<String>("unix-socket-owner") {
+        Some(owner) => {
+            let (uid, gid) = match owner.split_once(':') {
+                Some((uid, gid)) => (uid, Some(gid)),
+                None => (owner.as_str(), None),
+            };
+            let uid = uid
+                .parse::<u32>()
+                .map_err(|e| anyhow!("error parsing unix-socket-owner user id: {}", e))?;
+            let gid = gid
+                .map(|gid| {
+                    gid.parse::<u32>()
+                        .map_err(|e| anyhow!("error parsing unix-socket-owner group id: {}", e))
+                })
+                .transpose()?;
+            (Some(uid), gid)
+        }
+        None => (None, None),
+    };
+
+    Ok(Some(UnixSocketConfig {
+        path,
+        mode,
+        owner,
+        group,
+        exclusive,
+    }))
+}
+
 fn build_tls_config(matches: &clap::ArgMatches) -> Result<Option<TlsConfig>> {
     let cert_file = matches.get_one::<PathBuf>("cert-file").cloned();
     let key_file = matches.get_one::<PathBuf>("key-file").cloned();
     let client_ca_file = matches.get_many::<PathBuf>("client-ca-file");
+    let client_crl_file: Vec<PathBuf> = matches
+        .get_many::<PathBuf>("client-crl-file")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let sni_certs = sni_certs(matches)?;
 
-    match (cert_file, key_file, &client_ca_file) {
-        (Some(cert_file), Some(key_file), _) => Ok(Some(TlsConfig {
-            cert_file,
-            key_file,
-            client_ca_file: client_ca_file
-                .unwrap_or_default()
-                .map(|p| p.to_owned())
-                .collect::<Vec<PathBuf>>(),
-        })),
+    let min_version = match matches
+        .get_one::<String>("tls-min-version")
+        .expect("clap should have set a default value")
+        .as_str()
+    {
+        "1.2" => TlsVersion::Tls12,
+        "1.3" => TlsVersion::Tls13,
+        version => return Err(anyhow!("unsupported TLS version: {version}")),
+    };
+    let cipher_suites: Vec<String> = matches
+        .get_many::<String>("tls-cipher-suites")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let kx_groups: Vec<String> = matches
+        .get_many::<String>("tls-kx-groups")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let reload_poll_interval = matches
+        .get_one::<String>("tls-reload-poll-interval")
+        .map(|interval| {
+            interval
+                .parse::<u64>()
+                .map(Duration::from_secs)
+                .map_err(|e| anyhow!("error parsing tls-reload-poll-interval: {}", e))
+        })
+        .transpose()?;
+
+    if !client_crl_file.is_empty() && client_ca_file.is_none() {
+        return Err(anyhow!(
+            "client certificate revocation lists require the client CA certificate to be specified"
+        ));
+    }
+
+    let (cert_file, key_file) = match (cert_file, key_file, &client_ca_file) {
+        (Some(cert_file), Some(key_file), _) => (cert_file, key_file),
+        // Combined PEM file, holding both the certificate chain and the key
+        (Some(cert_file), None, _) => (cert_file.clone(), cert_file),
         // No TLS configuration provided
-        (None, None, None) => Ok(None),
+        (None, None, None) => {
+            if !sni_certs.is_empty() {
+                return Err(anyhow!(
+                    "SNI certificates require the default server certificate to be specified"
+                ));
+            }
+            if !cipher_suites.is_empty() || !kx_groups.is_empty() {
+                return Err(anyhow!(
+                    "TLS cipher suites and key exchange groups require the server certificate to be specified"
+                ));
+            }
+            return Ok(None);
+        }
         // Client CA certificate provided without server certificate and key
-        (None, None, Some(_)) => Err(anyhow!(
-            "client CA certificate requires server certificate and key to be specified"
-        )),
-        // Server certificate or key provided without the other
-        (Some(_), None, _) | (None, Some(_), _) => Err(anyhow!(
-            "both certificate and key must be provided together"
-        )),
+        (None, None, Some(_)) => {
+            return Err(anyhow!(
+                "client CA certificate requires server certificate and key to be specified"
+            ));
+        }
+        // Key provided without the certificate
+        (None, Some(_), _) => {
+            return Err(anyhow!("the key requires the certificate to be specified"));
+        }
+    };
+
+    Ok(Some(TlsConfig {
+        cert_file,
+        key_file,
+        client_ca_file: client_ca_file
+            .unwrap_or_default()
+            .map(|p| p.to_owned())
+            .collect::<Vec<PathBuf>>(),
+        client_crl_file,
+        sni_certs,
+        min_version,
+        cipher_suites,
+        kx_groups,
+        reload_poll_interval,
+    }))
+}
+
+fn sni_certs(matches: &clap::ArgMatches) -> Result<Vec<CertificateFiles>> {
+    let cert_files: Vec<PathBuf> = matches
+        .get_many::<PathBuf>("sni-cert-file")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let key_files: Vec<PathBuf> = matches
+        .get_many::<PathBuf>("sni-key-file")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+
+    if cert_files.len() != key_files.len() {
+        return Err(anyhow!(
+            "each SNI certificate file must have its own key file, {} certificates and {} keys provided",
+            cert_files.len(),
+            key_files.len()
+        ));
+    }
+
+    Ok(cert_files
+        .into_iter()
+        .zip(key_files)
+        .map(|(cert_file, key_file)| CertificateFiles {
+            cert_file,
+            key_file,
+        })
+        .collect())
+}
+
+fn policies_gc_config(matches: &clap::ArgMatches) -> Result<Option<PoliciesGcConfig>> {
+    if !*matches
+        .get_one::<bool>("enable-policies-gc")
+        .expect("clap should have set a default value")
+    {
+        return Ok(None);
+    }
+
+    let min_age = matches
+        .get_one::<String>("policies-gc-min-age")
+        .expect("policies-gc-min-age should always be set")
+        .parse::<u64>()
+        .map_err(|e| anyhow!("error parsing policies-gc-min-age: {}", e))?;
+    let dry_run = matches
+        .get_one::<bool>("policies-gc-dry-run")
+        .expect("clap should have set a default value")
+        .to_owned();
+
+    Ok(Some(PoliciesGcConfig {
+        min_age: Duration::from_secs(min_age),
+        dry_run,
+    }))
+}
+
+fn metrics_histogram_config(matches: &clap::ArgMatches) -> Result<MetricsHistogramConfig> {
+    let buckets = matches
+        .get_one::<String>("metrics-histogram-buckets")
+        .map(|buckets| {
+            buckets
+                .split(',')
+                .map(|boundary| boundary.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|e| anyhow!("error parsing metrics-histogram-buckets: {}", e))
+        })
+        .transpose()?
+        .unwrap_or_default();
+    if buckets.iter().any(|boundary| !boundary.is_finite())
+        || buckets.windows(2).any(|pair| pair[0] >= pair[1])
+    {
+        return Err(anyhow!(
+            "metrics-histogram-buckets must be finite and strictly increasing"
+        ));
     }
+    let latency_seconds = matches
+        .get_one::<bool>("metrics-latency-seconds")
+        .expect("clap should have set a default value")
+        .to_owned();
+
+    Ok(MetricsHistogramConfig {
+        buckets,
+        latency_seconds,
+    })
+}
+
+fn metrics_labels_config(matches: &clap::ArgMatches) -> Result<MetricsLabelsConfig> {
+    let dropped_labels: BTreeSet<String> = match matches.get_many::<String>("metrics-allow-labels")
+    {
+        Some(allowed_labels) => {
+            let allowed_labels: BTreeSet<&str> = allowed_labels.map(String::as_str).collect();
+            METRICS_OPTIONAL_LABELS
+                .iter()
+                .filter(|label| !allowed_labels.contains(*label))
+                .map(|label| label.to_string())
+                .collect()
+        }
+        None => matches
+            .get_many::<String>("metrics-drop-labels")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+    };
+    let namespace_buckets = matches
+        .get_many::<String>("metrics-namespace-buckets")
+        .unwrap_or_default()
+        .map(|bucket| {
+            bucket
+                .split_once('=')
+                .filter(|(name, pattern)| !name.is_empty() && !pattern.is_empty())
+                .map(|(name, pattern)| NamespaceBucket {
+                    name: name.to_owned(),
+                    pattern: pattern.to_owned(),
+                })
+                .ok_or_else(|| {
+                    anyhow!(
+                        "error parsing metrics-namespace-buckets: '{}' is not in the NAME=PATTERN format",
+                        bucket
+                    )
+                })
+        })
+        .collect::<Result<Vec<NamespaceBucket>>>()?;
+    let max_label_values = matches
+        .get_one::<String>("metrics-max-label-values")
+        .map(|max| {
+            max.parse::<usize>()
+                .map_err(|e| anyhow!("error parsing metrics-max-label-values: {}", e))
+        })
+        .transpose()?;
+
+    Ok(MetricsLabelsConfig {
+        dropped_labels,
+        namespace_buckets,
+        max_label_values,
+    })
+}
+
+fn tracing_config(matches: &clap::ArgMatches) -> Result<TracingConfig> {
+    let sampling_ratio = matches
+        .get_one::<String>("tracing-sampling-ratio")
+        .expect("clap should have assigned a default value")
+        .parse::<f64>()
+        .map_err(|e| anyhow!("error parsing tracing-sampling-ratio: {}", e))?;
+    if !(0.0..=1.0).contains(&sampling_ratio) {
+        return Err(anyhow!("tracing-sampling-ratio must be between 0 and 1"));
+    }
+    let parent_based_sampling = matches
+        .get_one::<bool>("tracing-parent-based-sampling")
+        .expect("clap should have assigned a default value")
+        .to_owned();
+    let always_sample_errors = matches
+        .get_one::<bool>("tracing-always-sample-errors")
+        .expect("clap should have assigned a default value")
+        .to_owned();
+    let cluster_name = matches.get_one::<String>("cluster-name").cloned();
+    let pod_name = matches.get_one::<String>("pod-name").cloned();
+
+    Ok(TracingConfig {
+        sampling_ratio,
+        parent_based_sampling,
+        always_sample_errors,
+        cluster_name,
+        pod_name,
+    })
 }
 
 fn policies(matches: &clap::ArgMatches) -> Result<HashMap<String, PolicyOrPolicyGroup>> {
@@ -237,12 +888,25 @@ fn policies(matches: &clap::ArgMatches) -> Result<HashMap<String, PolicyOrPolicy
 // Validate the policies and policy groups:
 //  - ensure policy names do not contain a '/' character
 //  - ensure names of policy group's policies do not contain a '/' character
+//  - ensure the verification requirements replacing the global ones are not empty
 fn validate_policies(policies: &HashMap<String, PolicyOrPolicyGroup>) -> Result<()> {
     for (name, policy) in policies.iter() {
         if name.contains('/') {
             return Err(anyhow!("policy name '{}' contains a '/' character", name));
         }
+        if let PolicyOrPolicyGroup::Policy {
+            verify: Some(verify),
+            ..
+        } = policy
+        {
+            validate_policy_verification(name, verify)?;
+        }
         if let PolicyOrPolicyGroup::PolicyGroup { policies, .. } = policy {
+            for (id, member) in policies {
+                if let Some(verify) = &member.verify {
+                    validate_policy_verification(&format!("{name}/{id}"), verify)?;
+                }
+            }
             let policies_with_invalid_name: Vec<String> = policies
                 .iter()
                 .filter_map(|(id, _)| if id.contains('/') { Some(id) } else { None })
@@ -260,6 +924,23 @@ fn validate_policies(policies: &HashMap<String, PolicyOrPolicyGroup>) -> Result<
     Ok(())
 }
 
+// Replacing the global requirements with no requirements would silently disable the
+// verification of the module, this must be done explicitly with `verify: skip`
+fn validate_policy_verification(name: &str, verify: &PolicyVerification) -> Result<()> {
+    if let PolicyVerification::Requirements(requirements) = verify
+        && requirements.replace_global
+        && requirements.all_of.as_ref().is_none_or(Vec::is_empty)
+        && requirements.any_of.is_none()
+        && requirements.attestations.is_none()
+    {
+        return Err(anyhow!(
+            "policy '{}' sets replaceGlobal without any verification requirement, use 'verify: skip' to disable the verification of its module",
+            name
+        ));
+    }
+    Ok(())
+}
+
 fn verification_config(matches: &clap::ArgMatches) -> Result<Option<LatestVerificationConfig>> {
     match matches.get_one::<String>("verification-path") {
         None => Ok(None),
@@ -270,6 +951,65 @@ fn verification_config(matches: &clap::ArgMatches) -> Result<Option<LatestVerifi
     }
 }
 
+fn attestation_requirements(matches: &clap::ArgMatches) -> Result<Option<AttestationRequirements>> {
+    match matches.get_one::<String>("attestations-path") {
+        None => Ok(None),
+        Some(path) => {
+            let attestations_file = Path::new(path);
+            Ok(Some(read_attestations_file(attestations_file).map_err(
+                |e| {
+                    anyhow!(
+                        "error while loading attestation requirements from {:?}: {}",
+                        attestations_file,
+                        e
+                    )
+                },
+            )?))
+        }
+    }
+}
+
+fn client_authorization(matches: &clap::ArgMatches) -> Result<Option<ClientAuthorizationConfig>> {
+    match matches.get_one::<String>("client-authorization-path") {
+        None => Ok(None),
+        Some(path) => {
+            let authorization_file = Path::new(path);
+            Ok(Some(
+                read_client_authorization_file(authorization_file).map_err(|e| {
+                    anyhow!(
+                        "error while loading client authorization config from {:?}: {}",
+                        authorization_file,
+                        e
+                    )
+                })?,
+            ))
+        }
+    }
+}
+
+fn bearer_token_auth(matches: &clap::ArgMatches) -> Result<Option<BearerTokenAuthConfig>> {
+    let Some(path) = matches.get_one::<String>("bearer-token-auth-path") else {
+        return Ok(None);
+    };
+    let bearer_token_auth_file = Path::new(path);
+    let bearer_token_auth = read_bearer_token_auth_file(bearer_token_auth_file).map_err(|e| {
+        anyhow!(
+            "error while loading bearer token authentication config from {:?}: {}",
+            bearer_token_auth_file,
+            e
+        )
+    })?;
+    if bearer_token_auth.static_tokens_file.is_none()
+        && bearer_token_auth.service_account_tokens.is_none()
+    {
+        return Err(anyhow!(
+            "bearer token authentication requires static tokens or ServiceAccount tokens to be configured"
+        ));
+    }
+
+    Ok(Some(bearer_token_auth))
+}
+
 fn remote_server_options(matches: &clap::ArgMatches) -> Result<Option<Sources>> {
     let sources = match matches.get_one::<String>("sources-path") {
         Some(sources_file) => Some(
@@ -312,6 +1052,8 @@ pub struct PolicyGroupMember {
     pub context_aware_resources: BTreeSet<ContextAwareResource>,
     /// Timeout for the evaluation of the policy
     pub timeout_eval_seconds: Option<u64>,
+    /// Signature verification requirements of the module
+    pub verify: Option<PolicyVerification>,
 }
 
 impl PolicyGroupMember {
@@ -322,6 +1064,186 @@ impl PolicyGroupMember {
     }
 }
 
+/// The signature verification requirements of a single module. These are merged with the
+/// global verification config provided via the `--verification-path` flag.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum PolicyVerification {
+    /// A special verification mode, like `verify: skip`
+    Mode(VerificationMode),
+    /// Additional signatures that must be satisfied by the module
+    Requirements(PolicyVerificationRequirements),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum VerificationMode {
+    /// Do not verify the module, regardless of the global verification config
+    Skip,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct PolicyVerificationRequirements {
+    /// Ignore the global verification config, enforce only the requirements defined here
+    #[serde(default)]
+    pub replace_global: bool,
+    /// Signatures that must all be satisfied, in addition to the global ones
+    pub all_of: Option<Vec<Signature>>,
+    /// Signatures of which a minimum number must be satisfied. When set, this replaces
+    /// the global `anyOf` requirement
+    pub any_of: Option<AnyOf>,
+    /// In-toto attestations the module must have. When set, this replaces the global
+    /// attestation requirements
+    pub attestations: Option<AttestationRequirements>,
+}
+
+/// Requirements about the in-toto attestations attached to a module hosted on an
+/// OCI registry, like the ones produced by `cosign attest`
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct AttestationRequirements {
+    /// PEM encoded public keys trusted to sign the attestations. Attestations that are
+    /// not signed by any of these keys are ignored
+    pub keys: Vec<String>,
+    /// Requirements about the SLSA provenance of the module
+    pub provenance: Option<ProvenanceRequirements>,
+    /// Require an SBOM attestation (SPDX or CycloneDX)
+    #[serde(default)]
+    pub sbom: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct ProvenanceRequirements {
+    /// The builders trusted to produce the module
+    pub trusted_builders: Vec<TrustedBuilder>,
+    /// Prefixes of the source repositories the module can be built from. Any
+    /// repository is accepted when empty
+    #[serde(default)]
+    pub source_repositories: Vec<String>,
+    /// The minimum SLSA build level of the builder
+    #[serde(default)]
+    pub minimum_slsa_level: u8,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct TrustedBuilder {
+    /// Prefix of the builder id, as found inside of the provenance
+    pub id: String,
+    /// The SLSA build level this builder provides
+    pub slsa_level: u8,
+}
+
+/// Authorization of the clients authenticated via mTLS. A request is allowed only when
+/// at least one of the rules matches both the client certificate and the request
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct ClientAuthorizationConfig {
+    pub rules: Vec<ClientAuthorizationRule>,
+}
+
+/// Grants the clients matching `subjects` or `sans` access to some endpoints.
+/// All the patterns can contain the `*` wildcard, which matches any sequence of characters
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct ClientAuthorizationRule {
+    /// Patterns matched against the common name of the client certificate subject
+    #[serde(default)]
+    pub subjects: Vec<String>,
+    /// Patterns matched against the DNS names, URIs and email addresses found inside
+    /// of the subject alternative names of the client certificate
+    #[serde(default)]
+    pub sans: Vec<String>,
+    /// The endpoints the clients can call
+    pub endpoints: Vec<EndpointKind>,
+    /// Patterns of the policy ids the clients can evaluate. Any policy is allowed when empty
+    #[serde(default)]
+    pub policies: Vec<String>,
+    /// Whether the clients can evaluate the policies in debug mode, using the
+    /// `debug=true` query parameter
+    #[serde(default)]
+    pub debug: bool,
+}
+
+/// The kinds of endpoints exposed by the API server
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum EndpointKind {
+    /// `/validate/{policy_id}`
+    Validate,
+    /// `/validate_raw/{policy_id}`
+    ValidateRaw,
+    /// `/audit/{policy_id}`
+    Audit,
+    /// `/debug/pprof/*`
+    Pprof,
+    /// `/debug/log_level`
+    LogLevel,
+}
+
+impl EndpointKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EndpointKind::Validate => "validate",
+            EndpointKind::ValidateRaw => "validate_raw",
+            EndpointKind::Audit => "audit",
+            EndpointKind::Pprof => "pprof",
+            EndpointKind::LogLevel => "log_level",
+        }
+    }
+}
+
+/// Bearer-token authentication of the clients calling the API server
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct BearerTokenAuthConfig {
+    /// The endpoints requiring a bearer token. Clients authenticated via mTLS do not
+    /// need one
+    #[serde(default = "default_bearer_token_endpoints")]
+    pub endpoints: Vec<EndpointKind>,
+    /// YAML file holding the static tokens. The file is reloaded when changed
+    pub static_tokens_file: Option<PathBuf>,
+    /// Validation of the Kubernetes ServiceAccount tokens
+    pub service_account_tokens: Option<ServiceAccountTokensConfig>,
+}
+
+fn default_bearer_token_endpoints() -> Vec<EndpointKind> {
+    vec![
+        EndpointKind::Validate,
+        EndpointKind::ValidateRaw,
+        EndpointKind::Audit,
+    ]
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct ServiceAccountTokensConfig {
+    /// JSON Web Key Set holding the keys used by the Kubernetes API server to sign the
+    /// tokens, as served by its `/openid/v1/jwks` endpoint. The file is reloaded when changed
+    pub jwks_file: PathBuf,
+    /// The expected issuer of the tokens
+    pub issuer: String,
+    /// The tokens must be issued for at least one of these audiences
+    pub audiences: Vec<String>,
+    /// The service accounts that can authenticate
+    pub service_accounts: Vec<ServiceAccountGrant>,
+}
+
+/// Grants the service accounts matching `subject` access to some policies
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct ServiceAccountGrant {
+    /// Pattern matched against the subject of the token, like `system:serviceaccount:ci:*`.
+    /// The `*` wildcard matches any sequence of characters
+    pub subject: String,
+    /// Patterns of the policy ids the service accounts can evaluate. Any policy is
+    /// allowed when empty
+    #[serde(default)]
+    pub policies: Vec<String>,
+}
+
 /// Describes a policy that can be either an individual policy or a group policy.
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
@@ -345,6 +1267,12 @@ pub enum PolicyOrPolicyGroup {
         message: Option<String>,
         /// Timeout for the evaluation of the policy
         timeout_eval_seconds: Option<u64>,
+        /// Signature verification requirements of the module
+        verify: Option<PolicyVerification>,
+        /// Return the log messages of the policy to the clients allowed to use the debug
+        /// mode, even when they don't request it
+        #[serde(default)]
+        debug: bool,
     },
     /// A group of policies that are evaluated together using a given expression
     #[serde(rename_all = "camelCase")]
@@ -359,10 +1287,49 @@ pub enum PolicyOrPolicyGroup {
         expression: String,
         /// The message that is returned when the group of policies evaluates to false
         message: String,
+        /// Return the log messages of the policies to the clients allowed to use the debug
+        /// mode, even when they don't request it
+        #[serde(default)]
+        debug: bool,
     },
 }
 
 impl PolicyOrPolicyGroup {
+    /// Returns the URLs of the WebAssembly modules used by the policy.
+    /// A policy group returns the modules of all its members.
+    pub fn modules(&self) -> Vec<&str> {
+        match self {
+            PolicyOrPolicyGroup::Policy { module, .. } => vec![module.as_str()],
+            PolicyOrPolicyGroup::PolicyGroup { policies, .. } => policies
+                .values()
+                .map(|member| member.module.as_str())
+                .collect(),
+        }
+    }
+
+    /// Returns `true` when the policy, or the policy group, sets `debug: true`
+    pub fn debug(&self) -> bool {
+        match self {
+            PolicyOrPolicyGroup::Policy { debug, .. }
+            | PolicyOrPolicyGroup::PolicyGroup { debug, .. } => *debug,
+        }
+    }
+
+    /// Returns `true` when the policy, or any member of the policy group, defines its own
+    /// signature verification requirements
+    pub fn has_verification_requirements(&self) -> bool {
+        let is_requirement = |verify: &Option<PolicyVerification>| {
+            matches!(verify, Some(PolicyVerification::Requirements(_)))
+        };
+
+        match self {
+            PolicyOrPolicyGroup::Policy { verify, .. } => is_requirement(verify),
+            PolicyOrPolicyGroup::PolicyGroup { policies, .. } => policies
+                .values()
+                .any(|member| is_requirement(&member.verify)),
+        }
+    }
+
     pub fn settings(&self) -> Result<PolicyOrPolicyGroupSettings> {
         match self {
             PolicyOrPolicyGroup::Policy { settings, .. } => Ok(
@@ -382,6 +1349,30 @@ impl PolicyOrPolicyGroup {
     }
 }
 
+/// Reads the attestation requirements file provided via `--attestations-path`
+fn read_attestations_file(path: &Path) -> Result<AttestationRequirements> {
+    let attestations_file = File::open(path)?;
+    let attestation_requirements: AttestationRequirements =
+        serde_yaml::from_reader(&attestations_file)?;
+    Ok(attestation_requirements)
+}
+
+/// Reads the client authorization rules file provided via `--client-authorization-path`
+fn read_client_authorization_file(path: &Path) -> Result<ClientAuthorizationConfig> {
+    let authorization_file = File::open(path)?;
+    let client_authorization: ClientAuthorizationConfig =
+        serde_yaml::from_reader(&authorization_file)?;
+    Ok(client_authorization)
+}
+
+/// Reads the bearer token authentication file provided via `--bearer-token-auth-path`
+fn read_bearer_token_auth_file(path: &Path) -> Result<BearerTokenAuthConfig> {
+    let bearer_token_auth_file = File::open(path)?;
+    let bearer_token_auth: BearerTokenAuthConfig =
+        serde_yaml::from_reader(&bearer_token_auth_file)?;
+    Ok(bearer_token_auth)
+}
+
 /// Reads the policies configuration file, returns a HashMap with String as value
 /// and Policy as values. The key is the name of the policy as provided by the user
 /// inside of the configuration file. This name is used to build the API path
@@ -453,6 +1444,7 @@ example:
     settings: {}
     allowedToMutate: true
     message: "my custom error message"
+    debug: true
     contextAwareResources:
         - apiVersion: v1
           kind: Namespace
@@ -497,6 +1489,8 @@ group_policy:
                     ]),
                     message: Some("my custom error message".to_owned()),
                     timeout_eval_seconds: None,
+                    verify: None,
+                    debug: true,
                 },
             ),
             (
@@ -513,6 +1507,7 @@ group_policy:
                                 settings: Some(PolicySettings::default()),
                                 context_aware_resources: BTreeSet::new(),
                                 timeout_eval_seconds: None,
+                                verify: None,
                             },
                         ),
                         (
@@ -522,9 +1517,11 @@ group_policy:
                                 settings: Some(PolicySettings::default()),
                                 context_aware_resources: BTreeSet::new(),
                                 timeout_eval_seconds: None,
+                                verify: None,
                             },
                         ),
                     ]),
+                    debug: false,
                 },
             ),
         ]);
@@ -581,6 +1578,91 @@ example:
         }
     }
 
+    #[rstest]
+    #[case::not_set(
+        r#"
+---
+example:
+  module: file:///tmp/namespace-validate-policy.wasm
+"#,
+        None
+    )]
+    #[case::skip(
+        r#"
+---
+example:
+  module: file:///tmp/namespace-validate-policy.wasm
+  verify: skip
+"#,
+        Some(PolicyVerification::Mode(VerificationMode::Skip))
+    )]
+    #[case::requirements(
+        r#"
+---
+example:
+  module: file:///tmp/namespace-validate-policy.wasm
+  verify:
+    replaceGlobal: true
+    allOf:
+      - kind: githubAction
+        owner: kubewarden
+"#,
+        Some(PolicyVerification::Requirements(PolicyVerificationRequirements {
+            replace_global: true,
+            all_of: Some(vec![Signature::GithubAction {
+                owner: "kubewarden".to_owned(),
+                repo: None,
+                annotations: None,
+            }]),
+            any_of: None,
+            attestations: None,
+        }))
+    )]
+    #[case::attestations(
+        r#"
+---
+example:
+  module: registry://ghcr.io/kubewarden/policies/example:v0.1.0
+  verify:
+    attestations:
+      keys:
+        - "public key"
+      provenance:
+        trustedBuilders:
+          - id: https://github.com/slsa-framework/slsa-github-generator/
+            slsaLevel: 3
+        sourceRepositories:
+          - https://github.com/kubewarden/
+        minimumSlsaLevel: 3
+      sbom: true
+"#,
+        Some(PolicyVerification::Requirements(PolicyVerificationRequirements {
+            replace_global: false,
+            all_of: None,
+            any_of: None,
+            attestations: Some(AttestationRequirements {
+                keys: vec!["public key".to_owned()],
+                provenance: Some(ProvenanceRequirements {
+                    trusted_builders: vec![TrustedBuilder {
+                        id: "https://github.com/slsa-framework/slsa-github-generator/".to_owned(),
+                        slsa_level: 3,
+                    }],
+                    source_repositories: vec!["https://github.com/kubewarden/".to_owned()],
+                    minimum_slsa_level: 3,
+                }),
+                sbom: true,
+            }),
+        }))
+    )]
+    fn policy_verification(#[case] input: &str, #[case] expected: Option<PolicyVerification>) {
+        let policies: HashMap<String, PolicyOrPolicyGroup> = serde_yaml::from_str(input).unwrap();
+
+        match policies.get("example").unwrap() {
+            PolicyOrPolicyGroup::Policy { verify, .. } => assert_eq!(&expected, verify),
+            _ => panic!("Expected an Individual policy"),
+        }
+    }
+
     #[test]
     fn boolean_flags() {
         let policies_yaml = r#"
@@ -596,9 +1678,12 @@ example:
 
         let boolean_flags = [
             "--enable-pprof",
+            "--enable-log-level-endpoint",
             "--log-no-color",
+            "--log-no-stdout",
             "--daemon",
             "--enable-metrics",
+            "--metrics-latency-seconds",
         ];
 
         for provide_flag in [true, false] {
@@ -607,14 +1692,221 @@ example:
             let mut flags = vec!["policy-server", &policies_flag];
             if provide_flag {
                 flags.extend(boolean_flags);
+                flags.push("--pprof-port=3001");
             }
 
             let matches = cli.clone().try_get_matches_from(flags).unwrap();
             let config = Config::from_args(&matches).unwrap();
             assert_eq!(provide_flag, config.enable_pprof);
+            assert_eq!(provide_flag, config.enable_log_level_endpoint);
             assert_eq!(provide_flag, config.log_no_color);
+            assert_eq!(provide_flag, config.log_no_stdout);
             assert_eq!(provide_flag, config.daemon);
             assert_eq!(provide_flag, config.metrics_enabled);
+            assert_eq!(provide_flag, config.metrics_histograms.latency_seconds);
+        }
+    }
+
+    #[rstest]
+    #[case::not_enabled(&[], true)]
+    #[case::unauthenticated_api(&["--enable-log-level-endpoint"], false)]
+    #[case::pprof_port(&["--enable-log-level-endpoint", "--pprof-port=3001"], true)]
+    #[case::unix_socket_only(
+        &["--enable-log-level-endpoint", "--unix-socket=/tmp/policy-server.sock", "--unix-socket-only"],
+        true
+    )]
+    #[case::pprof_port_without_debug_endpoints(&["--pprof-port=3001"], false)]
+    fn log_level_endpoint_listener(#[case] flags: &[&str], #[case] accepted: bool) {
+        let policies_yaml = r#"
+---
+example:
+  module: file:///tmp/namespace-validate-policy.wasm
+  settings: {}
+"#;
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(policies_yaml.as_bytes()).unwrap();
+        let file_path = temp_file.into_temp_path();
+        let policies_flag = format!("--policies={}", file_path.to_str().unwrap());
+
+        let cli = cli::build_cli();
+        let matches = cli
+            .try_get_matches_from(
+                ["policy-server", policies_flag.as_str()]
+                    .iter()
+                    .chain(flags),
+            )
+            .unwrap();
+
+        assert_eq!(accepted, Config::from_args(&matches).is_ok());
+    }
+
+    #[test]
+    fn gc_config_ignores_the_server_flags() {
+        let policies_yaml = r#"
+---
+example:
+  module: registry://ghcr.io/kubewarden/tests/pod-privileged:v0.2.1
+  settings: {}
+"#;
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(policies_yaml.as_bytes()).unwrap();
+        let file_path = temp_file.into_temp_path();
+        let policies_flag = format!("--policies={}", file_path.to_str().unwrap());
+
+        let cli = cli::build_cli();
+        let matches = cli
+            .try_get_matches_from([
+                "policy-server",
+                &policies_flag,
+                "--policies-download-dir=/var/lib/kubewarden",
+                "--client-authorization-path=/does/not/exist.yml",
+                "gc",
+            ])
+            .unwrap();
+
+        assert!(Config::from_args(&matches).is_err());
+        let gc_config = GcConfig::from_args(&matches).unwrap();
+        assert_eq!(
+            PathBuf::from("/var/lib/kubewarden"),
+            gc_config.policies_download_dir
+        );
+        assert!(gc_config.policies.contains_key("example"));
+    }
+
+    #[rstest]
+    #[case::not_set(&[], Some(vec![]))]
+    #[case::increasing(&["--metrics-histogram-buckets=0.5, 1,2.5,10"], Some(vec![0.5, 1.0, 2.5, 10.0]))]
+    #[case::not_a_number(&["--metrics-histogram-buckets=1,ten"], None)]
+    #[case::not_increasing(&["--metrics-histogram-buckets=1,10,5"], None)]
+    #[case::duplicated(&["--metrics-histogram-buckets=1,1"], None)]
+    fn metrics_histogram_buckets(#[case] flags: &[&str], #[case] expected: Option<Vec<f64>>) {
+        let cli = cli::build_cli();
+        let matches = cli
+            .try_get_matches_from(["policy-server"].iter().chain(flags))
+            .unwrap();
+
+        match (metrics_histogram_config(&matches), expected) {
+            (Ok(config), Some(buckets)) => assert_eq!(buckets, config.buckets),
+            (Err(_), None) => {}
+            (Ok(_), None) => panic!("invalid buckets should be rejected"),
+            (Err(e), Some(_)) => panic!("valid buckets should be accepted: {e}"),
+        }
+    }
+
+    #[rstest]
+    #[case::defaults(&[], Some(TracingConfig::default()))]
+    #[case::all_set(
+        &[
+            "--tracing-sampling-ratio=0.1",
+            "--tracing-parent-based-sampling",
+            "--tracing-always-sample-errors",
+            "--cluster-name=production",
+            "--pod-name=policy-server-default-0",
+        ],
+        Some(TracingConfig {
+            sampling_ratio: 0.1,
+            parent_based_sampling: true,
+            always_sample_errors: true,
+            cluster_name: Some("production".to_owned()),
+            pod_name: Some("policy-server-default-0".to_owned()),
+        })
+    )]
+    #[case::ratio_out_of_range(&["--tracing-sampling-ratio=1.5"], None)]
+    #[case::ratio_not_a_number(&["--tracing-sampling-ratio=half"], None)]
+    fn tracing_flags(#[case] flags: &[&str], #[case] expected: Option<TracingConfig>) {
+        let cli = cli::build_cli();
+        let matches = cli
+            .try_get_matches_from(["policy-server"].iter().chain(flags))
+            .unwrap();
+
+        match (tracing_config(&matches), expected) {
+            (Ok(config), Some(expected)) => assert_eq!(expected, config),
+            (Err(_), None) => {}
+            (Ok(_), None) => panic!("invalid flags should be rejected"),
+            (Err(e), Some(_)) => panic!("valid flags should be accepted: {e}"),
+        }
+    }
+
+    #[rstest]
+    #[case::not_set(&[], Some(MetricsLabelsConfig::default()))]
+    #[case::drop_labels(
+        &["--metrics-drop-labels=resource_namespace,error_code"],
+        Some(MetricsLabelsConfig {
+            dropped_labels: BTreeSet::from(["resource_namespace".to_owned(), "error_code".to_owned()]),
+            ..Default::default()
+        })
+    )]
+    #[case::allow_labels(
+        &["--metrics-allow-labels=resource_kind"],
+        Some(MetricsLabelsConfig {
+            dropped_labels: BTreeSet::from([
+                "resource_namespace".to_owned(),
+                "resource_request_operation".to_owned(),
+                "error_code".to_owned(),
+            ]),
+            ..Default::default()
+        })
+    )]
+    #[case::buckets_and_limit(
+        &["--metrics-namespace-buckets=system=kube-*,tenants=tenant-*", "--metrics-max-label-values=100"],
+        Some(MetricsLabelsConfig {
+            namespace_buckets: vec![
+                NamespaceBucket { name: "system".to_owned(), pattern: "kube-*".to_owned() },
+                NamespaceBucket { name: "tenants".to_owned(), pattern: "tenant-*".to_owned() },
+            ],
+            max_label_values: Some(100),
+            ..Default::default()
+        })
+    )]
+    #[case::invalid_bucket(&["--metrics-namespace-buckets=kube-*"], None)]
+    #[case::invalid_limit(&["--metrics-max-label-values=-1"], None)]
+    fn metrics_labels(#[case] flags: &[&str], #[case] expected: Option<MetricsLabelsConfig>) {
+        let cli = cli::build_cli();
+        let matches = cli
+            .try_get_matches_from(["policy-server"].iter().chain(flags))
+            .unwrap();
+
+        match (metrics_labels_config(&matches), expected) {
+            (Ok(config), Some(expected)) => assert_eq!(expected, config),
+            (Err(_), None) => {}
+            (Ok(_), None) => panic!("invalid flags should be rejected"),
+            (Err(e), Some(_)) => panic!("valid flags should be accepted: {e}"),
+        }
+    }
+
+    #[rstest]
+    #[case::defaults(&["--unix-socket=/run/policy-server.sock"], Some((None, None, None, false)))]
+    #[case::mode_and_owner(
+        &["--unix-socket=/run/policy-server.sock", "--unix-socket-mode=0660", "--unix-socket-owner=1000:2000"],
+        Some((Some(0o660), Some(1000), Some(2000), false))
+    )]
+    #[case::owner_only(
+        &["--unix-socket=/run/policy-server.sock", "--unix-socket-owner=1000", "--unix-socket-only"],
+        Some((None, Some(1000), None, true))
+    )]
+    #[case::invalid_mode(&["--unix-socket=/run/policy-server.sock", "--unix-socket-mode=rw"], None)]
+    #[case::invalid_owner(&["--unix-socket=/run/policy-server.sock", "--unix-socket-owner=root"], None)]
+    #[case::exclusive_without_socket(&["--unix-socket-only"], None)]
+    fn unix_socket_flags(
+        #[case] flags: &[&str],
+        #[case] expected: Option<(Option<u32>, Option<u32>, Option<u32>, bool)>,
+    ) {
+        let cli = cli::build_cli();
+        let matches = cli
+            .try_get_matches_from(["policy-server"].iter().chain(flags))
+            .unwrap();
+
+        match (unix_socket(&matches), expected) {
+            (Ok(Some(unix_socket)), Some((mode, owner, group, exclusive))) => {
+                assert_eq!(PathBuf::from("/run/policy-server.sock"), unix_socket.path);
+                assert_eq!(mode, unix_socket.mode);
+                assert_eq!(owner, unix_socket.owner);
+                assert_eq!(group, unix_socket.group);
+                assert_eq!(exclusive, unix_socket.exclusive);
+            }
+            (Err(_), None) => {}
+            (Ok(_), None) => panic!("invalid flags should be rejected"),
+            (_, Some(_)) => panic!("valid flags should be accepted"),
         }
     }
 
@@ -666,6 +1958,54 @@ group_policy:
 "#,
         false
     )]
+    #[case::replace_global_without_requirements(
+        r#"
+---
+example:
+  module: file:///tmp/namespace-validate-policy.wasm
+  verify:
+    replaceGlobal: true
+"#,
+        false
+    )]
+    #[case::group_member_replace_global_without_requirements(
+        r#"
+---
+group_policy:
+  expression: "true"
+  message: "group policy message"
+  policies:
+    policy1:
+      module: file:///tmp/namespace-validate-policy.wasm
+      verify:
+        replaceGlobal: true
+        allOf: []
+"#,
+        false
+    )]
+    #[case::replace_global_with_attestations(
+        r#"
+---
+example:
+  module: registry://ghcr.io/kubewarden/policies/example:v0.1.0
+  verify:
+    replaceGlobal: true
+    attestations:
+      keys:
+        - "public key"
+      sbom: true
+"#,
+        true
+    )]
+    #[case::skip_verification(
+        r#"
+---
+example:
+  module: file:///tmp/namespace-validate-policy.wasm
+  verify: skip
+"#,
+        true
+    )]
     fn policy_validation(#[case] policies_yaml: &str, #[case] is_valid: bool) {
         let policies: HashMap<String, PolicyOrPolicyGroup> =
             serde_yaml::from_str(policies_yaml).unwrap();
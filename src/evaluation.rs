@@ -1,3 +1,4 @@
+mod callback_instrumentation;
 mod evaluation_environment;
 mod policy_evaluation_settings;
 pub(crate) mod precompiled_policy;
@@ -0,0 +1,140 @@
+use policy_evaluator::callback_requests::{CallbackRequest, CallbackRequestType};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+
+use tracing::{Span, info_span};
+
+use crate::metrics;
+
+/// Size of the channel used by each policy to send its callback requests
+const CALLBACK_CHANNEL_SIZE: usize = 100;
+
+/// Return a channel to be used by the given policy to send its callback requests.
+/// The requests are forwarded to the `CallbackHandler`, each one traced by a `callback`
+/// span child of `evaluation_span`. The callback metrics are recorded too, when
+/// `record_metrics` is set.
+///
+/// Must be called from within a tokio runtime.
+pub(crate) fn instrumented_callback_channel(
+    policy_name: String,
+    callback_handler_tx: mpsc::Sender<CallbackRequest>,
+    evaluation_span: Span,
+    record_metrics: bool,
+) -> mpsc::Sender<CallbackRequest> {
+    let (tx, mut rx) = mpsc::channel::<CallbackRequest>(CALLBACK_CHANNEL_SIZE);
+
+    tokio::spawn(async move {
+        while let Some(CallbackRequest {
+            request,
+            response_channel,
+        }) = rx.recv().await
+        {
+            let callback_type = callback_type(&request);
+            let callback_span = info_span!(
+                parent: &evaluation_span,
+                "callback",
+                callback_type,
+                policy_id = policy_name.as_str(),
+                success = tracing::field::Empty,
+            );
+            if record_metrics {
+                metrics::record_callback_queue_depth(
+                    callback_handler_tx.max_capacity() - callback_handler_tx.capacity(),
+                );
+            }
+
+            let (instrumented_response_tx, instrumented_response_rx) = oneshot::channel();
+            let start_time = Instant::now();
+            if callback_handler_tx
+                .send(CallbackRequest {
+                    request,
+                    response_channel: instrumented_response_tx,
+                })
+                .await
+                .is_err()
+            {
+                // The CallbackHandler is gone, the policy gets an error because
+                // `response_channel` is dropped
+                break;
+            }
+
+            let policy_name = policy_name.clone();
+            tokio::spawn(async move {
+                // An error means the CallbackHandler dropped the request without answering,
+                // dropping `response_channel` propagates this to the policy
+                let Ok(response) = instrumented_response_rx.await else {
+                    return;
+                };
+                // The span ends when dropped, once the response is received
+                callback_span.record("success", response.is_ok());
+
+                if record_metrics {
+                    let callback = metrics::Callback {
+                        callback_type,
+                        policy_name,
+                        success: response.is_ok(),
+                    };
+                    metrics::record_callback_latency(start_time.elapsed(), &callback);
+                    metrics::add_callback_request(&callback);
+                }
+
+                // The policy might have been interrupted meanwhile
+                let _ = response_channel.send(response);
+            });
+        }
+    });
+
+    tx
+}
+
+/// The type of callback, like `kubernetes_get_resource`
+fn callback_type(request: &CallbackRequestType) -> &'static str {
+    match request {
+        CallbackRequestType::OciManifestDigest { .. } => "oci_manifest_digest",
+        CallbackRequestType::OciManifest { .. } => "oci_manifest",
+        CallbackRequestType::OciManifestAndConfig { .. } => "oci_manifest_and_config",
+        CallbackRequestType::SigstorePubKeyVerify { .. } => "sigstore_pub_key_verify",
+        CallbackRequestType::SigstoreKeylessVerify { .. } => "sigstore_keyless_verify",
+        CallbackRequestType::SigstoreKeylessPrefixVerify { .. } => "sigstore_keyless_prefix_verify",
+        CallbackRequestType::SigstoreGithubActionsVerify { .. } => "sigstore_github_actions_verify",
+        CallbackRequestType::SigstoreCertificateVerify { .. } => "sigstore_certificate_verify",
+        CallbackRequestType::DNSLookupHost { .. } => "dns_lookup_host",
+        CallbackRequestType::KubernetesListResourceAll { .. } => "kubernetes_list_resource_all",
+        CallbackRequestType::KubernetesListResourceNamespace { .. } => {
+            "kubernetes_list_resource_namespace"
+        }
+        CallbackRequestType::KubernetesGetResource { .. } => "kubernetes_get_resource",
+        CallbackRequestType::KubernetesGetResourcePluralName { .. } => {
+            "kubernetes_get_resource_plural_name"
+        }
+        CallbackRequestType::KubernetesCanI { .. } => "kubernetes_can_i",
+        CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant { .. } => {
+            "has_kubernetes_list_resource_all_result_changed_since_instant"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        CallbackRequestType::DNSLookupHost {
+            host: "kubewarden.io".to_owned(),
+        },
+        "dns_lookup_host"
+    )]
+    #[case(
+        CallbackRequestType::OciManifestDigest {
+            image: "ghcr.io/kubewarden/policy-server:latest".to_owned(),
+        },
+        "oci_manifest_digest"
+    )]
+    fn callback_types(#[case] request: CallbackRequestType, #[case] expected: &str) {
+        assert_eq!(expected, callback_type(&request));
+    }
+}
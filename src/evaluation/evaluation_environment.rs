@@ -1,6 +1,7 @@
 use std::{
     collections::{BTreeSet, HashMap, HashSet},
     sync::Arc,
+    time::Instant,
 };
 
 use policy_evaluator::{
@@ -20,14 +21,16 @@ use policy_evaluator::{
     wasmtime,
 };
 use tokio::sync::mpsc;
-use tracing::debug;
+use tracing::{Span, debug, info_span};
 
 use crate::{
     config::{PolicyOrPolicyGroup, PolicyOrPolicyGroupSettings},
     evaluation::{
+        callback_instrumentation::instrumented_callback_channel,
         policy_evaluation_settings::PolicyEvaluationSettings,
         precompiled_policy::{PrecompiledPolicies, PrecompiledPolicy},
     },
+    metrics,
 };
 
 #[cfg(test)]
@@ -95,6 +98,12 @@ pub(crate) struct EvaluationEnvironment {
     /// asynchronous block
     callback_handler_tx: Option<mpsc::Sender<CallbackRequest>>,
 
+    /// Record metrics about the callbacks issued by the policies
+    callback_metrics: bool,
+
+    /// Map a module digest to the execution mode of the module
+    module_digest_to_execution_mode: HashMap<ModuleDigest, PolicyExecutionMode>,
+
     /// When set, defines after how many seconds a policy evaluation is interrupted.
     global_policy_evaluation_limit_seconds: Option<u64>,
 }
@@ -104,6 +113,7 @@ pub(crate) struct EvaluationEnvironmentBuilder<'engine, 'precompiled_policies> {
     engine: &'engine wasmtime::Engine,
     precompiled_policies: &'precompiled_policies PrecompiledPolicies,
     callback_handler_tx: mpsc::Sender<CallbackRequest>,
+    callback_metrics: bool,
     continue_on_errors: bool,
     global_policy_evaluation_limit_seconds: Option<u64>,
     always_accept_admission_reviews_on_namespace: Option<String>,
@@ -120,6 +130,7 @@ impl<'engine, 'precompiled_policies> EvaluationEnvironmentBuilder<'engine, 'prec
             engine,
             precompiled_policies,
             callback_handler_tx,
+            callback_metrics: false,
             continue_on_errors: false,
             global_policy_evaluation_limit_seconds: None,
             always_accept_admission_reviews_on_namespace: None,
@@ -135,6 +146,12 @@ impl<'engine, 'precompiled_policies> EvaluationEnvironmentBuilder<'engine, 'prec
         self
     }
 
+    /// Record metrics about the callbacks issued by the policies
+    pub fn with_callback_metrics(mut self, callback_metrics: bool) -> Self {
+        self.callback_metrics = callback_metrics;
+        self
+    }
+
     /// Do not fail when a policy initialization error occurs
     pub fn with_continue_on_errors(mut self, continue_on_errors: bool) -> Self {
         self.continue_on_errors = continue_on_errors;
@@ -177,6 +194,7 @@ impl<'engine, 'precompiled_policies> EvaluationEnvironmentBuilder<'engine, 'prec
                 .always_accept_admission_reviews_on_namespace
                 .clone(),
             callback_handler_tx: Some(self.callback_handler_tx.clone()),
+            callback_metrics: self.callback_metrics,
             global_policy_evaluation_limit_seconds: self.global_policy_evaluation_limit_seconds,
             ..Default::default()
         };
@@ -407,6 +425,8 @@ impl EvaluationEnvironment {
 
             self.module_digest_to_policy_evaluator_pre
                 .insert(module_digest.to_owned(), Arc::new(pol_eval_pre));
+            self.module_digest_to_execution_mode
+                .insert(module_digest.to_owned(), precompiled_policy.execution_mode);
         }
 
         self.policy_id_to_module_digest
@@ -477,7 +497,7 @@ impl EvaluationEnvironment {
 
         match &settings.settings {
             PolicyOrPolicyGroupSettings::Policy(settings) => {
-                let mut evaluator = self.rehydrate(policy_id)?;
+                let mut evaluator = self.rehydrate(policy_id, Span::current())?;
                 match evaluator.validate_settings(settings) {
                     SettingsValidationResponse {
                         valid: true,
@@ -512,18 +532,56 @@ impl EvaluationEnvironment {
         Ok(())
     }
 
-    /// Internal method, create a `PolicyEvaluator` by using a pre-initialized instance
-    fn rehydrate(&self, policy_id: &PolicyID) -> Result<PolicyEvaluator> {
-        if self.policy_groups.contains(policy_id) {
-            return Err(EvaluationError::CannotRehydratePolicyGroup(
-                policy_id.to_string(),
-            ));
+    /// The channel to be used by the given policy, or policy group, to send its callback requests.
+    /// When the callbacks are traced or measured, the requests go through a channel that
+    /// instruments them as children of `evaluation_span`
+    fn callback_handler_tx(
+        &self,
+        policy_id: &PolicyID,
+        evaluation_span: Span,
+    ) -> Option<mpsc::Sender<CallbackRequest>> {
+        let callback_handler_tx = self.callback_handler_tx.clone()?;
+        if (evaluation_span.is_disabled() && !self.callback_metrics)
+            || tokio::runtime::Handle::try_current().is_err()
+        {
+            return Some(callback_handler_tx);
         }
 
+        Some(instrumented_callback_channel(
+            policy_id.to_string(),
+            callback_handler_tx,
+            evaluation_span,
+            self.callback_metrics,
+        ))
+    }
+
+    /// Given a policy ID, return the digest of its module and the execution mode of the module
+    fn module_digest_and_execution_mode(
+        &self,
+        policy_id: &PolicyID,
+    ) -> Result<(&ModuleDigest, PolicyExecutionMode)> {
         let module_digest = self
             .policy_id_to_module_digest
             .get(policy_id)
             .ok_or(EvaluationError::PolicyNotFound(policy_id.to_string()))?;
+        let execution_mode = self
+            .module_digest_to_execution_mode
+            .get(module_digest)
+            .ok_or(EvaluationError::PolicyNotFound(policy_id.to_string()))?;
+
+        Ok((module_digest, *execution_mode))
+    }
+
+    /// Internal method, create a `PolicyEvaluator` by using a pre-initialized instance.
+    /// The callback requests issued by the evaluator are traced as children of `evaluation_span`
+    fn rehydrate(&self, policy_id: &PolicyID, evaluation_span: Span) -> Result<PolicyEvaluator> {
+        if self.policy_groups.contains(policy_id) {
+            return Err(EvaluationError::CannotRehydratePolicyGroup(
+                policy_id.to_string(),
+            ));
+        }
+
+        let (module_digest, execution_mode) = self.module_digest_and_execution_mode(policy_id)?;
 
         let policy_settings = self.get_policy_settings(policy_id)?;
 
@@ -543,11 +601,21 @@ impl EvaluationEnvironment {
 
         let eval_ctx = EvaluationContext {
             policy_id: policy_id.to_string(),
-            callback_channel: self.callback_handler_tx.clone(),
+            callback_channel: self.callback_handler_tx(policy_id, evaluation_span),
             ctx_aware_resources_allow_list: ctx_aware_resources_allow_list.clone(),
             epoch_deadline,
         };
 
+        // Entered after creating the callback channel, the callbacks are not part of the
+        // instantiation
+        let _span = info_span!(
+            "policy_instantiation",
+            policy_id = policy_id.to_string().as_str(),
+            module_digest = module_digest.as_str(),
+            execution_mode = ?execution_mode,
+        )
+        .entered();
+
         policy_evaluator_pre.rehydrate(&eval_ctx).map_err(|e| {
             EvaluationError::WebAssemblyError(format!("cannot rehydrate PolicyEvaluatorPre: {e}"))
         })
@@ -585,9 +653,32 @@ impl EvaluationEnvironment {
             PolicyOrPolicyGroupSettings::Policy(settings) => settings,
             _ => unreachable!(),
         };
-        let mut evaluator = self.rehydrate(policy_id)?;
+        let policy_name = policy_id.to_string();
+
+        // The callback requests are issued while the policy is executed: the execution span
+        // is created before the evaluator, so that it can be the parent of the callback spans,
+        // but it's entered only after the instantiation
+        let (module_digest, execution_mode) = self.module_digest_and_execution_mode(policy_id)?;
+        let execution_span = info_span!(
+            "policy_execution",
+            policy_id = policy_name.as_str(),
+            module_digest = module_digest.as_str(),
+            execution_mode = ?execution_mode,
+            allowed = tracing::field::Empty,
+        );
+
+        let start_time = Instant::now();
+        let mut evaluator = self.rehydrate(policy_id, execution_span.clone())?;
+        metrics::record_policy_instantiation_latency(start_time.elapsed(), &policy_name);
+
+        let _enter = execution_span.enter();
 
-        Ok(evaluator.validate(req.clone(), &settings))
+        let start_time = Instant::now();
+        let response = evaluator.validate(req.clone(), &settings);
+        metrics::record_policy_execution_latency(start_time.elapsed(), &policy_name);
+        execution_span.record("allowed", response.allowed);
+
+        Ok(response)
     }
 
     /// Validate a policy group
@@ -599,8 +690,29 @@ impl EvaluationEnvironment {
         policy_id: &PolicyID,
         req: &ValidateRequest,
     ) -> Result<AdmissionResponse> {
+        let group_span = info_span!(
+            "policy_group_evaluation",
+            policy_id = policy_id.to_string().as_str(),
+            expression = tracing::field::Empty,
+            members = tracing::field::Empty,
+            allowed = tracing::field::Empty,
+        );
+        let _enter = group_span.enter();
+        if let PolicyOrPolicyGroupSettings::PolicyGroup {
+            expression,
+            policies,
+            ..
+        } = &self.get_policy_settings(policy_id)?.settings
+        {
+            group_span.record("expression", expression.as_str());
+            group_span.record("members", policies.join(",").as_str());
+        }
+
         let group_evaluator = Arc::new(self.build_policy_group_evaluator(policy_id)?);
-        Ok(group_evaluator.validate(req))
+        let response = group_evaluator.validate(req);
+        group_span.record("allowed", response.allowed);
+
+        Ok(response)
     }
 
     fn build_policy_group_evaluator(&self, policy_id: &PolicyID) -> Result<PolicyGroupEvaluator> {
@@ -617,7 +729,8 @@ impl EvaluationEnvironment {
             &policy_id.to_string(),
             &message,
             &expression,
-            self.callback_handler_tx.clone(),
+            // called from within the group evaluation span
+            self.callback_handler_tx(policy_id, Span::current()),
         );
 
         for sub_policy_name in policies {
@@ -783,6 +896,8 @@ mod tests {
                     context_aware_resources: BTreeSet::new(),
                     message: None,
                     timeout_eval_seconds: None,
+                    verify: None,
+                    debug: false,
                 },
             );
             precompiled_policies.insert(policy_url, Ok(precompiled_policy.clone()));
@@ -799,6 +914,8 @@ mod tests {
                 context_aware_resources: BTreeSet::new(),
                 message: None,
                 timeout_eval_seconds: Some(5),
+                verify: None,
+                debug: false,
             },
         );
 
@@ -814,12 +931,14 @@ mod tests {
                         settings: None,
                         context_aware_resources: BTreeSet::new(),
                         timeout_eval_seconds: None,
+                        verify: None,
                     },
                 )]
                 .into_iter()
                 .collect(),
                 expression: "true || happy_policy_1()".to_string(),
                 message: "something went wrong".to_string(),
+                debug: false,
             },
         );
         policies.insert(
@@ -829,6 +948,7 @@ mod tests {
                 expression: "2 > 1".to_string(),
                 message: "something went wrong".to_string(),
                 policies: HashMap::new(),
+                debug: false,
             },
         );
         policies.insert(
@@ -842,12 +962,14 @@ mod tests {
                         settings: None,
                         context_aware_resources: BTreeSet::new(),
                         timeout_eval_seconds: None,
+                        verify: None,
                     },
                 )]
                 .into_iter()
                 .collect(),
                 expression: "unknown_policy() || happy_policy_1()".to_string(),
                 message: "something went wrong".to_string(),
+                debug: false,
             },
         );
         policies.insert(
@@ -857,6 +979,7 @@ mod tests {
                 expression: "something that doesn't make sense".to_string(),
                 message: "something went wrong".to_string(),
                 policies: HashMap::new(),
+                debug: false,
             },
         );
         policies.insert(
@@ -866,6 +989,7 @@ mod tests {
                 expression: "1 + 1".to_string(),
                 message: "something went wrong".to_string(),
                 policies: HashMap::new(),
+                debug: false,
             },
         );
         policies.insert(
@@ -880,12 +1004,14 @@ mod tests {
                         settings: None,
                         context_aware_resources: BTreeSet::new(),
                         timeout_eval_seconds: None,
+                        verify: None,
                     },
                 )]
                 .into_iter()
                 .collect(),
                 expression: "happy_policy_1() + 1".to_string(),
                 message: "something went wrong".to_string(),
+                debug: false,
             },
         );
         policies.insert(
@@ -900,6 +1026,7 @@ mod tests {
                             settings: None,
                             context_aware_resources: BTreeSet::new(),
                             timeout_eval_seconds: None,
+                            verify: None,
                         },
                     ),
                     (
@@ -909,6 +1036,7 @@ mod tests {
                             settings: None,
                             context_aware_resources: BTreeSet::new(),
                             timeout_eval_seconds: None,
+                            verify: None,
                         },
                     ),
                     (
@@ -918,6 +1046,7 @@ mod tests {
                             settings: None,
                             context_aware_resources: BTreeSet::new(),
                             timeout_eval_seconds: None,
+                            verify: None,
                         },
                     ),
                 ]
@@ -926,6 +1055,7 @@ mod tests {
                 expression: "unhappy_policy_1() || (happy_policy_1() && unhappy_policy_2())"
                     .to_string(),
                 message: "something went wrong".to_string(),
+                debug: false,
             },
         );
 
@@ -941,6 +1071,7 @@ mod tests {
                             settings: None,
                             context_aware_resources: BTreeSet::new(),
                             timeout_eval_seconds: None,
+                            verify: None,
                         },
                     ),
                     (
@@ -950,6 +1081,7 @@ mod tests {
                             settings: None,
                             context_aware_resources: BTreeSet::new(),
                             timeout_eval_seconds: None,
+                            verify: None,
                         },
                     ),
                     (
@@ -959,6 +1091,7 @@ mod tests {
                             settings: None,
                             context_aware_resources: BTreeSet::new(),
                             timeout_eval_seconds: None,
+                            verify: None,
                         },
                     ),
                 ]
@@ -967,6 +1100,7 @@ mod tests {
                 expression: "unhappy_policy_1() || happy_policy_1() || unhappy_policy_2()"
                     .to_string(),
                 message: "something went wrong".to_string(),
+                debug: false,
             },
         );
 
@@ -0,0 +1,300 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use tracing::{debug, info, warn};
+
+use crate::config::PolicyOrPolicyGroup;
+
+/// The top-level directories created by `policy_fetcher::PullDestination::Store`
+/// inside of the download directory. One directory per supported URL scheme.
+///
+/// Only these directories are inspected by the garbage collector. This ensures files
+/// that have not been created by policy-server are never touched, even when the
+/// download directory is shared with something else (like the current working directory).
+const STORE_SCHEMES: [&str; 3] = ["registry", "https", "http"];
+
+/// Remove the WebAssembly modules stored inside of `download_dir` that are no longer
+/// referenced by any of the `policies` and that have not been modified for at least `min_age`.
+///
+/// When `dry_run` is set, nothing is removed from the disk.
+///
+/// Returns the list of modules that have been removed (or that would have been removed
+/// when running in dry-run mode).
+pub fn collect_garbage(
+    download_dir: &Path,
+    policies: &HashMap<String, PolicyOrPolicyGroup>,
+    min_age: Duration,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>> {
+    let referenced_modules: HashSet<String> = policies
+        .values()
+        .flat_map(|policy| policy.modules())
+        .map(normalize_module_url)
+        .collect();
+
+    info!(
+        download_dir = download_dir.to_str(),
+        referenced_modules = referenced_modules.len(),
+        min_age_seconds = min_age.as_secs(),
+        dry_run,
+        status = "init",
+        "policies garbage collection"
+    );
+
+    let mut removed_modules = Vec::new();
+    for scheme in STORE_SCHEMES {
+        let scheme_dir = download_dir.join(scheme);
+        if !scheme_dir.is_dir() {
+            continue;
+        }
+
+        collect_garbage_in_dir(
+            download_dir,
+            &scheme_dir,
+            &referenced_modules,
+            min_age,
+            dry_run,
+            &mut removed_modules,
+        )?;
+    }
+
+    info!(
+        removed_modules = removed_modules.len(),
+        dry_run,
+        status = "done",
+        "policies garbage collection"
+    );
+
+    Ok(removed_modules)
+}
+
+/// Walk `dir` recursively and remove all the stale modules. Directories left empty
+/// (including `dir` itself) are removed too, unless running in dry-run mode.
+fn collect_garbage_in_dir(
+    download_dir: &Path,
+    dir: &Path,
+    referenced_modules: &HashSet<String>,
+    min_age: Duration,
+    dry_run: bool,
+    removed_modules: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| anyhow!("cannot read directory {:?}: {}", dir, e))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| anyhow!("cannot read directory {:?}: {}", dir, e))?;
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .map_err(|e| anyhow!("cannot read file type of {:?}: {}", path, e))?;
+
+        if file_type.is_dir() {
+            collect_garbage_in_dir(
+                download_dir,
+                &path,
+                referenced_modules,
+                min_age,
+                dry_run,
+                removed_modules,
+            )?;
+            continue;
+        }
+
+        if !file_type.is_file() {
+            continue;
+        }
+
+        let module_url = match module_url_from_path(download_dir, &path) {
+            Some(url) => url,
+            None => continue,
+        };
+        if referenced_modules.contains(&module_url) {
+            debug!(module = module_url.as_str(), "module still referenced");
+            continue;
+        }
+
+        let age = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok());
+        match age {
+            Some(age) if age >= min_age => {}
+            _ => {
+                debug!(
+                    module = module_url.as_str(),
+                    "module not referenced, but too recent to be removed"
+                );
+                continue;
+            }
+        }
+
+        if dry_run {
+            info!(
+                module = module_url.as_str(),
+                path = path.to_str(),
+                "unreferenced module would be removed (dry-run)"
+            );
+        } else {
+            info!(
+                module = module_url.as_str(),
+                path = path.to_str(),
+                "removing unreferenced module"
+            );
+            if let Err(e) = fs::remove_file(&path) {
+                warn!(path = path.to_str(), error = ?e, "cannot remove module");
+                continue;
+            }
+        }
+        removed_modules.push(path);
+    }
+
+    if !dry_run && fs::read_dir(dir)?.next().is_none() {
+        debug!(path = dir.to_str(), "removing empty directory");
+        fs::remove_dir(dir).map_err(|e| anyhow!("cannot remove directory {:?}: {}", dir, e))?;
+    }
+
+    Ok(())
+}
+
+/// Policies can reference modules hosted on OCI registries without specifying
+/// the `registry://` scheme. Ensure all the URLs use the same format used to
+/// store them inside of the download directory.
+fn normalize_module_url(url: &str) -> String {
+    if url.contains("://") {
+        url.to_owned()
+    } else {
+        format!("registry://{url}")
+    }
+}
+
+/// Compute the URL of the module stored at `path`.
+///
+/// The download directory has the following layout: `<scheme>/<host>/<path>`.
+/// For example, `registry://ghcr.io/kubewarden/policies/psp-capabilities:v0.1.3` is stored
+/// under `registry/ghcr.io/kubewarden/policies/psp-capabilities:v0.1.3`.
+fn module_url_from_path(download_dir: &Path, path: &Path) -> Option<String> {
+    let relative_path = path.strip_prefix(download_dir).ok()?;
+    let mut components = relative_path
+        .components()
+        .map(|component| component.as_os_str().to_str());
+
+    let scheme = components.next()??;
+    let location = components.collect::<Option<Vec<&str>>>()?.join("/");
+
+    Some(format!("{scheme}://{location}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+    use tempfile::TempDir;
+
+    const REFERENCED_MODULE: &str = "registry/ghcr.io/kubewarden/policies/referenced:v0.1.0";
+    const STALE_MODULE: &str = "registry/ghcr.io/kubewarden/policies/stale:v0.1.0";
+    const STALE_HTTPS_MODULE: &str = "https/example.com/policies/stale.wasm";
+    const UNRELATED_FILE: &str = "unrelated.txt";
+
+    fn build_download_dir() -> TempDir {
+        let download_dir = TempDir::new().expect("cannot create temp dir");
+        for file in [
+            REFERENCED_MODULE,
+            STALE_MODULE,
+            STALE_HTTPS_MODULE,
+            UNRELATED_FILE,
+        ] {
+            let path = download_dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, b"\0asm").unwrap();
+        }
+        download_dir
+    }
+
+    fn build_policies() -> HashMap<String, PolicyOrPolicyGroup> {
+        serde_yaml::from_str(
+            r#"
+referenced:
+  module: ghcr.io/kubewarden/policies/referenced:v0.1.0
+local:
+  module: file:///tmp/namespace-validate-policy.wasm
+"#,
+        )
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case::remove(false)]
+    #[case::dry_run(true)]
+    fn remove_unreferenced_modules(#[case] dry_run: bool) {
+        let download_dir = build_download_dir();
+
+        let removed_modules = collect_garbage(
+            download_dir.path(),
+            &build_policies(),
+            Duration::ZERO,
+            dry_run,
+        )
+        .unwrap();
+
+        let removed_modules: HashSet<PathBuf> = removed_modules.into_iter().collect();
+        assert_eq!(
+            removed_modules,
+            HashSet::from([
+                download_dir.path().join(STALE_MODULE),
+                download_dir.path().join(STALE_HTTPS_MODULE),
+            ])
+        );
+
+        assert!(download_dir.path().join(REFERENCED_MODULE).exists());
+        assert!(download_dir.path().join(UNRELATED_FILE).exists());
+        assert_eq!(
+            dry_run,
+            download_dir.path().join(STALE_MODULE).exists(),
+            "stale module should be removed only when not running in dry-run mode"
+        );
+        assert_eq!(
+            dry_run,
+            download_dir.path().join("https").exists(),
+            "empty directories should be removed only when not running in dry-run mode"
+        );
+    }
+
+    #[test]
+    fn keep_recent_modules() {
+        let download_dir = build_download_dir();
+
+        let removed_modules = collect_garbage(
+            download_dir.path(),
+            &build_policies(),
+            Duration::from_secs(3600),
+            false,
+        )
+        .unwrap();
+
+        assert!(removed_modules.is_empty());
+        assert!(download_dir.path().join(STALE_MODULE).exists());
+    }
+
+    #[rstest]
+    #[case::registry(
+        "registry/ghcr.io/kubewarden/policies/psp:v0.1.3",
+        Some("registry://ghcr.io/kubewarden/policies/psp:v0.1.3")
+    )]
+    #[case::https("https/example.com/psp.wasm", Some("https://example.com/psp.wasm"))]
+    #[case::outside_of_download_dir("/somewhere/else", None)]
+    fn module_url(#[case] path: &str, #[case] expected: Option<&str>) {
+        let download_dir = Path::new("/download-dir");
+        let path = download_dir.join(path);
+
+        assert_eq!(
+            expected.map(|url| url.to_owned()),
+            module_url_from_path(download_dir, &path)
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use policy_evaluator::policy_fetcher::verify::config::LatestVerificationConfig;
+use serde::Serialize;
+use tokio::time::{self, MissedTickBehavior};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    config::PolicyOrPolicyGroup,
+    metrics,
+    policy_downloader::{
+        Downloader, FetchedPolicies, contains_layer, is_hosted_on_registry, policies_to_download,
+    },
+};
+
+/// The outcome of the integrity check of a policy module
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum IntegrityCheckResult {
+    /// The module has not been checked yet
+    Pending,
+    /// The module still resolves to the manifest digest in use and its signatures are valid
+    Ok,
+    /// The module now resolves to a manifest digest different from the one in use
+    DigestMismatch,
+    /// The signatures of the module no longer satisfy the verification requirements
+    VerificationFailed,
+    /// The check could not be performed, for example because the registry is not reachable
+    Error,
+}
+
+impl IntegrityCheckResult {
+    fn as_str(&self) -> &'static str {
+        match self {
+            IntegrityCheckResult::Pending => "pending",
+            IntegrityCheckResult::Ok => "ok",
+            IntegrityCheckResult::DigestMismatch => "digest_mismatch",
+            IntegrityCheckResult::VerificationFailed => "verification_failed",
+            IntegrityCheckResult::Error => "error",
+        }
+    }
+}
+
+/// The latest integrity status of a policy module
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ModuleIntegrityStatus {
+    /// The policies using the module
+    pub(crate) policies: Vec<String>,
+    /// The manifest digest of the module being used by policy-server
+    pub(crate) expected_digest: Option<String>,
+    /// The manifest digest the module resolved to during the last check
+    pub(crate) current_digest: Option<String>,
+    pub(crate) result: IntegrityCheckResult,
+    /// Details about the failure, if any
+    pub(crate) message: Option<String>,
+    /// Unix timestamp of the last check
+    pub(crate) last_check: Option<u64>,
+}
+
+/// The integrity status of all the monitored modules, indexed by module URL
+pub(crate) type IntegrityStatus = Arc<RwLock<BTreeMap<String, ModuleIntegrityStatus>>>;
+
+/// A module hosted on an OCI registry that is periodically checked
+struct MonitoredModule {
+    url: String,
+    expected_digest: Option<String>,
+    /// The sha256 digest of the module being used
+    sha256: Option<String>,
+    /// The verification configs of the policies using the module, sorted by policy name
+    verification_configs: Vec<(String, LatestVerificationConfig)>,
+}
+
+/// Periodically ensures the policy modules being used have not been tampered with.
+///
+/// For each module hosted on an OCI registry, the checker:
+/// * resolves the manifest digest of the module again, and compares it with the one
+///   obtained when the module was downloaded. A difference means the tag has been moved
+///   to another image (tag drift).
+/// * verifies the signatures of the module again, using the verification config of
+///   each policy using it. A failure means a signature that was trusted at startup is
+///   no longer valid.
+///
+/// Issues are reported via logs, metrics and the status exposed by the `/integrity` endpoint.
+/// The modules being evaluated are never changed by the checker.
+pub(crate) struct IntegrityChecker {
+    downloader: Downloader,
+    modules: Vec<MonitoredModule>,
+    status: IntegrityStatus,
+}
+
+impl IntegrityChecker {
+    pub(crate) fn new(
+        downloader: Downloader,
+        policies: &HashMap<String, PolicyOrPolicyGroup>,
+        verification_config: Option<&LatestVerificationConfig>,
+        fetched_policies: &FetchedPolicies,
+    ) -> Self {
+        let mut modules: BTreeMap<String, MonitoredModule> = BTreeMap::new();
+        let mut policy_names: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        // Attestations are not checked again, they are bound to the manifest digest
+        // and cannot change without the digest changing too
+        let mut policies: Vec<_> = policies_to_download(policies, verification_config, None)
+            .into_iter()
+            .collect();
+        policies.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (name, policy) in policies {
+            // Modules that could not be downloaded are not being evaluated
+            let fetched_module = match fetched_policies.get(&policy.url) {
+                Some(Ok(fetched_module)) => fetched_module,
+                _ => continue,
+            };
+            if !is_hosted_on_registry(&policy.url) {
+                continue;
+            }
+
+            let module = modules
+                .entry(policy.url.clone())
+                .or_insert_with(|| MonitoredModule {
+                    url: policy.url.clone(),
+                    expected_digest: fetched_module.manifest_digest.clone(),
+                    sha256: fetched_module.sha256.clone(),
+                    verification_configs: Vec::new(),
+                });
+            if let Some(verification_config) = policy.verification_config {
+                module
+                    .verification_configs
+                    .push((name.clone(), verification_config));
+            }
+            policy_names.entry(policy.url).or_default().push(name);
+        }
+
+        let status = modules
+            .values()
+            .map(|module| {
+                (
+                    module.url.clone(),
+                    ModuleIntegrityStatus {
+                        policies: policy_names.remove(&module.url).unwrap_or_default(),
+                        expected_digest: module.expected_digest.clone(),
+                        current_digest: None,
+                        result: IntegrityCheckResult::Pending,
+                        message: None,
+                        last_check: None,
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            downloader,
+            modules: modules.into_values().collect(),
+            status: Arc::new(RwLock::new(status)),
+        }
+    }
+
+    /// A handle to the integrity status of the monitored modules
+    pub(crate) fn status(&self) -> IntegrityStatus {
+        self.status.clone()
+    }
+
+    /// Check the integrity of all the monitored modules every `interval`. The first check
+    /// happens once `interval` has elapsed, the modules have just been verified at startup.
+    pub(crate) async fn run(mut self, interval: Duration) {
+        info!(
+            modules = self.modules.len(),
+            interval_seconds = interval.as_secs(),
+            "policy modules integrity check enabled"
+        );
+
+        let mut interval = time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // the first tick completes immediately
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            self.check_modules().await;
+        }
+    }
+
+    async fn check_modules(&mut self) {
+        debug!(status = "init", "policy modules integrity check");
+
+        for module in self.modules.iter() {
+            let (result, current_digest, message) =
+                check_module(&mut self.downloader, module).await;
+
+            match result {
+                IntegrityCheckResult::DigestMismatch => warn!(
+                    module = module.url.as_str(),
+                    expected_digest = module.expected_digest.as_deref(),
+                    current_digest = current_digest.as_deref(),
+                    message = message.as_deref(),
+                    "policy module tag drift detected"
+                ),
+                IntegrityCheckResult::VerificationFailed => error!(
+                    module = module.url.as_str(),
+                    message = message.as_deref(),
+                    "policy module signatures are no longer valid"
+                ),
+                IntegrityCheckResult::Error => warn!(
+                    module = module.url.as_str(),
+                    message = message.as_deref(),
+                    "cannot check policy module integrity"
+                ),
+                _ => debug!(module = module.url.as_str(), "policy module integrity ok"),
+            }
+            metrics::add_policy_module_integrity_check(&module.url, result.as_str());
+
+            let last_check = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .ok();
+            let mut status = self
+                .status
+                .write()
+                .expect("cannot acquire integrity status lock");
+            if let Some(status) = status.get_mut(&module.url) {
+                status.current_digest = current_digest;
+                status.result = result;
+                status.message = message;
+                status.last_check = last_check;
+            }
+        }
+
+        debug!(status = "done", "policy modules integrity check");
+    }
+}
+
+/// Check the integrity of a single module. Returns the result of the check, the manifest
+/// digest the module currently resolves to and the details about the failure, if any.
+async fn check_module(
+    downloader: &mut Downloader,
+    module: &MonitoredModule,
+) -> (IntegrityCheckResult, Option<String>, Option<String>) {
+    let current_digest = match downloader.manifest_digest(&module.url).await {
+        Ok(digest) => digest,
+        Err(e) => return (IntegrityCheckResult::Error, None, Some(e.to_string())),
+    };
+
+    for (policy, verification_config) in module.verification_configs.iter() {
+        match downloader.verify(&module.url, verification_config).await {
+            // The downloader has not been configured to verify modules
+            None => break,
+            Some(Err(e)) => {
+                return (
+                    IntegrityCheckResult::VerificationFailed,
+                    Some(current_digest),
+                    Some(format!("policy '{policy}': {e}")),
+                );
+            }
+            Some(Ok(verified_digest)) if verified_digest != current_digest => {
+                return (
+                    IntegrityCheckResult::DigestMismatch,
+                    Some(current_digest),
+                    Some(format!(
+                        "policy '{policy}': verified manifest digest {verified_digest} differs from the resolved one"
+                    )),
+                );
+            }
+            Some(Ok(_)) => {}
+        }
+    }
+
+    match (&module.expected_digest, &module.sha256) {
+        (Some(expected_digest), _) if *expected_digest != current_digest => (
+            IntegrityCheckResult::DigestMismatch,
+            Some(current_digest),
+            Some("the tag now references a different manifest".to_owned()),
+        ),
+        // The manifest digest of the module in use is not known, compare the module
+        // with the layers of the manifest the tag references
+        (None, Some(sha256)) => {
+            match downloader
+                .manifest_layer_digests(&module.url, &current_digest)
+                .await
+            {
+                Ok(layer_digests) if contains_layer(&layer_digests, sha256) => {
+                    (IntegrityCheckResult::Ok, Some(current_digest), None)
+                }
+                Ok(_) => (
+                    IntegrityCheckResult::DigestMismatch,
+                    Some(current_digest),
+                    Some(
+                        "the tag now references a manifest not containing the module in use"
+                            .to_owned(),
+                    ),
+                ),
+                Err(e) => (
+                    IntegrityCheckResult::Error,
+                    Some(current_digest),
+                    Some(e.to_string()),
+                ),
+            }
+        }
+        _ => (IntegrityCheckResult::Ok, Some(current_digest), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy_downloader::FetchedModule;
+    use anyhow::anyhow;
+    use std::path::PathBuf;
+
+    fn fetched_module(url: &str, manifest_digest: Option<&str>) -> FetchedModule {
+        FetchedModule {
+            url: url.to_owned(),
+            policies: Vec::new(),
+            local_path: PathBuf::from("/tmp/module.wasm"),
+            sha256: None,
+            manifest_digest: manifest_digest.map(|digest| digest.to_owned()),
+            verified_signatures: Vec::new(),
+            verified_attestations: Vec::new(),
+            verified_at: None,
+            metadata: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn monitor_only_downloaded_registry_modules() {
+        let policies: HashMap<String, PolicyOrPolicyGroup> = serde_yaml::from_str(
+            r#"
+pod-privileged:
+  module: registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9
+another-pod-privileged:
+  module: registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9
+local:
+  module: file:///tmp/namespace-validate-policy.wasm
+broken:
+  module: registry://ghcr.io/kubewarden/tests/broken:v0.1.0
+"#,
+        )
+        .unwrap();
+
+        let mut fetched_policies: FetchedPolicies = HashMap::new();
+        fetched_policies.insert(
+            "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9".to_owned(),
+            Ok(fetched_module(
+                "registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9",
+                Some("sha256:1234"),
+            )),
+        );
+        fetched_policies.insert(
+            "file:///tmp/namespace-validate-policy.wasm".to_owned(),
+            Ok(fetched_module(
+                "file:///tmp/namespace-validate-policy.wasm",
+                None,
+            )),
+        );
+        fetched_policies.insert(
+            "registry://ghcr.io/kubewarden/tests/broken:v0.1.0".to_owned(),
+            Err(anyhow!("cannot download")),
+        );
+
+        let downloader = Downloader::new(None, None).await.unwrap();
+        let integrity_checker =
+            IntegrityChecker::new(downloader, &policies, None, &fetched_policies);
+
+        let status = integrity_checker.status();
+        let status = status.read().unwrap();
+        assert_eq!(status.len(), 1);
+
+        let module_status = status
+            .get("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9")
+            .expect("module should be monitored");
+        assert_eq!(
+            module_status.policies,
+            vec!["another-pod-privileged", "pod-privileged"]
+        );
+        assert_eq!(
+            module_status.expected_digest.as_deref(),
+            Some("sha256:1234")
+        );
+        assert_eq!(module_status.result, IntegrityCheckResult::Pending);
+    }
+}
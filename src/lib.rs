@@ -1,6 +1,9 @@
 mod certs;
 mod evaluation;
+mod integrity_check;
+mod pattern;
 mod policy_downloader;
+mod unix_socket;
 
 #[cfg(test)]
 mod test_utils;
@@ -10,6 +13,7 @@ mod cli;
 
 pub mod api;
 pub mod config;
+pub mod gc;
 pub mod metrics;
 pub mod profiling;
 pub mod tracing;
@@ -17,11 +21,11 @@ pub mod tracing;
 use ::tracing::{Level, debug, info, trace, warn};
 use anyhow::{Result, anyhow};
 use axum::{
-    Router,
+    Router, middleware,
     routing::{get, post},
 };
-use axum_server::tls_rustls::RustlsConfig;
-use certs::create_tls_config_and_watch_certificate_changes;
+use axum_server::{Handle, tls_rustls::RustlsConfig};
+use certs::{ClientIdentityAcceptor, create_tls_config_and_watch_certificate_changes};
 use evaluation::EvaluationEnvironmentBuilder;
 use policy_evaluator::{
     callback_handler::{CallbackHandler, CallbackHandlerBuilder},
@@ -31,21 +35,39 @@ use policy_evaluator::{
 };
 use profiling::activate_memory_profiling;
 use rayon::prelude::*;
-use std::{fs, net::SocketAddr, sync::Arc};
+use std::{
+    fs,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 use tokio::{
-    sync::{Notify, Semaphore, oneshot},
+    signal::unix::{SignalKind, signal},
+    sync::{Notify, Semaphore, oneshot, watch},
     time,
 };
 use tower_http::trace::{self, TraceLayer};
 
+use crate::api::authorization::{ClientAuthorizer, authorize_client};
+use crate::api::bearer_token::{
+    BearerTokenAuthenticator, authenticate_bearer_token, watch_bearer_token_files,
+};
 use crate::api::handlers::{
-    audit_handler, pprof_get_cpu, pprof_get_heap, readiness_handler, validate_handler,
-    validate_raw_handler,
+    audit_handler, integrity_handler, log_level_delete_handler, log_level_get_handler,
+    log_level_put_handler, modules_handler, pprof_get_cpu, pprof_get_heap, readiness_handler,
+    validate_handler, validate_raw_handler,
 };
+use crate::api::request_metrics::record_request_latency;
 use crate::api::state::ApiServerState;
 use crate::evaluation::precompiled_policy::{PrecompiledPolicies, PrecompiledPolicy};
-use crate::policy_downloader::{Downloader, FetchedPolicies};
-use config::Config;
+use crate::integrity_check::IntegrityChecker;
+use crate::policy_downloader::{Downloader, FetchedModule, FetchedPolicies};
+use crate::tracing::TraceContextMakeSpan;
+use crate::unix_socket::bind_unix_socket;
+use config::{Config, UnixSocketConfig};
 
 use tikv_jemallocator::Jemalloc;
 
@@ -64,8 +86,35 @@ pub struct PolicyServer {
     callback_handler: CallbackHandler,
     callback_handler_shutdown_channel_tx: oneshot::Sender<()>,
     addr: SocketAddr,
+    unix_socket_listener: Option<UnixSocketListener>,
     tls_config: Option<RustlsConfig>,
+    audit_listener: Option<AuditListener>,
+    pprof_listener: Option<PprofListener>,
     readiness_probe_addr: SocketAddr,
+    integrity_checker: Option<(IntegrityChecker, Duration)>,
+    /// Reported by the readiness probe, cleared when shutting down
+    ready: Arc<AtomicBool>,
+    shutdown_delay: Duration,
+    shutdown_timeout: Duration,
+}
+
+/// The Unix domain socket serving the API to local callers
+struct UnixSocketListener {
+    config: UnixSocketConfig,
+    router: Router,
+}
+
+/// The listener dedicated to the audit endpoint
+struct AuditListener {
+    addr: SocketAddr,
+    tls_config: Option<RustlsConfig>,
+    router: Router,
+}
+
+/// The plain HTTP listener, bound to localhost, dedicated to the pprof and the log level endpoints
+struct PprofListener {
+    addr: SocketAddr,
+    router: Router,
 }
 
 impl PolicyServer {
@@ -128,13 +177,21 @@ impl PolicyServer {
         let callback_sender_channel = callback_handler.sender_channel();
 
         // Download policies
-        let downloader_sigstore_trust_root = if config.verification_config.is_some() {
+        let verification_required = config.verification_config.is_some()
+            || config
+                .policies
+                .values()
+                .any(|policy| policy.has_verification_requirements());
+        let downloader_sigstore_trust_root = if verification_required {
             sigstore_trust_root.clone()
         } else {
             None
         };
         let mut downloader =
-            Downloader::new(config.sources.clone(), downloader_sigstore_trust_root).await?;
+            Downloader::new(config.sources.clone(), downloader_sigstore_trust_root)
+                .await?
+                .with_manifest_digest_resolution(config.policies_integrity_check_interval.is_some())
+                .with_attestation_requirements(config.attestation_requirements.clone());
 
         let fetched_policies = downloader
             .download_policies(
@@ -144,6 +201,53 @@ impl PolicyServer {
             )
             .await;
 
+        // Keep track of the modules that have been fetched, for auditing purposes
+        let mut fetched_modules: Vec<FetchedModule> = fetched_policies
+            .values()
+            .filter_map(|fetched_policy| fetched_policy.as_ref().ok())
+            .cloned()
+            .collect();
+        fetched_modules.sort_by(|a, b| a.url.cmp(&b.url));
+        for fetched_module in fetched_modules.iter() {
+            metrics::record_policy_module_info(&metrics::PolicyModuleInfo {
+                module: fetched_module.url.clone(),
+                sha256: fetched_module.sha256.clone(),
+                manifest_digest: fetched_module.manifest_digest.clone(),
+                verified: fetched_module.is_verified(),
+                verified_by: fetched_module
+                    .verified_signatures
+                    .iter()
+                    .map(policy_downloader::signer)
+                    .collect(),
+                mutating: fetched_module
+                    .metadata
+                    .as_ref()
+                    .map(|metadata| metadata.mutating),
+            });
+        }
+
+        if let Some(policies_gc) = &config.policies_gc
+            && let Err(e) = gc::collect_garbage(
+                &config.policies_download_dir,
+                &config.policies,
+                policies_gc.min_age,
+                policies_gc.dry_run,
+            )
+        {
+            // Not being able to reclaim disk space must not prevent the server from starting
+            warn!(error = ?e, "cannot garbage collect the policies download directory");
+        }
+
+        let integrity_checker = config.policies_integrity_check_interval.map(|interval| {
+            let integrity_checker = IntegrityChecker::new(
+                downloader,
+                &config.policies,
+                config.verification_config.as_ref(),
+                &fetched_policies,
+            );
+            (integrity_checker, interval)
+        });
+
         let mut wasmtime_config = wasmtime::Config::new();
 
         let any_policy_has_timeout = config.policies.values().any(|policy| match policy {
@@ -175,7 +279,8 @@ impl PolicyServer {
             &precompiled_policies,
             callback_sender_channel.clone(),
         )
-        .with_continue_on_errors(config.continue_on_errors);
+        .with_continue_on_errors(config.continue_on_errors)
+        .with_callback_metrics(config.metrics_enabled);
         if let Some(namespace) = config.always_accept_admission_reviews_on_namespace {
             evaluation_environment_builder = evaluation_environment_builder
                 .with_always_accept_admission_reviews_on_namespace(namespace);
@@ -204,11 +309,22 @@ impl PolicyServer {
             info!("policy timeout protection is disabled");
         }
 
+        let evaluation_environment = Arc::new(evaluation_environment);
         let state = Arc::new(ApiServerState {
             semaphore: Semaphore::new(config.pool_size),
-            evaluation_environment: Arc::new(evaluation_environment),
+            evaluation_environment: evaluation_environment.clone(),
         });
 
+        let audit_tls_config = match (&config.audit_listener, &config.tls_config) {
+            (Some(audit_listener), Some(tls_config)) => {
+                let mut audit_tls_config = tls_config.clone();
+                if !audit_listener.client_ca_file.is_empty() {
+                    audit_tls_config.client_ca_file = audit_listener.client_ca_file.clone();
+                }
+                Some(create_tls_config_and_watch_certificate_changes(audit_tls_config).await?)
+            }
+            _ => None,
+        };
         let tls_config = if let Some(tls_config) = config.tls_config {
             Some(create_tls_config_and_watch_certificate_changes(tls_config).await?)
         } else {
@@ -216,26 +332,119 @@ impl PolicyServer {
         };
 
         let mut router = Router::new()
-            .route("/audit/{policy_id}", post(audit_handler))
             .route("/validate/{policy_id}", post(validate_handler))
             .route("/validate_raw/{policy_id}", post(validate_raw_handler))
-            .with_state(state.clone())
-            .layer(
-                TraceLayer::new_for_http()
-                    .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
-                    .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
-            );
+            .with_state(state.clone());
+
+        // The audit endpoint gets its own workers when served by a dedicated listener
+        let audit_state = match &config.audit_listener {
+            Some(audit_listener) => Arc::new(ApiServerState {
+                semaphore: Semaphore::new(audit_listener.pool_size),
+                evaluation_environment,
+            }),
+            None => state.clone(),
+        };
+        let audit_router = Router::new()
+            .route("/audit/{policy_id}", post(audit_handler))
+            .with_state(audit_state);
+        let mut audit_router = if config.audit_listener.is_some() {
+            Some(audit_router.layer(api_trace_layer()))
+        } else {
+            router = router.merge(audit_router);
+            None
+        };
+        router = router.layer(api_trace_layer());
 
+        let mut pprof_router = None;
         if config.enable_pprof {
             activate_memory_profiling().await?;
 
-            let pprof_router = Router::new()
+            let router_with_pprof = Router::new()
                 .route("/debug/pprof/cpu", get(pprof_get_cpu))
                 .route("/debug/pprof/heap", get(pprof_get_heap));
-            router = Router::new().merge(router).merge(pprof_router);
+            if config.pprof_addr.is_some() {
+                pprof_router = Some(router_with_pprof);
+            } else {
+                router = Router::new().merge(router).merge(router_with_pprof);
+            }
+        }
+
+        // Served on the API port only when its clients are authenticated, the configuration
+        // requires pprof-port otherwise
+        if config.enable_log_level_endpoint {
+            let log_level_router = Router::new().route(
+                "/debug/log_level",
+                get(log_level_get_handler)
+                    .put(log_level_put_handler)
+                    .delete(log_level_delete_handler),
+            );
+            if config.pprof_addr.is_some() {
+                pprof_router = Some(pprof_router.unwrap_or_default().merge(log_level_router));
+            } else {
+                router = router.merge(log_level_router);
+            }
+        }
+
+        // Local callers are not authenticated, access to the Unix socket is controlled
+        // by the permissions of the socket file. They don't present a certificate nor
+        // a token, the client authorization and the bearer token layers would reject them
+        let mut unix_socket_router = config.unix_socket.as_ref().map(|_| router.clone());
+
+        if let Some(client_authorization) = &config.client_authorization {
+            let debug_policies = config
+                .policies
+                .iter()
+                .filter(|(_, policy)| policy.debug())
+                .map(|(name, _)| name.clone())
+                .collect();
+            let authorizer = Arc::new(ClientAuthorizer::new(client_authorization, debug_policies)?);
+            router = router.route_layer(middleware::from_fn_with_state(
+                authorizer.clone(),
+                authorize_client,
+            ));
+            audit_router = audit_router.map(|audit_router| {
+                audit_router
+                    .route_layer(middleware::from_fn_with_state(authorizer, authorize_client))
+            });
         }
 
-        let readiness_probe_router = Router::new().route("/readiness", get(readiness_handler));
+        if let Some(bearer_token_auth) = config.bearer_token_auth {
+            let authenticator = Arc::new(BearerTokenAuthenticator::new(bearer_token_auth).await?);
+            watch_bearer_token_files(authenticator.clone()).await?;
+            router = router.route_layer(middleware::from_fn_with_state(
+                authenticator.clone(),
+                authenticate_bearer_token,
+            ));
+            audit_router = audit_router.map(|audit_router| {
+                audit_router.route_layer(middleware::from_fn_with_state(
+                    authenticator,
+                    authenticate_bearer_token,
+                ))
+            });
+        }
+
+        // Outermost layer, the latency includes the authentication and authorization
+        router = router.layer(middleware::from_fn(record_request_latency));
+        unix_socket_router = unix_socket_router.map(|unix_socket_router| {
+            unix_socket_router.layer(middleware::from_fn(record_request_latency))
+        });
+        audit_router = audit_router
+            .map(|audit_router| audit_router.layer(middleware::from_fn(record_request_latency)));
+
+        let modules_router = Router::new()
+            .route("/modules", get(modules_handler))
+            .with_state(Arc::new(fetched_modules));
+        let ready = Arc::new(AtomicBool::new(true));
+        let mut readiness_probe_router = Router::new()
+            .route("/readiness", get(readiness_handler))
+            .with_state(ready.clone())
+            .merge(modules_router);
+        if let Some((integrity_checker, _)) = &integrity_checker {
+            let integrity_router = Router::new()
+                .route("/integrity", get(integrity_handler))
+                .with_state(integrity_checker.status());
+            readiness_probe_router = readiness_probe_router.merge(integrity_router);
+        }
 
         Ok(Self {
             router,
@@ -243,8 +452,27 @@ impl PolicyServer {
             callback_handler,
             callback_handler_shutdown_channel_tx,
             addr: config.addr,
+            unix_socket_listener: config
+                .unix_socket
+                .zip(unix_socket_router)
+                .map(|(config, router)| UnixSocketListener { config, router }),
             tls_config,
+            audit_listener: config.audit_listener.zip(audit_router).map(
+                |(audit_listener, router)| AuditListener {
+                    addr: audit_listener.addr,
+                    tls_config: audit_tls_config,
+                    router,
+                },
+            ),
+            pprof_listener: config
+                .pprof_addr
+                .zip(pprof_router)
+                .map(|(addr, router)| PprofListener { addr, router }),
             readiness_probe_addr: config.readiness_probe_addr,
+            integrity_checker,
+            ready,
+            shutdown_delay: config.shutdown_delay,
+            shutdown_timeout: config.shutdown_timeout,
         })
     }
 
@@ -258,29 +486,152 @@ impl PolicyServer {
             info!(status = "exit", "CallbackHandler task");
         });
 
+        if let Some((integrity_checker, interval)) = self.integrity_checker {
+            tokio::spawn(integrity_checker.run(interval));
+        }
+
+        // Stops the API listeners, draining the in-flight requests
+        let handle = Handle::new();
+        // Stops the readiness probe listener once the API listeners are done
+        let readiness_probe_handle = Handle::new();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let termination_signal = termination_signal()?;
+        tokio::spawn({
+            let handle = handle.clone();
+            let ready = self.ready.clone();
+            let shutdown_delay = self.shutdown_delay;
+            let shutdown_timeout = self.shutdown_timeout;
+            async move {
+                termination_signal.await;
+
+                info!(
+                    delay_seconds = shutdown_delay.as_secs(),
+                    "termination signal received, reporting not ready"
+                );
+                ready.store(false, Ordering::Relaxed);
+                time::sleep(shutdown_delay).await;
+
+                info!(
+                    timeout_seconds = shutdown_timeout.as_secs(),
+                    "draining in-flight requests"
+                );
+                handle.graceful_shutdown(Some(shutdown_timeout));
+                // The receivers are gone when no listener needs it
+                let _ = shutdown_tx.send(true);
+            }
+        });
+
+        let serve_tcp = self
+            .unix_socket_listener
+            .as_ref()
+            .is_none_or(|unix_socket_listener| !unix_socket_listener.config.exclusive);
+
         let api_server = async {
+            if !serve_tcp {
+                return Ok(());
+            }
+
             if let Some(tls_config) = self.tls_config {
-                let server_with_tls = axum_server::bind_rustls(self.addr, tls_config);
+                let server_with_tls = axum_server::bind(self.addr)
+                    .acceptor(ClientIdentityAcceptor::new(tls_config))
+                    .handle(handle.clone());
                 notify.notify_one();
 
-                server_with_tls.serve(self.router.into_make_service()).await
+                server_with_tls
+                    .serve(self.router.into_make_service())
+                    .await?;
             } else {
-                let server = axum_server::bind(self.addr);
+                let server = axum_server::bind(self.addr).handle(handle.clone());
+                notify.notify_one();
+
+                server.serve(self.router.into_make_service()).await?;
+            }
+            Ok::<(), anyhow::Error>(())
+        };
+
+        let unix_socket_server = async {
+            if let Some(UnixSocketListener {
+                config: unix_socket,
+                router,
+            }) = self.unix_socket_listener
+            {
+                let listener = bind_unix_socket(&unix_socket)?;
+                info!(path = %unix_socket.path.display(), "Serving the API on the Unix socket");
                 notify.notify_one();
 
-                server.serve(self.router.into_make_service()).await
+                let server = axum::serve(listener, router)
+                    .with_graceful_shutdown(wait_for_shutdown(shutdown_rx.clone()));
+                // axum waits for all the connections to be closed, enforce the drain deadline
+                let drain_deadline = async {
+                    wait_for_shutdown(shutdown_rx.clone()).await;
+                    time::sleep(self.shutdown_timeout).await;
+                };
+                tokio::select! {
+                    result = server.into_future() => result?,
+                    _ = drain_deadline => {
+                        warn!("closing the Unix socket connections that are still in-flight");
+                    }
+                }
+            }
+            Ok::<(), anyhow::Error>(())
+        };
+
+        let audit_server = async {
+            match self.audit_listener {
+                Some(AuditListener {
+                    addr,
+                    tls_config: Some(tls_config),
+                    router,
+                }) => {
+                    axum_server::bind(addr)
+                        .acceptor(ClientIdentityAcceptor::new(tls_config))
+                        .handle(handle.clone())
+                        .serve(router.into_make_service())
+                        .await?;
+                }
+                Some(AuditListener {
+                    addr,
+                    tls_config: None,
+                    router,
+                }) => {
+                    axum_server::bind(addr)
+                        .handle(handle.clone())
+                        .serve(router.into_make_service())
+                        .await?;
+                }
+                None => {}
+            }
+            Ok::<(), anyhow::Error>(())
+        };
+
+        let pprof_server = async {
+            if let Some(PprofListener { addr, router }) = self.pprof_listener {
+                axum_server::bind(addr)
+                    .handle(handle.clone())
+                    .serve(router.into_make_service())
+                    .await?;
             }
+            Ok::<(), anyhow::Error>(())
+        };
+
+        let api_servers = async {
+            tokio::try_join!(api_server, unix_socket_server, audit_server, pprof_server)?;
+            readiness_probe_handle.shutdown();
+            Ok::<(), anyhow::Error>(())
         };
 
         let readiness_probe_server = async {
             notify.notified().await;
 
             axum_server::bind(self.readiness_probe_addr)
+                .handle(readiness_probe_handle.clone())
                 .serve(self.readiness_probe_router.into_make_service())
-                .await
+                .await?;
+            Ok::<(), anyhow::Error>(())
         };
 
-        tokio::try_join!(api_server, readiness_probe_server)?;
+        tokio::try_join!(api_servers, readiness_probe_server)?;
 
         self.callback_handler_shutdown_channel_tx
             .send(())
@@ -288,6 +639,7 @@ impl PolicyServer {
         callback_handler
             .await
             .expect("Cannot wait for CallbackHandler to exit");
+        info!("shutdown completed");
 
         Ok(())
     }
@@ -297,6 +649,34 @@ impl PolicyServer {
     }
 }
 
+/// Resolves on the first SIGTERM or SIGINT received
+fn termination_signal() -> Result<impl Future<Output = ()>> {
+    let mut sigterm = signal(SignalKind::terminate())
+        .map_err(|e| anyhow!("cannot install SIGTERM handler: {}", e))?;
+    let mut sigint = signal(SignalKind::interrupt())
+        .map_err(|e| anyhow!("cannot install SIGINT handler: {}", e))?;
+
+    Ok(async move {
+        tokio::select! {
+            _ = sigterm.recv() => {}
+            _ = sigint.recv() => {}
+        }
+    })
+}
+
+async fn wait_for_shutdown(mut shutdown_rx: watch::Receiver<bool>) {
+    // An error means the shutdown can no longer be requested
+    if shutdown_rx.wait_for(|shutdown| *shutdown).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+fn api_trace_layer() -> TraceLayer<trace::HttpMakeClassifier, TraceContextMakeSpan> {
+    TraceLayer::new_for_http()
+        .make_span_with(TraceContextMakeSpan::new(Level::INFO))
+        .on_response(trace::DefaultOnResponse::new().level(Level::INFO))
+}
+
 fn precompile_policies(
     engine: &wasmtime::Engine,
     fetched_policies: &FetchedPolicies,
@@ -310,7 +690,7 @@ fn precompile_policies(
         .par_iter()
         .map(|(policy_url, fetched_policy)| match fetched_policy {
             Ok(policy) => {
-                let precompiled_policy = PrecompiledPolicy::new(engine, policy);
+                let precompiled_policy = PrecompiledPolicy::new(engine, &policy.local_path);
                 debug!(?policy_url, "module compiled");
                 (policy_url.clone(), precompiled_policy)
             }
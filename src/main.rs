@@ -2,14 +2,16 @@ mod cli;
 
 use std::fs;
 use std::io::prelude::*;
+use std::time::Duration;
 
 use ::tracing::info;
 use anyhow::Result;
 use anyhow::anyhow;
 use clap::ArgMatches;
 use policy_server::PolicyServer;
+use policy_server::config;
 use policy_server::metrics::setup_metrics;
-use policy_server::tracing::setup_tracing;
+use policy_server::tracing::{setup_logging, setup_tracing};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -23,13 +25,28 @@ async fn main() -> Result<()> {
     if matches.subcommand_name() == Some("docs") {
         return run_docs_subcommand(matches.subcommand_matches("docs"));
     }
+    if matches.subcommand_name() == Some("gc") {
+        return run_gc_subcommand(&matches);
+    }
 
-    let config = policy_server::config::Config::from_args(&matches)?;
+    let config = config::Config::from_args(&matches)?;
 
-    let tracer_provider = setup_tracing(&config.log_level, &config.log_fmt, config.log_no_color)?;
+    let otlp_providers = setup_tracing(
+        &config.log_level,
+        &config.log_fmt,
+        config.log_no_color,
+        config.log_no_stdout,
+        &config.tracing_config,
+        config.debug_mode_enabled(),
+    )?;
 
-    if config.metrics_enabled {
-        setup_metrics()?;
+    let meter_provider = if config.metrics_enabled {
+        Some(setup_metrics(
+            &config.metrics_histograms,
+            &config.metrics_labels,
+        )?)
+    } else {
+        None
     };
 
     if config.daemon {
@@ -57,8 +74,12 @@ async fn main() -> Result<()> {
     let api_server = PolicyServer::new_from_config(config).await?;
     api_server.run().await?;
 
-    if let Some(trace_provider) = tracer_provider {
-        trace_provider.shutdown()?;
+    // Flush the telemetry data collected while draining the requests
+    if let Some(meter_provider) = meter_provider {
+        meter_provider.shutdown()?;
+    }
+    if let Some(otlp_providers) = otlp_providers {
+        otlp_providers.shutdown()?;
     }
 
     Ok(())
@@ -76,3 +97,39 @@ fn run_docs_subcommand(matches: Option<&ArgMatches>) -> Result<()> {
     }
     Ok(())
 }
+
+/// Handle the gc subcommand: remove the modules of the download directory that are
+/// no longer referenced by the policies file
+fn run_gc_subcommand(matches: &ArgMatches) -> Result<()> {
+    let gc_matches = matches
+        .subcommand_matches("gc")
+        .expect("gc subcommand should have been provided");
+    let min_age = gc_matches
+        .get_one::<String>("min-age")
+        .expect("min-age should always be set")
+        .parse::<u64>()
+        .map_err(|e| anyhow!("error parsing min-age: {}", e))?;
+    let dry_run = gc_matches
+        .get_one::<bool>("dry-run")
+        .expect("clap should have set a default value")
+        .to_owned();
+
+    let config = config::GcConfig::from_args(matches)?;
+    setup_logging(&config.log_level, config.log_no_color)?;
+
+    let removed_modules = policy_server::gc::collect_garbage(
+        &config.policies_download_dir,
+        &config.policies,
+        Duration::from_secs(min_age),
+        dry_run,
+    )?;
+
+    for module in removed_modules {
+        if dry_run {
+            println!("would remove {}", module.display());
+        } else {
+            println!("removed {}", module.display());
+        }
+    }
+    Ok(())
+}
@@ -1,17 +1,67 @@
 use anyhow::Result;
-use opentelemetry::{KeyValue, global};
+use opentelemetry::{KeyValue, global, metrics::Histogram};
 use opentelemetry_otlp::{ExportConfig, WithExportConfig, WithTonicConfig};
+use opentelemetry_sdk::metrics::{Aggregation, Instrument, InstrumentKind, Stream};
+use std::{
+    convert::TryFrom,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 mod policy_evaluations_total;
 pub use policy_evaluations_total::add_policy_evaluation;
 mod policy_evaluations_latency;
 pub use policy_evaluations_latency::record_policy_latency;
+mod policy_evaluation_queue_wait;
+pub use policy_evaluation_queue_wait::record_policy_evaluation_queue_wait;
+mod policy_instantiation_latency;
+pub use policy_instantiation_latency::record_policy_instantiation_latency;
+mod policy_execution_latency;
+pub use policy_execution_latency::record_policy_execution_latency;
+mod http_request_latency;
+pub use http_request_latency::record_http_request_latency;
+mod policy_evaluations_in_flight;
+pub use policy_evaluations_in_flight::add_policy_evaluations_in_flight;
+mod policy_evaluation_permits_available;
+pub use policy_evaluation_permits_available::record_policy_evaluation_permits_available;
+mod callback_requests_total;
+pub use callback_requests_total::add_callback_request;
+mod callback_latency;
+pub use callback_latency::record_callback_latency;
+mod callback_queue_depth;
+pub use callback_queue_depth::record_callback_queue_depth;
+mod policy_module_integrity_checks_total;
+pub use policy_module_integrity_checks_total::add_policy_module_integrity_check;
+mod policy_module_info;
+pub use policy_module_info::record_policy_module_info;
+mod bearer_token_authentications_total;
+pub use bearer_token_authentications_total::add_bearer_token_authentication;
+mod client_authorization_decisions_total;
+pub use client_authorization_decisions_total::add_client_authorization_decision;
+mod tls_client_certificate_rejections_total;
+pub use tls_client_certificate_rejections_total::add_tls_client_certificate_rejection;
+mod tls_certificate_expiration;
+pub use tls_certificate_expiration::record_tls_certificate_expiration;
+mod labels;
 
-use crate::config::build_client_tls_config_from_env;
+use crate::config::{
+    MetricsHistogramConfig, MetricsLabelsConfig, build_client_tls_config_from_env,
+};
 
 const METER_NAME: &str = "kubewarden";
 
-pub fn setup_metrics() -> Result<()> {
+// Set once by `setup_metrics`, before any latency histogram is created
+static LATENCY_SECONDS: AtomicBool = AtomicBool::new(false);
+
+// The function returns the meter provider, it must be shut down before the
+// program exits to flush the pending metrics
+pub fn setup_metrics(
+    histograms: &MetricsHistogramConfig,
+    labels: &MetricsLabelsConfig,
+) -> Result<opentelemetry_sdk::metrics::SdkMeterProvider> {
+    LATENCY_SECONDS.store(histograms.latency_seconds, Ordering::Relaxed);
+    labels::set_labels_filter(labels::LabelsFilter::new(labels)?);
+
     let metric_exporter = opentelemetry_otlp::MetricExporter::builder()
         .with_tonic()
         .with_tls_config(build_client_tls_config_from_env("METRICS")?)
@@ -20,12 +70,70 @@ pub fn setup_metrics() -> Result<()> {
 
     let periodic_reader =
         opentelemetry_sdk::metrics::PeriodicReader::builder(metric_exporter).build();
-    let meter_provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
-        .with_reader(periodic_reader)
-        .build();
+    let mut meter_provider_builder =
+        opentelemetry_sdk::metrics::SdkMeterProvider::builder().with_reader(periodic_reader);
+    if !histograms.buckets.is_empty() {
+        let boundaries = histograms.buckets.clone();
+        meter_provider_builder =
+            meter_provider_builder.with_view(move |instrument: &Instrument| {
+                latency_histogram_view(instrument, &boundaries)
+            });
+    }
+    let meter_provider = meter_provider_builder.build();
 
-    global::set_meter_provider(meter_provider);
-    Ok(())
+    global::set_meter_provider(meter_provider.clone());
+    Ok(meter_provider)
+}
+
+// Replaces the SDK default boundaries of the latency histograms
+fn latency_histogram_view(instrument: &Instrument, boundaries: &[f64]) -> Option<Stream> {
+    if instrument.kind() != InstrumentKind::Histogram
+        || instrument.scope().name() != METER_NAME
+        || !(instrument.name().ends_with("_milliseconds")
+            || instrument.name().ends_with("_seconds"))
+    {
+        return None;
+    }
+    Stream::builder()
+        .with_aggregation(Aggregation::ExplicitBucketHistogram {
+            boundaries: boundaries.to_vec(),
+            record_min_max: true,
+        })
+        .build()
+        .ok()
+}
+
+/// A histogram recording latencies either as whole milliseconds or, when enabled
+/// with `MetricsHistogramConfig::latency_seconds`, as floating-point seconds
+pub(crate) enum LatencyHistogram {
+    Milliseconds(Histogram<u64>),
+    Seconds(Histogram<f64>),
+}
+
+impl LatencyHistogram {
+    /// Creates the histogram named `<name>_milliseconds` or `<name>_seconds`
+    pub(crate) fn new(name: &str) -> Self {
+        let meter = global::meter(METER_NAME);
+        if LATENCY_SECONDS.load(Ordering::Relaxed) {
+            LatencyHistogram::Seconds(meter.f64_histogram(format!("{name}_seconds")).build())
+        } else {
+            LatencyHistogram::Milliseconds(
+                meter.u64_histogram(format!("{name}_milliseconds")).build(),
+            )
+        }
+    }
+
+    pub(crate) fn record(&self, latency: Duration, attributes: &[KeyValue]) {
+        match self {
+            LatencyHistogram::Milliseconds(histogram) => histogram.record(
+                u64::try_from(latency.as_millis()).unwrap_or(u64::MAX),
+                attributes,
+            ),
+            LatencyHistogram::Seconds(histogram) => {
+                histogram.record(latency.as_secs_f64(), attributes)
+            }
+        }
+    }
 }
 
 pub trait PolicyEvaluationMetric: Into<Vec<KeyValue>> {}
@@ -51,23 +159,33 @@ impl Into<Vec<KeyValue>> for &PolicyEvaluation {
         let mut baggage = vec![
             KeyValue::new("policy_name", self.policy_name.clone()),
             KeyValue::new("policy_mode", self.policy_mode.clone()),
-            KeyValue::new("resource_kind", self.resource_kind.clone()),
-            KeyValue::new(
-                "resource_request_operation",
-                self.resource_request_operation.clone(),
-            ),
             KeyValue::new("accepted", self.accepted),
             KeyValue::new("mutated", self.mutated),
             KeyValue::new("request_origin", self.request_origin.clone()),
         ];
-        if let Some(resource_namespace) = &self.resource_namespace {
-            baggage.append(&mut vec![KeyValue::new(
-                "resource_namespace",
-                resource_namespace.clone(),
-            )]);
+        if let Some(resource_kind) = labels::label_value("resource_kind", &self.resource_kind) {
+            baggage.push(KeyValue::new("resource_kind", resource_kind));
         }
-        if let Some(error_code) = self.error_code {
-            baggage.append(&mut vec![KeyValue::new("error_code", error_code as i64)]);
+        if let Some(resource_request_operation) = labels::label_value(
+            "resource_request_operation",
+            &self.resource_request_operation,
+        ) {
+            baggage.push(KeyValue::new(
+                "resource_request_operation",
+                resource_request_operation,
+            ));
+        }
+        if let Some(resource_namespace) = self
+            .resource_namespace
+            .as_ref()
+            .and_then(|namespace| labels::label_value("resource_namespace", namespace))
+        {
+            baggage.push(KeyValue::new("resource_namespace", resource_namespace));
+        }
+        if let Some(error_code) = self.error_code
+            && !labels::is_label_dropped("error_code")
+        {
+            baggage.push(KeyValue::new("error_code", error_code as i64));
         }
         baggage
     }
@@ -94,7 +212,9 @@ impl Into<Vec<KeyValue>> for &RawPolicyEvaluation {
             KeyValue::new("mutated", self.mutated),
         ];
 
-        if let Some(error_code) = self.error_code {
+        if let Some(error_code) = self.error_code
+            && !labels::is_label_dropped("error_code")
+        {
             baggage.append(&mut vec![KeyValue::new("error_code", error_code as i64)]);
         }
         baggage
@@ -118,3 +238,57 @@ impl Into<Vec<KeyValue>> for &PolicyInitializationError {
         ]
     }
 }
+
+#[derive(Clone)]
+pub struct PolicyModuleInfo {
+    pub module: String,
+    pub sha256: Option<String>,
+    pub manifest_digest: Option<String>,
+    pub verified: bool,
+    /// Who signed the module, see `policy_downloader::signer`
+    pub verified_by: Vec<String>,
+    pub mutating: Option<bool>,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Vec<KeyValue>> for &PolicyModuleInfo {
+    fn into(self) -> Vec<KeyValue> {
+        let mut baggage = vec![
+            KeyValue::new("module", self.module.clone()),
+            KeyValue::new("verified", self.verified),
+        ];
+        if let Some(sha256) = &self.sha256 {
+            baggage.push(KeyValue::new("sha256", sha256.clone()));
+        }
+        if let Some(manifest_digest) = &self.manifest_digest {
+            baggage.push(KeyValue::new("manifest_digest", manifest_digest.clone()));
+        }
+        if !self.verified_by.is_empty() {
+            baggage.push(KeyValue::new("verified_by", self.verified_by.join(",")));
+        }
+        if let Some(mutating) = self.mutating {
+            baggage.push(KeyValue::new("mutating", mutating));
+        }
+        baggage
+    }
+}
+
+#[derive(Clone)]
+pub struct Callback {
+    pub(crate) callback_type: &'static str,
+    /// The policy issuing the callback. The callbacks of the members of a policy group
+    /// are attributed to the group
+    pub(crate) policy_name: String,
+    pub(crate) success: bool,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Vec<KeyValue>> for &Callback {
+    fn into(self) -> Vec<KeyValue> {
+        vec![
+            KeyValue::new("callback_type", self.callback_type),
+            KeyValue::new("policy_name", self.policy_name.clone()),
+            KeyValue::new("success", self.success),
+        ]
+    }
+}
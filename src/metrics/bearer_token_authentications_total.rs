@@ -0,0 +1,19 @@
+use lazy_static::lazy_static;
+use opentelemetry::{KeyValue, metrics::Counter};
+
+lazy_static! {
+    static ref BEARER_TOKEN_AUTHENTICATIONS_TOTAL: Counter<u64> =
+        opentelemetry::global::meter(super::METER_NAME)
+            .u64_counter("kubewarden_bearer_token_authentications_total")
+            .build();
+}
+
+pub fn add_bearer_token_authentication(endpoint: &str, result: &str) {
+    BEARER_TOKEN_AUTHENTICATIONS_TOTAL.add(
+        1,
+        &[
+            KeyValue::new("endpoint", endpoint.to_owned()),
+            KeyValue::new("result", result.to_owned()),
+        ],
+    );
+}
@@ -0,0 +1,14 @@
+use lazy_static::lazy_static;
+use opentelemetry::KeyValue;
+use std::time::Duration;
+
+use crate::metrics::{Callback, LatencyHistogram};
+
+lazy_static! {
+    static ref CALLBACK_LATENCY: LatencyHistogram =
+        LatencyHistogram::new("kubewarden_callback_latency");
+}
+
+pub fn record_callback_latency(latency: Duration, callback: &Callback) {
+    CALLBACK_LATENCY.record(latency, &Into::<Vec<KeyValue>>::into(callback));
+}
@@ -0,0 +1,12 @@
+use lazy_static::lazy_static;
+use opentelemetry::metrics::Gauge;
+
+lazy_static! {
+    static ref CALLBACK_QUEUE_DEPTH: Gauge<u64> = opentelemetry::global::meter(super::METER_NAME)
+        .u64_gauge("kubewarden_callback_queue_depth")
+        .build();
+}
+
+pub fn record_callback_queue_depth(depth: usize) {
+    CALLBACK_QUEUE_DEPTH.record(depth as u64, &[]);
+}
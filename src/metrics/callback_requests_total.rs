@@ -0,0 +1,15 @@
+use lazy_static::lazy_static;
+use opentelemetry::{KeyValue, metrics::Counter};
+
+use crate::metrics::Callback;
+
+lazy_static! {
+    static ref CALLBACK_REQUESTS_TOTAL: Counter<u64> =
+        opentelemetry::global::meter(super::METER_NAME)
+            .u64_counter("kubewarden_callback_requests_total")
+            .build();
+}
+
+pub fn add_callback_request(callback: &Callback) {
+    CALLBACK_REQUESTS_TOTAL.add(1, &Into::<Vec<KeyValue>>::into(callback));
+}
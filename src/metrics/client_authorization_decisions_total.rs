@@ -0,0 +1,19 @@
+use lazy_static::lazy_static;
+use opentelemetry::{KeyValue, metrics::Counter};
+
+lazy_static! {
+    static ref CLIENT_AUTHORIZATION_DECISIONS_TOTAL: Counter<u64> =
+        opentelemetry::global::meter(super::METER_NAME)
+            .u64_counter("kubewarden_client_authorization_decisions_total")
+            .build();
+}
+
+pub fn add_client_authorization_decision(endpoint: &str, allowed: bool) {
+    CLIENT_AUTHORIZATION_DECISIONS_TOTAL.add(
+        1,
+        &[
+            KeyValue::new("endpoint", endpoint.to_owned()),
+            KeyValue::new("allowed", allowed),
+        ],
+    );
+}
@@ -0,0 +1,20 @@
+use lazy_static::lazy_static;
+use opentelemetry::KeyValue;
+use std::time::Duration;
+
+use crate::metrics::LatencyHistogram;
+
+lazy_static! {
+    static ref HTTP_REQUEST_LATENCY: LatencyHistogram =
+        LatencyHistogram::new("kubewarden_http_request_latency");
+}
+
+pub fn record_http_request_latency(latency: Duration, endpoint: &str, status_code: u16) {
+    HTTP_REQUEST_LATENCY.record(
+        latency,
+        &[
+            KeyValue::new("endpoint", endpoint.to_owned()),
+            KeyValue::new("status_code", i64::from(status_code)),
+        ],
+    );
+}
@@ -0,0 +1,175 @@
+use anyhow::Result;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{Mutex, OnceLock},
+};
+use tracing::warn;
+
+use crate::{config::MetricsLabelsConfig, pattern::Pattern};
+
+/// Reported instead of the namespaces not matching any bucket
+const OTHER_NAMESPACES_LABEL_VALUE: &str = "other";
+/// Reported instead of the label values seen once the limit is reached
+const OVERFLOW_LABEL_VALUE: &str = "__overflow__";
+
+static LABELS_FILTER: OnceLock<LabelsFilter> = OnceLock::new();
+
+/// Applies the cardinality limits of `MetricsLabelsConfig` to the optional labels
+/// of the policy evaluation metrics
+pub(crate) struct LabelsFilter {
+    dropped_labels: BTreeSet<String>,
+    namespace_buckets: Vec<(String, Pattern)>,
+    max_label_values: Option<usize>,
+    seen_values: Mutex<HashMap<&'static str, HashSet<String>>>,
+}
+
+impl LabelsFilter {
+    pub(crate) fn new(config: &MetricsLabelsConfig) -> Result<Self> {
+        let namespace_buckets = config
+            .namespace_buckets
+            .iter()
+            .map(|bucket| Ok((bucket.name.clone(), Pattern::new(&bucket.pattern)?)))
+            .collect::<Result<Vec<(String, Pattern)>>>()?;
+
+        Ok(LabelsFilter {
+            dropped_labels: config.dropped_labels.clone(),
+            namespace_buckets,
+            max_label_values: config.max_label_values,
+            seen_values: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub(crate) fn is_dropped(&self, label: &str) -> bool {
+        self.dropped_labels.contains(label)
+    }
+
+    /// The value reported for the given label, `None` when the label is dropped
+    pub(crate) fn value(&self, label: &'static str, value: &str) -> Option<String> {
+        if self.is_dropped(label) {
+            return None;
+        }
+
+        let value = if label == "resource_namespace" && !self.namespace_buckets.is_empty() {
+            self.namespace_buckets
+                .iter()
+                .find(|(_, pattern)| pattern.matches(value))
+                .map_or(OTHER_NAMESPACES_LABEL_VALUE, |(name, _)| name.as_str())
+        } else {
+            value
+        };
+
+        let Some(max_label_values) = self.max_label_values else {
+            return Some(value.to_owned());
+        };
+        let mut seen_values = self
+            .seen_values
+            .lock()
+            .expect("cannot lock the metrics label values");
+        let label_values = seen_values.entry(label).or_default();
+        if label_values.contains(value) {
+            Some(value.to_owned())
+        } else if label_values.len() < max_label_values {
+            label_values.insert(value.to_owned());
+            Some(value.to_owned())
+        } else {
+            Some(OVERFLOW_LABEL_VALUE.to_owned())
+        }
+    }
+}
+
+/// Sets the filter applied to the policy evaluation metrics. The labels are
+/// reported as they are when not set
+pub(crate) fn set_labels_filter(filter: LabelsFilter) {
+    if LABELS_FILTER.set(filter).is_err() {
+        warn!("the metrics labels filter is already set");
+    }
+}
+
+/// The value reported for the given optional label, `None` when the label is dropped
+pub(crate) fn label_value(label: &'static str, value: &str) -> Option<String> {
+    match LABELS_FILTER.get() {
+        Some(filter) => filter.value(label, value),
+        None => Some(value.to_owned()),
+    }
+}
+
+/// Whether the given optional label is dropped
+pub(crate) fn is_label_dropped(label: &str) -> bool {
+    LABELS_FILTER
+        .get()
+        .is_some_and(|filter| filter.is_dropped(label))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NamespaceBucket;
+
+    #[test]
+    fn drop_labels() {
+        let filter = LabelsFilter::new(&MetricsLabelsConfig {
+            dropped_labels: BTreeSet::from(["resource_namespace".to_owned()]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(None, filter.value("resource_namespace", "default"));
+        assert_eq!(Some("Pod".to_owned()), filter.value("resource_kind", "Pod"));
+    }
+
+    #[test]
+    fn bucket_namespaces() {
+        let filter = LabelsFilter::new(&MetricsLabelsConfig {
+            namespace_buckets: vec![
+                NamespaceBucket {
+                    name: "system".to_owned(),
+                    pattern: "kube-*".to_owned(),
+                },
+                NamespaceBucket {
+                    name: "tenants".to_owned(),
+                    pattern: "tenant-*".to_owned(),
+                },
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+
+        for (namespace, expected) in [
+            ("kube-system", "system"),
+            ("tenant-a", "tenants"),
+            ("default", OTHER_NAMESPACES_LABEL_VALUE),
+        ] {
+            assert_eq!(
+                Some(expected.to_owned()),
+                filter.value("resource_namespace", namespace)
+            );
+        }
+    }
+
+    #[test]
+    fn cap_label_values() {
+        let filter = LabelsFilter::new(&MetricsLabelsConfig {
+            max_label_values: Some(2),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let values: Vec<Option<String>> = ["a", "b", "c", "a", "b", "d"]
+            .iter()
+            .map(|namespace| filter.value("resource_namespace", namespace))
+            .collect();
+        assert_eq!(
+            vec![
+                Some("a".to_owned()),
+                Some("b".to_owned()),
+                Some(OVERFLOW_LABEL_VALUE.to_owned()),
+                Some("a".to_owned()),
+                Some("b".to_owned()),
+                Some(OVERFLOW_LABEL_VALUE.to_owned()),
+            ],
+            values
+        );
+        // every label has its own limit
+        assert_eq!(Some("Pod".to_owned()), filter.value("resource_kind", "Pod"));
+    }
+}
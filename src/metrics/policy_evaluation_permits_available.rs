@@ -0,0 +1,16 @@
+use lazy_static::lazy_static;
+use opentelemetry::{KeyValue, metrics::Gauge};
+
+lazy_static! {
+    static ref POLICY_EVALUATION_PERMITS_AVAILABLE: Gauge<u64> =
+        opentelemetry::global::meter(super::METER_NAME)
+            .u64_gauge("kubewarden_policy_evaluation_permits_available")
+            .build();
+}
+
+pub fn record_policy_evaluation_permits_available(permits: usize, request_origin: &str) {
+    POLICY_EVALUATION_PERMITS_AVAILABLE.record(
+        permits as u64,
+        &[KeyValue::new("request_origin", request_origin.to_owned())],
+    );
+}
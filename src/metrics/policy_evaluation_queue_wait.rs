@@ -0,0 +1,17 @@
+use lazy_static::lazy_static;
+use opentelemetry::KeyValue;
+use std::time::Duration;
+
+use crate::metrics::LatencyHistogram;
+
+lazy_static! {
+    static ref POLICY_EVALUATION_QUEUE_WAIT: LatencyHistogram =
+        LatencyHistogram::new("kubewarden_policy_evaluation_queue_wait");
+}
+
+pub fn record_policy_evaluation_queue_wait(wait: Duration, request_origin: &str) {
+    POLICY_EVALUATION_QUEUE_WAIT.record(
+        wait,
+        &[KeyValue::new("request_origin", request_origin.to_owned())],
+    );
+}
@@ -0,0 +1,16 @@
+use lazy_static::lazy_static;
+use opentelemetry::{KeyValue, metrics::UpDownCounter};
+
+lazy_static! {
+    static ref POLICY_EVALUATIONS_IN_FLIGHT: UpDownCounter<i64> =
+        opentelemetry::global::meter(super::METER_NAME)
+            .i64_up_down_counter("kubewarden_policy_evaluations_in_flight")
+            .build();
+}
+
+pub fn add_policy_evaluations_in_flight(delta: i64, request_origin: &str) {
+    POLICY_EVALUATIONS_IN_FLIGHT.add(
+        delta,
+        &[KeyValue::new("request_origin", request_origin.to_owned())],
+    );
+}
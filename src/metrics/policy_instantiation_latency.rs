@@ -0,0 +1,17 @@
+use lazy_static::lazy_static;
+use opentelemetry::KeyValue;
+use std::time::Duration;
+
+use crate::metrics::LatencyHistogram;
+
+lazy_static! {
+    static ref POLICY_INSTANTIATION_LATENCY: LatencyHistogram =
+        LatencyHistogram::new("kubewarden_policy_instantiation_latency");
+}
+
+pub fn record_policy_instantiation_latency(latency: Duration, policy_name: &str) {
+    POLICY_INSTANTIATION_LATENCY.record(
+        latency,
+        &[KeyValue::new("policy_name", policy_name.to_owned())],
+    );
+}
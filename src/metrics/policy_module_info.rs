@@ -0,0 +1,14 @@
+use lazy_static::lazy_static;
+use opentelemetry::{KeyValue, metrics::Gauge};
+
+use crate::metrics::PolicyModuleInfo;
+
+lazy_static! {
+    static ref POLICY_MODULE_INFO: Gauge<u64> = opentelemetry::global::meter(super::METER_NAME)
+        .u64_gauge("kubewarden_policy_module_info")
+        .build();
+}
+
+pub fn record_policy_module_info(policy_module_info: &PolicyModuleInfo) {
+    POLICY_MODULE_INFO.record(1, &Into::<Vec<KeyValue>>::into(policy_module_info));
+}
@@ -0,0 +1,19 @@
+use lazy_static::lazy_static;
+use opentelemetry::{KeyValue, metrics::Counter};
+
+lazy_static! {
+    static ref POLICY_MODULE_INTEGRITY_CHECKS_TOTAL: Counter<u64> =
+        opentelemetry::global::meter(super::METER_NAME)
+            .u64_counter("kubewarden_policy_module_integrity_checks_total")
+            .build();
+}
+
+pub fn add_policy_module_integrity_check(module: &str, result: &str) {
+    POLICY_MODULE_INTEGRITY_CHECKS_TOTAL.add(
+        1,
+        &[
+            KeyValue::new("module", module.to_owned()),
+            KeyValue::new("result", result.to_owned()),
+        ],
+    );
+}
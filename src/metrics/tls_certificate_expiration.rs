@@ -0,0 +1,16 @@
+use lazy_static::lazy_static;
+use opentelemetry::{KeyValue, metrics::Gauge};
+
+lazy_static! {
+    static ref TLS_CERTIFICATE_EXPIRATION: Gauge<u64> =
+        opentelemetry::global::meter(super::METER_NAME)
+            .u64_gauge("kubewarden_tls_certificate_expiration_timestamp_seconds")
+            .build();
+}
+
+pub fn record_tls_certificate_expiration(cert_file: &str, not_after: u64) {
+    TLS_CERTIFICATE_EXPIRATION.record(
+        not_after,
+        &[KeyValue::new("cert_file", cert_file.to_owned())],
+    );
+}
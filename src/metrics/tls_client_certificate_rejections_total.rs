@@ -0,0 +1,13 @@
+use lazy_static::lazy_static;
+use opentelemetry::{KeyValue, metrics::Counter};
+
+lazy_static! {
+    static ref TLS_CLIENT_CERTIFICATE_REJECTIONS_TOTAL: Counter<u64> =
+        opentelemetry::global::meter(super::METER_NAME)
+            .u64_counter("kubewarden_tls_client_certificate_rejections_total")
+            .build();
+}
+
+pub fn add_tls_client_certificate_rejection(reason: &str) {
+    TLS_CLIENT_CERTIFICATE_REJECTIONS_TOTAL.add(1, &[KeyValue::new("reason", reason.to_owned())]);
+}
@@ -0,0 +1,51 @@
+use anyhow::{Result, anyhow};
+use regex::Regex;
+
+/// A pattern where `*` matches any sequence of characters
+#[derive(Clone, Debug)]
+pub(crate) struct Pattern(Regex);
+
+impl Pattern {
+    pub(crate) fn new(pattern: &str) -> Result<Self> {
+        let regex = pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<String>>()
+            .join(".*");
+        Regex::new(&format!("^{regex}$"))
+            .map(Pattern)
+            .map_err(|e| anyhow!("invalid pattern '{pattern}': {e}"))
+    }
+
+    pub(crate) fn matches(&self, value: &str) -> bool {
+        self.0.is_match(value)
+    }
+}
+
+pub(crate) fn compile_patterns(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| Pattern::new(pattern))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("audit-scanner", "audit-scanner", true)]
+    #[case("audit-scanner", "audit-scanner-2", false)]
+    #[case("ci-*", "ci-image-signatures", true)]
+    #[case("ci-*", "privileged-pods", false)]
+    #[case(
+        "spiffe://cluster.local/ns/*/sa/ci",
+        "spiffe://cluster.local/ns/ci/sa/ci",
+        true
+    )]
+    #[case("kube.system", "kube-system", false)]
+    fn pattern_matches(#[case] pattern: &str, #[case] value: &str, #[case] expected: bool) {
+        assert_eq!(expected, Pattern::new(pattern).unwrap().matches(value));
+    }
+}
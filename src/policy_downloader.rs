@@ -1,33 +1,108 @@
+mod attestation;
+mod oci;
+
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap},
     path::{Path, PathBuf},
     sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{Result, anyhow};
 use policy_evaluator::{
+    policy_evaluator::PolicyExecutionMode,
     policy_fetcher,
     policy_fetcher::{
+        registry::Registry,
         sigstore,
         sources::Sources,
-        verify::{Verifier, config::LatestVerificationConfig},
+        verify::{
+            Verifier,
+            config::{LatestVerificationConfig, Signature, Subject},
+        },
     },
     policy_metadata::Metadata,
 };
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use sigstore::trust::sigstore::SigstoreTrustRoot;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+pub(crate) use oci::contains_layer;
 
-use crate::config::PolicyOrPolicyGroup;
+use crate::config::{
+    AttestationRequirements, PolicyOrPolicyGroup, PolicyVerification, VerificationMode,
+};
 
 /// A Map with the `policy.url` as key,
-/// and a `PathBuf` as value. The `PathBuf` points to the location where
-/// the WebAssembly module has been downloaded.
-pub(crate) type FetchedPolicies = HashMap<String, Result<PathBuf>>;
+/// and a `FetchedModule` as value.
+pub(crate) type FetchedPolicies = HashMap<String, Result<FetchedModule>>;
+
+/// A WebAssembly module that has been downloaded, together with the results
+/// of its verification
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FetchedModule {
+    /// The URL of the module
+    pub(crate) url: String,
+    /// The policies using the module
+    pub(crate) policies: Vec<String>,
+    /// The location where the WebAssembly module has been downloaded
+    pub(crate) local_path: PathBuf,
+    /// The sha256 digest of the WebAssembly module
+    pub(crate) sha256: Option<String>,
+    /// The digest of the OCI manifest of the module. This is known only for modules
+    /// hosted on OCI registries that have been verified, or when the manifest digest
+    /// resolution has been requested.
+    pub(crate) manifest_digest: Option<String>,
+    /// The signatures of the module that have been verified, identifying the keys and
+    /// the identities that signed it. Empty when the signatures of the module have not
+    /// been verified
+    pub(crate) verified_signatures: Vec<Signature>,
+    /// The attestation requirements the module satisfies. Empty when the
+    /// attestations of the module have not been verified
+    pub(crate) verified_attestations: Vec<AttestationRequirements>,
+    /// Unix timestamp of the verification of the module, if any
+    pub(crate) verified_at: Option<u64>,
+    /// The metadata embedded into the module, if any
+    pub(crate) metadata: Option<FetchedModuleMetadata>,
+}
+
+impl FetchedModule {
+    /// Whether the signatures or the attestations of the module have been verified
+    pub(crate) fn is_verified(&self) -> bool {
+        self.verified_at.is_some()
+    }
+}
+
+/// The relevant bits of the metadata embedded into a module
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FetchedModuleMetadata {
+    pub(crate) mutating: bool,
+    pub(crate) execution_mode: PolicyExecutionMode,
+    pub(crate) minimum_kubewarden_version: Option<String>,
+}
+
+impl From<&Metadata> for FetchedModuleMetadata {
+    fn from(metadata: &Metadata) -> Self {
+        FetchedModuleMetadata {
+            mutating: metadata.mutating,
+            execution_mode: metadata.execution_mode,
+            minimum_kubewarden_version: metadata
+                .minimum_kubewarden_version
+                .as_ref()
+                .map(|version| version.to_string()),
+        }
+    }
+}
 
 /// Handles download and verification of policies
 pub(crate) struct Downloader {
     verifier: Option<Verifier>,
     sources: Option<Sources>,
+    resolve_manifest_digests: bool,
+    attestation_requirements: Option<AttestationRequirements>,
 }
 
 impl Downloader {
@@ -47,7 +122,64 @@ impl Downloader {
             None
         };
 
-        Ok(Downloader { verifier, sources })
+        Ok(Downloader {
+            verifier,
+            sources,
+            resolve_manifest_digests: false,
+            attestation_requirements: None,
+        })
+    }
+
+    /// Resolve the OCI manifest digest of all the modules hosted on OCI registries,
+    /// even when they are not verified
+    pub fn with_manifest_digest_resolution(mut self, resolve_manifest_digests: bool) -> Self {
+        self.resolve_manifest_digests = resolve_manifest_digests;
+        self
+    }
+
+    /// The attestations all the modules hosted on OCI registries must have, unless
+    /// overridden by the policies
+    pub fn with_attestation_requirements(
+        mut self,
+        attestation_requirements: Option<AttestationRequirements>,
+    ) -> Self {
+        self.attestation_requirements = attestation_requirements;
+        self
+    }
+
+    /// Resolve the digest of the OCI manifest referenced by `policy_url`
+    pub async fn manifest_digest(&self, policy_url: &str) -> Result<String> {
+        Registry::new()
+            .manifest_digest(policy_url, self.sources.as_ref())
+            .await
+            .map_err(|e| anyhow!("cannot resolve manifest digest of {}: {}", policy_url, e))
+    }
+
+    /// Fetch the digests of the layers of the OCI manifest with the given digest, living
+    /// in the repository of `policy_url`
+    pub async fn manifest_layer_digests(
+        &self,
+        policy_url: &str,
+        manifest_digest: &str,
+    ) -> Result<Vec<String>> {
+        oci::manifest_layer_digests(policy_url, manifest_digest, self.sources.as_ref()).await
+    }
+
+    /// Verify the signatures of `policy_url` against the given verification config.
+    /// Returns the digest of the verified OCI manifest.
+    ///
+    /// Returns `None` when the downloader has not been configured to verify modules.
+    pub async fn verify(
+        &mut self,
+        policy_url: &str,
+        verification_config: &LatestVerificationConfig,
+    ) -> Option<Result<String>> {
+        let ver = self.verifier.as_mut()?;
+        Some(
+            ver.verify(policy_url, verification_config)
+                .await
+                .map_err(|e| anyhow!("{}", e)),
+        )
     }
 
     /// Download all the policies to the given destination
@@ -57,7 +189,11 @@ impl Downloader {
         destination: impl AsRef<Path>,
         verification_config: Option<&LatestVerificationConfig>,
     ) -> FetchedPolicies {
-        let policies = policies_to_download(policies);
+        let policies = policies_to_download(
+            policies,
+            verification_config,
+            self.attestation_requirements.as_ref(),
+        );
         let policies_total = policies.len();
         info!(
             download_dir = destination
@@ -69,152 +205,410 @@ impl Downloader {
             "policies download",
         );
 
-        let verification_config = verification_config.unwrap_or(&LatestVerificationConfig {
-            all_of: None,
-            any_of: None,
-        });
-
         // The same WebAssembly module can be referenced by multiple policies,
-        // there's no need to keep downloading and verifying it
-
-        // List of policies that we have already tried to download & verify.
-        // Note: this Set includes both successful downloads and ones that
-        // failed.
-        let mut processed_policies: HashSet<&str> = HashSet::new();
+        // there's no need to keep downloading it. However, each policy can have
+        // different verification requirements, all of them must be satisfied
+        // before the module is used.
+        let mut modules: BTreeMap<&str, Vec<(&str, &PolicyToDownload)>> = BTreeMap::new();
+        for (name, policy) in policies.iter() {
+            modules
+                .entry(policy.url.as_str())
+                .or_default()
+                .push((name.as_str(), policy));
+        }
 
-        // List of policies that have been successfully fetched.
-        // This can be a subset of `processed_policies`
         let mut fetched_policies: FetchedPolicies = HashMap::new();
 
-        for (name, policy_url) in policies.iter() {
-            debug!(policy = name.as_str(), "download");
-            if !processed_policies.insert(policy_url) {
+        for (policy_url, mut module_policies) in modules {
+            module_policies.sort_by_key(|(name, _)| *name);
+
+            let fetched_policy = self
+                .download_policy(policy_url, &module_policies, destination.as_ref())
+                .await;
+            fetched_policies.insert(policy_url.to_owned(), fetched_policy);
+        }
+
+        fetched_policies
+    }
+
+    /// Download and verify a single WebAssembly module. The module is verified against the
+    /// verification config of each policy referencing it.
+    async fn download_policy(
+        &mut self,
+        policy_url: &str,
+        module_policies: &[(&str, &PolicyToDownload)],
+        destination: &Path,
+    ) -> Result<FetchedModule> {
+        let name = module_policies
+            .first()
+            .map(|(name, _)| *name)
+            .expect("a module is always referenced by at least one policy");
+        debug!(policy = name, "download");
+
+        // Verification configs that have already been checked, and the manifest digests
+        // obtained by them
+        let mut verified_configs: Vec<&LatestVerificationConfig> = Vec::new();
+        let mut verified_manifest_digests: Vec<String> = Vec::new();
+        // The signatures satisfied by the module, across all the verification configs
+        let mut verified_signatures: Vec<Signature> = Vec::new();
+
+        for (name, policy) in module_policies {
+            let verification_config = match &policy.verification_config {
+                Some(verification_config) => verification_config,
+                None => {
+                    debug!(policy = name, "verification skipped");
+                    continue;
+                }
+            };
+            if verified_configs.contains(&verification_config) {
                 debug!(
-                    policy = name.as_str(),
-                    "skipping, wasm module already processed"
+                    policy = name,
+                    "skipping, wasm module already verified with the same requirements"
                 );
-
                 continue;
             }
 
-            let mut verified_manifest_digest: Option<String> = None;
-
             if let Some(ver) = self.verifier.as_mut() {
                 info!(
-                    policy = name.as_str(),
+                    policy = name,
                     "verifying policy authenticity and integrity using sigstore"
                 );
-                verified_manifest_digest = match ver.verify(policy_url, verification_config).await {
-                    Ok(d) => Some(d),
-                    Err(e) => {
-                        error!(policy = name.as_str(), error =?e, "policy cannot be verified");
-                        fetched_policies.insert(
-                            policy_url.to_owned(),
-                            Err(anyhow!("Policy '{}' cannot be verified: {}", name, e)),
-                        );
-
-                        continue;
-                    }
-                };
+                let verified_manifest_digest = ver
+                    .verify(policy_url, verification_config)
+                    .await
+                    .map_err(|e| {
+                        error!(policy = name, error =?e, "policy cannot be verified");
+                        anyhow!("Policy '{}' cannot be verified: {}", name, e)
+                    })?;
                 info!(
-                    name = name.as_str(),
-                    sha256sum = verified_manifest_digest
-                        .as_ref()
-                        .unwrap_or(&"unknown".to_string())
-                        .as_str(),
+                    name = name,
+                    sha256sum = verified_manifest_digest.as_str(),
                     status = "verified-signatures",
                     "policy download",
                 );
+
+                for signature in satisfied_signatures(
+                    ver,
+                    policy_url,
+                    verification_config,
+                    &verified_manifest_digest,
+                )
+                .await
+                {
+                    if !verified_signatures.contains(&signature) {
+                        verified_signatures.push(signature);
+                    }
+                }
+                verified_configs.push(verification_config);
+                if !verified_manifest_digests.contains(&verified_manifest_digest) {
+                    verified_manifest_digests.push(verified_manifest_digest);
+                }
             }
+        }
 
-            let fetched_policy = match policy_fetcher::fetch_policy(
-                policy_url,
-                policy_fetcher::PullDestination::Store(destination.as_ref().to_path_buf()),
-                self.sources.as_ref(),
-            )
-            .await
-            {
-                Ok(fetched_policy) => fetched_policy,
-                Err(e) => {
-                    error!(
-                        policy = name.as_str(),
-                        error =? e,
-                        "policy download failed"
-                    );
-                    fetched_policies.insert(
-                        policy_url.to_owned(),
-                        Err(anyhow!(
-                            "Error while downloading policy '{}' from {}: {}",
-                            name,
-                            policy_url,
-                            e
-                        )),
-                    );
+        // Attestations are checked before the module is fetched. They are fetched once,
+        // then checked against the requirements of each policy
+        let mut verified_attestation_requirements: Vec<&AttestationRequirements> = Vec::new();
+        let mut attestations: Option<(String, Vec<attestation::Envelope>)> = None;
 
-                    continue;
-                }
+        for (name, policy) in module_policies {
+            let attestation_requirements = match &policy.attestation_requirements {
+                Some(attestation_requirements) => attestation_requirements,
+                None => continue,
             };
+            if verified_attestation_requirements.contains(&attestation_requirements) {
+                continue;
+            }
+            if !is_hosted_on_registry(policy_url) {
+                return Err(anyhow!(
+                    "Policy '{}' requires attestations, only modules hosted on OCI registries can have them",
+                    name
+                ));
+            }
 
-            if let Some(ver) = self.verifier.as_mut() {
+            if attestations.is_none() {
+                let manifest_digest = match verified_manifest_digests.first() {
+                    Some(verified_manifest_digest) => verified_manifest_digest.to_owned(),
+                    None => self.manifest_digest(policy_url).await?,
+                };
+                let envelopes = attestation::fetch_attestations(
+                    policy_url,
+                    &manifest_digest,
+                    self.sources.as_ref(),
+                )
+                .await
+                .map_err(|e| {
+                    error!(policy = name, error =? e, "policy attestations cannot be fetched");
+                    anyhow!("Policy '{}' attestations cannot be fetched: {}", name, e)
+                })?;
+                attestations = Some((manifest_digest, envelopes));
+            }
+            let (manifest_digest, envelopes) = attestations
+                .as_ref()
+                .expect("attestations have just been fetched");
+
+            info!(policy = name, "verifying policy attestations");
+            attestation::verify_attestations(envelopes, manifest_digest, attestation_requirements)
+                .map_err(|e| {
+                    error!(policy = name, error =? e, "policy attestations cannot be verified");
+                    anyhow!("Policy '{}' attestations cannot be verified: {}", name, e)
+                })?;
+            info!(
+                name = name,
+                sha256sum = manifest_digest.as_str(),
+                status = "verified-attestations",
+                "policy download",
+            );
+            verified_attestation_requirements.push(attestation_requirements);
+        }
+
+        let fetched_policy = policy_fetcher::fetch_policy(
+            policy_url,
+            policy_fetcher::PullDestination::Store(destination.to_path_buf()),
+            self.sources.as_ref(),
+        )
+        .await
+        .map_err(|e| {
+            error!(
+                policy = name,
+                error =? e,
+                "policy download failed"
+            );
+            anyhow!(
+                "Error while downloading policy '{}' from {}: {}",
+                name,
+                policy_url,
+                e
+            )
+        })?;
+
+        if let Some(ver) = self.verifier.as_mut() {
+            for verified_manifest_digest in verified_manifest_digests.iter() {
                 if let Err(e) = ver
-                    .verify_local_file_checksum(
-                        &fetched_policy,
-                        verified_manifest_digest.as_ref().unwrap(),
-                    )
+                    .verify_local_file_checksum(&fetched_policy, verified_manifest_digest)
                     .await
                 {
                     error!(
-                        policy = name.as_str(),
+                        policy = name,
                         error =? e,
                         "verification failed"
                     );
 
-                    fetched_policies.insert(
-                        policy_url.to_owned(),
-                        Err(anyhow!("Verification of policy {} failed: {}", name, e)),
-                    );
-                    continue;
+                    return Err(anyhow!("Verification of policy {} failed: {}", name, e));
                 }
 
                 info!(
-                    name = name.as_str(),
-                    sha256sum = verified_manifest_digest
-                        .as_ref()
-                        .unwrap_or(&"unknown".to_string())
-                        .as_str(),
+                    name = name,
+                    sha256sum = verified_manifest_digest.as_str(),
                     status = "verified-local-checksum",
                     "policy download",
                 );
             }
+        }
 
-            if let Ok(Some(policy_metadata)) = Metadata::from_path(&fetched_policy.local_path) {
-                info!(
-                    name = name.as_str(),
-                    path = fetched_policy.local_path.clone().into_os_string().to_str(),
-                    sha256sum = fetched_policy
-                        .digest()
-                        .unwrap_or_else(|_| "unknown".to_string())
-                        .as_str(),
-                    mutating = policy_metadata.mutating,
-                    "policy download",
-                );
-            } else {
-                info!(
-                    name = name.as_str(),
-                    path = fetched_policy.local_path.clone().into_os_string().to_str(),
-                    sha256sum = fetched_policy
-                        .digest()
-                        .unwrap_or_else(|_| "unknown".to_string())
-                        .as_str(),
-                    "policy download",
+        let sha256 = fetched_policy.digest().ok();
+
+        // The module is pulled by tag, which could have been moved since the attestations
+        // have been checked. Unlike the signatures, nothing else ensures the module is the
+        // one of the attested manifest
+        if let Some((attested_manifest_digest, _)) = &attestations {
+            let layer_digests = self
+                .manifest_layer_digests(policy_url, attested_manifest_digest)
+                .await
+                .map_err(|e| {
+                    error!(policy = name, error =? e, "verification failed");
+                    anyhow!("Verification of policy {} failed: {}", name, e)
+                })?;
+            if !sha256
+                .as_deref()
+                .is_some_and(|sha256| oci::contains_layer(&layer_digests, sha256))
+            {
+                error!(
+                    policy = name,
+                    manifest_digest = attested_manifest_digest.as_str(),
+                    "the downloaded module does not belong to the attested manifest"
                 );
+                return Err(anyhow!(
+                    "Verification of policy {} failed: the downloaded module does not belong to the attested manifest {}",
+                    name,
+                    attested_manifest_digest
+                ));
             }
 
-            fetched_policies.insert(policy_url.to_owned(), Ok(fetched_policy.local_path));
+            info!(
+                name = name,
+                sha256sum = attested_manifest_digest.as_str(),
+                status = "verified-local-checksum",
+                "policy download",
+            );
         }
 
-        fetched_policies
+        let metadata = Metadata::from_path(&fetched_policy.local_path)
+            .ok()
+            .flatten();
+        if let Some(policy_metadata) = &metadata {
+            info!(
+                name = name,
+                path = fetched_policy.local_path.clone().into_os_string().to_str(),
+                sha256sum = sha256.as_deref().unwrap_or("unknown"),
+                mutating = policy_metadata.mutating,
+                "policy download",
+            );
+        } else {
+            info!(
+                name = name,
+                path = fetched_policy.local_path.clone().into_os_string().to_str(),
+                sha256sum = sha256.as_deref().unwrap_or("unknown"),
+                "policy download",
+            );
+        }
+
+        let manifest_digest = match verified_manifest_digests.first().or(attestations
+            .as_ref()
+            .map(|(manifest_digest, _)| manifest_digest))
+        {
+            Some(verified_manifest_digest) => Some(verified_manifest_digest.to_owned()),
+            None if self.resolve_manifest_digests && is_hosted_on_registry(policy_url) => {
+                self.resolve_downloaded_manifest_digest(name, policy_url, sha256.as_deref())
+                    .await
+            }
+            None => None,
+        };
+
+        let verified_at =
+            if verified_configs.is_empty() && verified_attestation_requirements.is_empty() {
+                None
+            } else {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .ok()
+            };
+
+        Ok(FetchedModule {
+            url: policy_url.to_owned(),
+            policies: module_policies
+                .iter()
+                .map(|(name, _)| (*name).to_owned())
+                .collect(),
+            local_path: fetched_policy.local_path,
+            sha256,
+            manifest_digest,
+            verified_signatures,
+            verified_attestations: verified_attestation_requirements
+                .into_iter()
+                .cloned()
+                .collect(),
+            verified_at,
+            metadata: metadata.as_ref().map(FetchedModuleMetadata::from),
+        })
     }
+
+    /// Resolve the manifest digest of a module that has not been verified. The tag could
+    /// have been moved since the module has been pulled, the digest is returned only when
+    /// the downloaded module is a layer of the manifest.
+    async fn resolve_downloaded_manifest_digest(
+        &self,
+        name: &str,
+        policy_url: &str,
+        sha256: Option<&str>,
+    ) -> Option<String> {
+        let sha256 = sha256?;
+        let manifest_digest = self
+            .manifest_digest(policy_url)
+            .await
+            .inspect_err(|e| warn!(policy = name, error =? e, "cannot resolve manifest digest"))
+            .ok()?;
+        let layer_digests = self
+            .manifest_layer_digests(policy_url, &manifest_digest)
+            .await
+            .inspect_err(|e| warn!(policy = name, error =? e, "cannot resolve manifest digest"))
+            .ok()?;
+
+        if oci::contains_layer(&layer_digests, sha256) {
+            Some(manifest_digest)
+        } else {
+            warn!(
+                policy = name,
+                manifest_digest = manifest_digest.as_str(),
+                "the tag has been moved while the module was downloaded"
+            );
+            None
+        }
+    }
+}
+
+/// The signatures of `verification_config` the module has, once the module has been
+/// verified against it. All the `allOf` signatures are satisfied, while the `anyOf`
+/// ones are verified one by one to find out which of them the module has.
+async fn satisfied_signatures(
+    verifier: &mut Verifier,
+    policy_url: &str,
+    verification_config: &LatestVerificationConfig,
+    verified_manifest_digest: &str,
+) -> Vec<Signature> {
+    let mut signatures = verification_config.all_of.clone().unwrap_or_default();
+
+    let any_of = verification_config
+        .any_of
+        .as_ref()
+        .map(|any_of| any_of.signatures.as_slice())
+        .unwrap_or_default();
+    for signature in any_of {
+        let single_signature_config = LatestVerificationConfig {
+            all_of: Some(vec![signature.clone()]),
+            any_of: None,
+        };
+        match verifier.verify(policy_url, &single_signature_config).await {
+            // The tag could have been moved in the meantime
+            Ok(manifest_digest) if manifest_digest == verified_manifest_digest => {
+                signatures.push(signature.clone());
+            }
+            Ok(_) => {}
+            Err(e) => debug!(
+                policy_url,
+                signer = signer(signature).as_str(),
+                error = e.to_string(),
+                "anyOf signature not satisfied"
+            ),
+        }
+    }
+
+    signatures
+}
+
+/// A short description of who produced the signature, like the owner of the key or
+/// the issuer and subject of the certificate
+pub(crate) fn signer(signature: &Signature) -> String {
+    match signature {
+        Signature::PubKey { owner, key, .. } => match owner {
+            Some(owner) => format!("pubKey:{owner}"),
+            None => format!("pubKey:sha256:{:x}", Sha256::digest(key.as_bytes())),
+        },
+        Signature::GenericIssuer {
+            issuer, subject, ..
+        } => {
+            let subject = match subject {
+                Subject::Equal(subject) => subject.to_string(),
+                Subject::UrlPrefix(url_prefix) => format!("{url_prefix}*"),
+            };
+            format!("genericIssuer:{issuer}:{subject}")
+        }
+        Signature::GithubAction { owner, repo, .. } => match repo {
+            Some(repo) => format!("githubAction:{owner}/{repo}"),
+            None => format!("githubAction:{owner}"),
+        },
+        Signature::Certificate { certificate, .. } => format!(
+            "certificate:sha256:{:x}",
+            Sha256::digest(certificate.as_bytes())
+        ),
+    }
+}
+
+/// Returns `true` when the module is pulled from an OCI registry. Modules
+/// without scheme are pulled from OCI registries too.
+pub(crate) fn is_hosted_on_registry(policy_url: &str) -> bool {
+    policy_url.starts_with("registry://") || !policy_url.contains("://")
 }
 
 /// Creates a new Verifier that fetches Fulcio and Rekor data from the official
@@ -228,25 +622,66 @@ async fn create_verifier(
     Ok(verifier)
 }
 
+/// A WebAssembly module to be downloaded on behalf of a policy
+pub(crate) struct PolicyToDownload {
+    /// The URL of the module
+    pub(crate) url: String,
+    /// The verification config to be used for the module. `None` when the
+    /// module must not be verified.
+    pub(crate) verification_config: Option<LatestVerificationConfig>,
+    /// The attestations the module must have. `None` when the attestations
+    /// of the module must not be checked.
+    pub(crate) attestation_requirements: Option<AttestationRequirements>,
+}
+
 /// Group policies need to be flattened into a single list of policies to download
 ///
-/// Return a map with the name of the policy as key, and the its download url as value.
+/// Return a map with the name of the policy as key, and the module to download as value.
 /// Sub-policies are named as `group_name/sub_policy_name`
-fn policies_to_download(
+pub(crate) fn policies_to_download(
     policies: &HashMap<String, PolicyOrPolicyGroup>,
-) -> HashMap<String, String> {
-    let mut flattened_policies: HashMap<String, String> = HashMap::new();
+    verification_config: Option<&LatestVerificationConfig>,
+    attestation_requirements: Option<&AttestationRequirements>,
+) -> HashMap<String, PolicyToDownload> {
+    let mut flattened_policies: HashMap<String, PolicyToDownload> = HashMap::new();
 
     for (name, policy) in policies {
         match policy {
-            PolicyOrPolicyGroup::Policy { module: url, .. } => {
-                flattened_policies.insert(name.to_owned(), url.to_owned());
+            PolicyOrPolicyGroup::Policy {
+                module: url,
+                verify,
+                ..
+            } => {
+                flattened_policies.insert(
+                    name.to_owned(),
+                    PolicyToDownload {
+                        url: url.to_owned(),
+                        verification_config: effective_verification_config(
+                            verification_config,
+                            verify.as_ref(),
+                        ),
+                        attestation_requirements: effective_attestation_requirements(
+                            attestation_requirements,
+                            verify.as_ref(),
+                        ),
+                    },
+                );
             }
             PolicyOrPolicyGroup::PolicyGroup { policies, .. } => {
                 for (sub_policy_name, sub_policy) in policies {
                     flattened_policies.insert(
                         format!("{name}/#{sub_policy_name}"),
-                        sub_policy.module.to_owned(),
+                        PolicyToDownload {
+                            url: sub_policy.module.to_owned(),
+                            verification_config: effective_verification_config(
+                                verification_config,
+                                sub_policy.verify.as_ref(),
+                            ),
+                            attestation_requirements: effective_attestation_requirements(
+                                attestation_requirements,
+                                sub_policy.verify.as_ref(),
+                            ),
+                        },
                     );
                 }
             }
@@ -256,11 +691,205 @@ fn policies_to_download(
     flattened_policies
 }
 
+/// Merge the global verification config with the requirements of a single policy.
+///
+/// The `allOf` signatures of the policy are added to the global ones, while the `anyOf`
+/// requirement of the policy replaces the global one. The global config is ignored
+/// when the policy sets `replaceGlobal`, the policies file validation ensures the policy
+/// has requirements of its own in that case.
+///
+/// Returns `None` when the module must not be verified.
+pub(crate) fn effective_verification_config(
+    global_verification_config: Option<&LatestVerificationConfig>,
+    policy_verification: Option<&PolicyVerification>,
+) -> Option<LatestVerificationConfig> {
+    let requirements = match policy_verification {
+        None => return global_verification_config.cloned(),
+        Some(PolicyVerification::Mode(VerificationMode::Skip)) => return None,
+        Some(PolicyVerification::Requirements(requirements)) => requirements,
+    };
+
+    let global_verification_config = if requirements.replace_global {
+        None
+    } else {
+        global_verification_config
+    };
+
+    let mut all_of: Vec<Signature> = global_verification_config
+        .and_then(|config| config.all_of.clone())
+        .unwrap_or_default();
+    all_of.extend(requirements.all_of.clone().unwrap_or_default());
+
+    let any_of = requirements
+        .any_of
+        .clone()
+        .or_else(|| global_verification_config.and_then(|config| config.any_of.clone()));
+
+    if all_of.is_empty() && any_of.is_none() {
+        return None;
+    }
+
+    Some(LatestVerificationConfig {
+        all_of: if all_of.is_empty() {
+            None
+        } else {
+            Some(all_of)
+        },
+        any_of,
+    })
+}
+
+/// The attestation requirements of a policy replace the global ones. The global
+/// requirements are ignored when the policy sets `replaceGlobal`.
+///
+/// Returns `None` when the attestations of the module must not be checked.
+pub(crate) fn effective_attestation_requirements(
+    global_attestation_requirements: Option<&AttestationRequirements>,
+    policy_verification: Option<&PolicyVerification>,
+) -> Option<AttestationRequirements> {
+    match policy_verification {
+        None => global_attestation_requirements.cloned(),
+        Some(PolicyVerification::Mode(VerificationMode::Skip)) => None,
+        Some(PolicyVerification::Requirements(requirements)) => match &requirements.attestations {
+            Some(attestations) => Some(attestations.clone()),
+            None if requirements.replace_global => None,
+            None => global_attestation_requirements.cloned(),
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::PolicyVerificationRequirements;
+    use rstest::*;
     use tempfile::TempDir;
 
+    fn github_action_signature(owner: &str) -> Signature {
+        Signature::GithubAction {
+            owner: owner.to_owned(),
+            repo: None,
+            annotations: None,
+        }
+    }
+
+    #[rstest]
+    #[case::github_action(github_action_signature("kubewarden"), "githubAction:kubewarden")]
+    #[case::github_action_repo(
+        Signature::GithubAction {
+            owner: "kubewarden".to_owned(),
+            repo: Some("policy-server".to_owned()),
+            annotations: None,
+        },
+        "githubAction:kubewarden/policy-server"
+    )]
+    #[case::pub_key(
+        Signature::PubKey {
+            owner: Some("internal-team".to_owned()),
+            key: "key".to_owned(),
+            annotations: None,
+        },
+        "pubKey:internal-team"
+    )]
+    #[case::pub_key_without_owner(
+        Signature::PubKey {
+            owner: None,
+            key: "key".to_owned(),
+            annotations: None,
+        },
+        "pubKey:sha256:2c70e12b7a0646f92279f427c7b38e7334d8e5389cff167a1dc30e73f826b683"
+    )]
+    fn signature_signer(#[case] signature: Signature, #[case] expected: &str) {
+        assert_eq!(expected, signer(&signature));
+    }
+
+    fn global_verification_config() -> LatestVerificationConfig {
+        LatestVerificationConfig {
+            all_of: Some(vec![github_action_signature("kubewarden")]),
+            any_of: None,
+        }
+    }
+
+    #[rstest]
+    #[case::inherit_global(None, Some(global_verification_config()))]
+    #[case::skip(Some(PolicyVerification::Mode(VerificationMode::Skip)), None)]
+    #[case::merge_with_global(
+        Some(PolicyVerification::Requirements(PolicyVerificationRequirements {
+            replace_global: false,
+            all_of: Some(vec![github_action_signature("third-party")]),
+            any_of: None,
+            attestations: None,
+        })),
+        Some(LatestVerificationConfig {
+            all_of: Some(vec![
+                github_action_signature("kubewarden"),
+                github_action_signature("third-party"),
+            ]),
+            any_of: None,
+        })
+    )]
+    #[case::replace_global(
+        Some(PolicyVerification::Requirements(PolicyVerificationRequirements {
+            replace_global: true,
+            all_of: Some(vec![github_action_signature("internal")]),
+            any_of: None,
+            attestations: None,
+        })),
+        Some(LatestVerificationConfig {
+            all_of: Some(vec![github_action_signature("internal")]),
+            any_of: None,
+        })
+    )]
+    fn merge_verification_config(
+        #[case] policy_verification: Option<PolicyVerification>,
+        #[case] expected: Option<LatestVerificationConfig>,
+    ) {
+        let global_verification_config = global_verification_config();
+
+        assert_eq!(
+            expected,
+            effective_verification_config(
+                Some(&global_verification_config),
+                policy_verification.as_ref()
+            )
+        );
+    }
+
+    fn attestation_requirements(sbom: bool) -> AttestationRequirements {
+        AttestationRequirements {
+            keys: vec!["key".to_owned()],
+            provenance: None,
+            sbom,
+        }
+    }
+
+    #[rstest]
+    #[case::inherit_global(None, Some(attestation_requirements(false)))]
+    #[case::skip(Some(PolicyVerification::Mode(VerificationMode::Skip)), None)]
+    #[case::replace(
+        Some(PolicyVerification::Requirements(PolicyVerificationRequirements {
+            replace_global: false,
+            all_of: None,
+            any_of: None,
+            attestations: Some(attestation_requirements(true)),
+        })),
+        Some(attestation_requirements(true))
+    )]
+    fn merge_attestation_requirements(
+        #[case] policy_verification: Option<PolicyVerification>,
+        #[case] expected: Option<AttestationRequirements>,
+    ) {
+        let global_attestation_requirements = attestation_requirements(false);
+
+        assert_eq!(
+            expected,
+            effective_attestation_requirements(
+                Some(&global_attestation_requirements),
+                policy_verification.as_ref()
+            )
+        );
+    }
+
     #[tokio::test]
     async fn verify_success() {
         let verification_cfg_yml = r#"---
@@ -372,4 +1001,49 @@ mod tests {
             Err(error) if error.to_string().contains("Policy 'pod-privileged' cannot be verified: Image verification failed: missing signatures")
         ));
     }
+
+    #[tokio::test]
+    async fn verify_skip() {
+        let verification_cfg_yml = r#"---
+    allOf:
+      - kind: githubAction
+        owner: kubewarden
+       "#;
+        let verification_config =
+            serde_yaml::from_str::<LatestVerificationConfig>(verification_cfg_yml)
+                .expect("Cannot convert verification config");
+
+        let policies_cfg = r#"
+    pod-privileged:
+      module: registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9
+      verify: skip
+    "#;
+
+        let policies: HashMap<String, PolicyOrPolicyGroup> =
+            serde_yaml::from_str(policies_cfg).expect("Cannot parse policy cfg");
+
+        let policy_download_dir = TempDir::new().expect("Cannot create temp dir");
+        let trust_root = sigstore::trust::sigstore::SigstoreTrustRoot::new(None)
+            .await
+            .unwrap();
+
+        let mut downloader = Downloader::new(None, Some(Arc::new(trust_root)))
+            .await
+            .unwrap();
+
+        let fetched_policies = downloader
+            .download_policies(
+                &policies,
+                policy_download_dir.path().to_str().unwrap(),
+                Some(&verification_config),
+            )
+            .await;
+
+        assert!(
+            fetched_policies
+                .get("registry://ghcr.io/kubewarden/tests/pod-privileged:v0.1.9")
+                .unwrap()
+                .is_ok()
+        );
+    }
 }
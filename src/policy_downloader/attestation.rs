@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use oci_client::{Reference, manifest::OciManifest};
+use policy_evaluator::policy_fetcher::{
+    sigstore::crypto::{CosignVerificationKey, Signature},
+    sources::Sources,
+};
+use serde::Deserialize;
+use tracing::debug;
+
+use super::oci;
+use crate::config::{AttestationRequirements, ProvenanceRequirements};
+
+const DSSE_ENVELOPE_MEDIA_TYPE: &str = "application/vnd.dsse.envelope.v1+json";
+const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+const SLSA_PROVENANCE_V02_PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v0.2";
+const SLSA_PROVENANCE_V1_PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+
+/// Predicate types of SBOM attestations. Versioned variants (like
+/// `https://spdx.dev/Document/v2.3`) are accepted too.
+const SBOM_PREDICATE_TYPE_PREFIXES: [&str; 2] =
+    ["https://spdx.dev/Document", "https://cyclonedx.org/bom"];
+
+/// A DSSE envelope, see https://github.com/secure-systems-lab/dsse/blob/master/envelope.md
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Envelope {
+    payload_type: String,
+    /// base64 encoded payload
+    payload: String,
+    signatures: Vec<EnvelopeSignature>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EnvelopeSignature {
+    /// base64 encoded signature
+    sig: String,
+}
+
+/// An in-toto statement, see https://github.com/in-toto/attestation/blob/main/spec/v1/statement.md
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Statement {
+    subject: Vec<Subject>,
+    predicate_type: String,
+    #[serde(default)]
+    predicate: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct Subject {
+    digest: HashMap<String, String>,
+}
+
+/// Fetch the attestations of the module referenced by `policy_url`, whose manifest
+/// has the given digest.
+///
+/// Attestations are expected to be stored using the same layout used by `cosign attest`:
+/// an OCI image tagged `sha256-<manifest digest>.att`, living next to the module, whose
+/// layers are DSSE envelopes wrapping in-toto statements.
+pub(crate) async fn fetch_attestations(
+    policy_url: &str,
+    manifest_digest: &str,
+    sources: Option<&Sources>,
+) -> Result<Vec<Envelope>> {
+    let reference = oci::reference(policy_url)?;
+    let attestations_reference = Reference::with_tag(
+        reference.registry().to_owned(),
+        reference.repository().to_owned(),
+        format!("{}.att", manifest_digest.replace(':', "-")),
+    );
+
+    let client = oci::client(sources);
+    let auth = oci::registry_auth(reference.resolve_registry());
+
+    let (manifest, _) = client
+        .pull_manifest(&attestations_reference, &auth)
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "cannot fetch attestations from {}: {}",
+                attestations_reference,
+                e
+            )
+        })?;
+    let manifest = match manifest {
+        OciManifest::Image(manifest) => manifest,
+        OciManifest::ImageIndex(_) => {
+            return Err(anyhow!(
+                "{} is an image index, not an attestations image",
+                attestations_reference
+            ));
+        }
+    };
+
+    let mut envelopes = Vec::new();
+    for layer in manifest
+        .layers
+        .iter()
+        .filter(|layer| layer.media_type == DSSE_ENVELOPE_MEDIA_TYPE)
+    {
+        let mut blob: Vec<u8> = Vec::new();
+        client
+            .pull_blob(&attestations_reference, layer, &mut blob)
+            .await
+            .map_err(|e| anyhow!("cannot fetch attestation {}: {}", layer.digest, e))?;
+        let envelope: Envelope = serde_json::from_slice(&blob)
+            .map_err(|e| anyhow!("cannot parse attestation {}: {}", layer.digest, e))?;
+        envelopes.push(envelope);
+    }
+
+    Ok(envelopes)
+}
+
+/// Ensure the attestations of the module with the given manifest digest satisfy
+/// the requirements.
+///
+/// Only the attestations signed by one of the trusted keys and referencing the
+/// module are taken into account, all the others are ignored.
+pub(crate) fn verify_attestations(
+    envelopes: &[Envelope],
+    manifest_digest: &str,
+    requirements: &AttestationRequirements,
+) -> Result<()> {
+    let keys = requirements
+        .keys
+        .iter()
+        .map(|key| {
+            CosignVerificationKey::try_from_pem(key.as_bytes())
+                .map_err(|e| anyhow!("invalid attestation key: {}", e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if keys.is_empty() {
+        return Err(anyhow!("no key trusted to sign the attestations"));
+    }
+
+    let statements: Vec<Statement> = envelopes
+        .iter()
+        .filter_map(
+            |envelope| match trusted_statement(envelope, &keys, manifest_digest) {
+                Ok(statement) => Some(statement),
+                Err(e) => {
+                    debug!(error = e.to_string(), "attestation ignored");
+                    None
+                }
+            },
+        )
+        .collect();
+
+    if let Some(provenance_requirements) = &requirements.provenance {
+        let mut failures = Vec::new();
+        let provenances = statements.iter().filter(|statement| {
+            statement.predicate_type == SLSA_PROVENANCE_V02_PREDICATE_TYPE
+                || statement.predicate_type == SLSA_PROVENANCE_V1_PREDICATE_TYPE
+        });
+        let satisfied = provenances
+            .map(|statement| {
+                check_provenance(
+                    &statement.predicate_type,
+                    &statement.predicate,
+                    provenance_requirements,
+                )
+            })
+            .any(|result| match result {
+                Ok(()) => true,
+                Err(e) => {
+                    failures.push(e.to_string());
+                    false
+                }
+            });
+        if !satisfied {
+            return Err(if failures.is_empty() {
+                anyhow!("no trusted SLSA provenance attestation found")
+            } else {
+                anyhow!(
+                    "no SLSA provenance attestation satisfies the requirements: {}",
+                    failures.join("; ")
+                )
+            });
+        }
+    }
+
+    if requirements.sbom
+        && !statements.iter().any(|statement| {
+            SBOM_PREDICATE_TYPE_PREFIXES
+                .iter()
+                .any(|prefix| statement.predicate_type.starts_with(prefix))
+        })
+    {
+        return Err(anyhow!("no trusted SBOM attestation found"));
+    }
+
+    Ok(())
+}
+
+/// Returns the in-toto statement wrapped by the envelope, provided the envelope
+/// is signed by one of the `keys` and the statement is about the given manifest digest
+fn trusted_statement(
+    envelope: &Envelope,
+    keys: &[CosignVerificationKey],
+    manifest_digest: &str,
+) -> Result<Statement> {
+    if envelope.payload_type != IN_TOTO_PAYLOAD_TYPE {
+        return Err(anyhow!("unexpected payload type {}", envelope.payload_type));
+    }
+    let payload = BASE64
+        .decode(&envelope.payload)
+        .map_err(|e| anyhow!("cannot decode payload: {}", e))?;
+
+    let message = pre_authentication_encoding(&envelope.payload_type, &payload);
+    let signed_by_trusted_key = envelope.signatures.iter().any(|signature| {
+        let Ok(signature) = BASE64.decode(&signature.sig) else {
+            return false;
+        };
+        keys.iter().any(|key| {
+            key.verify_signature(Signature::Raw(&signature), &message)
+                .is_ok()
+        })
+    });
+    if !signed_by_trusted_key {
+        return Err(anyhow!("not signed by any of the trusted keys"));
+    }
+
+    let statement: Statement = serde_json::from_slice(&payload)
+        .map_err(|e| anyhow!("cannot parse in-toto statement: {}", e))?;
+
+    let digest = manifest_digest
+        .strip_prefix("sha256:")
+        .unwrap_or(manifest_digest);
+    if !statement
+        .subject
+        .iter()
+        .any(|subject| subject.digest.get("sha256").map(String::as_str) == Some(digest))
+    {
+        return Err(anyhow!("the statement is not about {}", manifest_digest));
+    }
+
+    Ok(statement)
+}
+
+/// The message signed by DSSE, see
+/// https://github.com/secure-systems-lab/dsse/blob/master/protocol.md
+fn pre_authentication_encoding(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut message = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Ensure a SLSA provenance predicate satisfies the requirements
+fn check_provenance(
+    predicate_type: &str,
+    predicate: &serde_json::Value,
+    requirements: &ProvenanceRequirements,
+) -> Result<()> {
+    let (builder_id, source_repository) = if predicate_type == SLSA_PROVENANCE_V1_PREDICATE_TYPE {
+        (
+            predicate.pointer("/runDetails/builder/id"),
+            predicate
+                .pointer("/buildDefinition/externalParameters/workflow/repository")
+                .or_else(|| predicate.pointer("/buildDefinition/resolvedDependencies/0/uri")),
+        )
+    } else {
+        (
+            predicate.pointer("/builder/id"),
+            predicate
+                .pointer("/invocation/configSource/uri")
+                .or_else(|| predicate.pointer("/materials/0/uri")),
+        )
+    };
+
+    let builder_id = builder_id
+        .and_then(|id| id.as_str())
+        .ok_or_else(|| anyhow!("provenance without builder id"))?;
+    let builder = requirements
+        .trusted_builders
+        .iter()
+        .find(|builder| matches_prefix(builder_id, &builder.id))
+        .ok_or_else(|| anyhow!("builder {} is not trusted", builder_id))?;
+    if builder.slsa_level < requirements.minimum_slsa_level {
+        return Err(anyhow!(
+            "builder {} provides SLSA level {}, level {} is required",
+            builder_id,
+            builder.slsa_level,
+            requirements.minimum_slsa_level
+        ));
+    }
+
+    if requirements.source_repositories.is_empty() {
+        return Ok(());
+    }
+    let source_repository = source_repository
+        .and_then(|uri| uri.as_str())
+        .ok_or_else(|| anyhow!("provenance without source repository"))?;
+    // source URIs are usually prefixed by the VCS, like `git+https://github.com/...`
+    let source_repository = source_repository
+        .strip_prefix("git+")
+        .unwrap_or(source_repository);
+    if !requirements
+        .source_repositories
+        .iter()
+        .any(|prefix| matches_prefix(source_repository, prefix))
+    {
+        return Err(anyhow!(
+            "source repository {} is not allowed",
+            source_repository
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns `true` when `value` is `prefix`, or when it continues `prefix` after a path
+/// segment or a ref. `https://github.com/kubewarden/policies` matches
+/// `https://github.com/kubewarden/policies@refs/tags/v1.0.0`, not
+/// `https://github.com/kubewarden/policies-evil`
+fn matches_prefix(value: &str, prefix: &str) -> bool {
+    let Some(rest) = value.strip_prefix(prefix) else {
+        return false;
+    };
+
+    rest.is_empty() || prefix.ends_with('/') || rest.starts_with(['/', '@'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TrustedBuilder;
+    use policy_evaluator::policy_fetcher::sigstore::crypto::{SigStoreSigner, SigningScheme};
+    use rstest::*;
+    use serde_json::json;
+
+    const MANIFEST_DIGEST: &str =
+        "sha256:0bbd9a6b2e4f1c2e8e1f1bd7d0f0ba5b5a8c7cba4bd0e0d0e1f0d5c2a9b1c3d4";
+    const GENERATOR_BUILDER: &str = "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/generator_generic_slsa3.yml@refs/tags/v2.0.0";
+
+    fn provenance_requirements() -> ProvenanceRequirements {
+        ProvenanceRequirements {
+            trusted_builders: vec![TrustedBuilder {
+                id: "https://github.com/slsa-framework/slsa-github-generator/".to_owned(),
+                slsa_level: 3,
+            }],
+            source_repositories: vec!["https://github.com/kubewarden/".to_owned()],
+            minimum_slsa_level: 3,
+        }
+    }
+
+    fn provenance_v02(builder_id: &str, source_repository: &str) -> serde_json::Value {
+        json!({
+            "builder": { "id": builder_id },
+            "invocation": {
+                "configSource": { "uri": source_repository }
+            }
+        })
+    }
+
+    #[rstest]
+    #[case::v02(
+        SLSA_PROVENANCE_V02_PREDICATE_TYPE,
+        provenance_v02(
+            GENERATOR_BUILDER,
+            "git+https://github.com/kubewarden/policies@refs/tags/v1.0.0"
+        ),
+        true
+    )]
+    #[case::v1(
+        SLSA_PROVENANCE_V1_PREDICATE_TYPE,
+        json!({
+            "buildDefinition": {
+                "externalParameters": {
+                    "workflow": { "repository": "https://github.com/kubewarden/policies" }
+                }
+            },
+            "runDetails": {
+                "builder": { "id": GENERATOR_BUILDER }
+            }
+        }),
+        true
+    )]
+    #[case::untrusted_builder(
+        SLSA_PROVENANCE_V02_PREDICATE_TYPE,
+        provenance_v02(
+            "https://example.com/builder",
+            "git+https://github.com/kubewarden/policies"
+        ),
+        false
+    )]
+    #[case::untrusted_source(
+        SLSA_PROVENANCE_V02_PREDICATE_TYPE,
+        provenance_v02(GENERATOR_BUILDER, "git+https://github.com/someone-else/policies"),
+        false
+    )]
+    #[case::missing_builder(SLSA_PROVENANCE_V02_PREDICATE_TYPE, json!({}), false)]
+    fn provenance(
+        #[case] predicate_type: &str,
+        #[case] predicate: serde_json::Value,
+        #[case] is_valid: bool,
+    ) {
+        assert_eq!(
+            is_valid,
+            check_provenance(predicate_type, &predicate, &provenance_requirements()).is_ok()
+        );
+    }
+
+    #[rstest]
+    #[case::same_repository(
+        "https://github.com/kubewarden/policies",
+        "https://github.com/kubewarden/policies",
+        true
+    )]
+    #[case::repository_ref(
+        "https://github.com/kubewarden/policies",
+        "https://github.com/kubewarden/policies@refs/tags/v1.0.0",
+        true
+    )]
+    #[case::lookalike_repository(
+        "https://github.com/kubewarden/policies",
+        "https://github.com/kubewarden/policies-evil",
+        false
+    )]
+    #[case::owner_repository(
+        "https://github.com/kubewarden",
+        "https://github.com/kubewarden/policies",
+        true
+    )]
+    #[case::lookalike_owner(
+        "https://github.com/kubewarden",
+        "https://github.com/kubewarden-attacker/policies",
+        false
+    )]
+    #[case::owner_with_slash(
+        "https://github.com/kubewarden/",
+        "https://github.com/kubewarden/policies",
+        true
+    )]
+    fn source_repository_boundary(
+        #[case] allowed_repository: &str,
+        #[case] source_repository: &str,
+        #[case] is_valid: bool,
+    ) {
+        let requirements = ProvenanceRequirements {
+            source_repositories: vec![allowed_repository.to_owned()],
+            ..provenance_requirements()
+        };
+
+        assert_eq!(
+            is_valid,
+            check_provenance(
+                SLSA_PROVENANCE_V02_PREDICATE_TYPE,
+                &provenance_v02(GENERATOR_BUILDER, source_repository),
+                &requirements,
+            )
+            .is_ok()
+        );
+    }
+
+    #[rstest]
+    #[case::same_workflow(GENERATOR_BUILDER, true)]
+    #[case::lookalike_workflow(
+        "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/generator_generic_slsa3.yml.evil@refs/tags/v2.0.0",
+        false
+    )]
+    #[case::lookalike_owner(
+        "https://github.com/slsa-framework-attacker/slsa-github-generator/.github/workflows/generator_generic_slsa3.yml@refs/tags/v2.0.0",
+        false
+    )]
+    fn builder_id_boundary(#[case] builder_id: &str, #[case] is_valid: bool) {
+        let requirements = ProvenanceRequirements {
+            trusted_builders: vec![TrustedBuilder {
+                id: "https://github.com/slsa-framework/slsa-github-generator/.github/workflows/generator_generic_slsa3.yml".to_owned(),
+                slsa_level: 3,
+            }],
+            ..provenance_requirements()
+        };
+
+        assert_eq!(
+            is_valid,
+            check_provenance(
+                SLSA_PROVENANCE_V02_PREDICATE_TYPE,
+                &provenance_v02(builder_id, "git+https://github.com/kubewarden/policies"),
+                &requirements,
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn provenance_minimum_slsa_level() {
+        let requirements = ProvenanceRequirements {
+            minimum_slsa_level: 4,
+            ..provenance_requirements()
+        };
+
+        assert!(
+            check_provenance(
+                SLSA_PROVENANCE_V02_PREDICATE_TYPE,
+                &provenance_v02(
+                    GENERATOR_BUILDER,
+                    "git+https://github.com/kubewarden/policies"
+                ),
+                &requirements,
+            )
+            .is_err()
+        );
+    }
+
+    fn sign_statement(
+        signer: &SigStoreSigner,
+        predicate_type: &str,
+        predicate: serde_json::Value,
+    ) -> Envelope {
+        let statement = json!({
+            "_type": "https://in-toto.io/Statement/v0.1",
+            "subject": [{
+                "name": "ghcr.io/kubewarden/policies/example",
+                "digest": { "sha256": MANIFEST_DIGEST.strip_prefix("sha256:").unwrap() }
+            }],
+            "predicateType": predicate_type,
+            "predicate": predicate,
+        });
+        let payload = serde_json::to_vec(&statement).unwrap();
+        let signature = signer
+            .sign(&pre_authentication_encoding(IN_TOTO_PAYLOAD_TYPE, &payload))
+            .unwrap();
+
+        Envelope {
+            payload_type: IN_TOTO_PAYLOAD_TYPE.to_owned(),
+            payload: BASE64.encode(payload),
+            signatures: vec![EnvelopeSignature {
+                sig: BASE64.encode(signature),
+            }],
+        }
+    }
+
+    fn public_key(signer: &SigStoreSigner) -> String {
+        signer
+            .to_sigstore_keypair()
+            .unwrap()
+            .public_key_to_pem()
+            .unwrap()
+    }
+
+    #[rstest]
+    #[case::provenance_and_sbom(true, true, true)]
+    #[case::missing_sbom(true, false, false)]
+    #[case::missing_provenance(false, true, false)]
+    fn attestations(
+        #[case] with_provenance: bool,
+        #[case] with_sbom: bool,
+        #[case] is_valid: bool,
+    ) {
+        let signer = SigningScheme::default().create_signer().unwrap();
+
+        let mut envelopes = Vec::new();
+        if with_provenance {
+            envelopes.push(sign_statement(
+                &signer,
+                SLSA_PROVENANCE_V02_PREDICATE_TYPE,
+                provenance_v02(
+                    GENERATOR_BUILDER,
+                    "git+https://github.com/kubewarden/policies",
+                ),
+            ));
+        }
+        if with_sbom {
+            envelopes.push(sign_statement(
+                &signer,
+                "https://spdx.dev/Document",
+                json!({}),
+            ));
+        }
+
+        let requirements = AttestationRequirements {
+            keys: vec![public_key(&signer)],
+            provenance: Some(provenance_requirements()),
+            sbom: true,
+        };
+
+        assert_eq!(
+            is_valid,
+            verify_attestations(&envelopes, MANIFEST_DIGEST, &requirements).is_ok()
+        );
+    }
+
+    #[test]
+    fn ignore_attestations_signed_by_untrusted_keys() {
+        let signer = SigningScheme::default().create_signer().unwrap();
+        let untrusted_signer = SigningScheme::default().create_signer().unwrap();
+
+        let envelopes = vec![sign_statement(
+            &untrusted_signer,
+            "https://spdx.dev/Document",
+            json!({}),
+        )];
+        let requirements = AttestationRequirements {
+            keys: vec![public_key(&signer)],
+            provenance: None,
+            sbom: true,
+        };
+
+        assert!(verify_attestations(&envelopes, MANIFEST_DIGEST, &requirements).is_err());
+    }
+
+    #[test]
+    fn ignore_attestations_about_other_modules() {
+        let signer = SigningScheme::default().create_signer().unwrap();
+
+        let envelopes = vec![sign_statement(
+            &signer,
+            "https://spdx.dev/Document",
+            json!({}),
+        )];
+        let requirements = AttestationRequirements {
+            keys: vec![public_key(&signer)],
+            provenance: None,
+            sbom: true,
+        };
+
+        assert!(verify_attestations(&envelopes, "sha256:1234", &requirements).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use oci_client::{
+    Client, Reference, client::ClientConfig, manifest::OciManifest, secrets::RegistryAuth,
+};
+use policy_evaluator::policy_fetcher::sources::Sources;
+use tracing::debug;
+
+/// Parse the reference of a module hosted on an OCI registry
+pub(crate) fn reference(policy_url: &str) -> Result<Reference> {
+    Reference::from_str(policy_url.trim_start_matches("registry://"))
+        .map_err(|e| anyhow!("invalid reference {}: {}", policy_url, e))
+}
+
+/// Create a client honoring the sources configuration
+pub(crate) fn client(sources: Option<&Sources>) -> Client {
+    let client_config = sources.cloned().map(ClientConfig::from).unwrap_or_default();
+    Client::new(client_config)
+}
+
+/// Use the same credentials used to pull the modules: the ones stored inside of
+/// the docker config file
+pub(crate) fn registry_auth(registry: &str) -> RegistryAuth {
+    match docker_credential::get_credential(registry) {
+        Ok(docker_credential::DockerCredential::UsernamePassword(username, password)) => {
+            RegistryAuth::Basic(username, password)
+        }
+        Ok(docker_credential::DockerCredential::IdentityToken(_)) => {
+            debug!(
+                registry,
+                "identity tokens are not supported, using anonymous access"
+            );
+            RegistryAuth::Anonymous
+        }
+        Err(_) => RegistryAuth::Anonymous,
+    }
+}
+
+/// Fetch the digests of the layers of the manifest with the given digest, living in
+/// the repository of the module referenced by `policy_url`
+pub(crate) async fn manifest_layer_digests(
+    policy_url: &str,
+    manifest_digest: &str,
+    sources: Option<&Sources>,
+) -> Result<Vec<String>> {
+    let reference = reference(policy_url)?;
+    let manifest_reference = Reference::with_digest(
+        reference.registry().to_owned(),
+        reference.repository().to_owned(),
+        manifest_digest.to_owned(),
+    );
+
+    let client = client(sources);
+    let auth = registry_auth(reference.resolve_registry());
+    let (manifest, _) = client
+        .pull_manifest(&manifest_reference, &auth)
+        .await
+        .map_err(|e| anyhow!("cannot fetch manifest {}: {}", manifest_reference, e))?;
+
+    match manifest {
+        OciManifest::Image(manifest) => Ok(manifest
+            .layers
+            .into_iter()
+            .map(|layer| layer.digest)
+            .collect()),
+        OciManifest::ImageIndex(_) => Err(anyhow!(
+            "{} is an image index, not a policy module",
+            manifest_reference
+        )),
+    }
+}
+
+/// Whether the module with the given sha256 digest is one of the layers
+pub(crate) fn contains_layer(layer_digests: &[String], sha256: &str) -> bool {
+    let sha256 = sha256.trim_start_matches("sha256:");
+    layer_digests
+        .iter()
+        .any(|digest| digest.trim_start_matches("sha256:") == sha256)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::prefixed("sha256:abcd", true)]
+    #[case::not_prefixed("abcd", true)]
+    #[case::other_layer("sha256:ef01", false)]
+    fn module_layer(#[case] sha256: &str, #[case] expected: bool) {
+        let layer_digests = vec!["sha256:1234".to_owned(), "sha256:abcd".to_owned()];
+
+        assert_eq!(expected, contains_layer(&layer_digests, sha256));
+    }
+}
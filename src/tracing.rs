@@ -1,40 +1,97 @@
 use anyhow::{Result, anyhow};
-use opentelemetry::trace::TracerProvider;
+use axum::http::{HeaderMap, Request};
+use opentelemetry::{
+    KeyValue, global,
+    propagation::Extractor,
+    trace::{TraceContextExt, TracerProvider},
+};
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
 use opentelemetry_otlp::WithTonicConfig;
 
-use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::{
+    Resource,
+    logs::SdkLoggerProvider,
+    propagation::TraceContextPropagator,
+    trace::{BatchSpanProcessor, Sampler, SdkTracerProvider},
+};
+use tower_http::trace::{DefaultMakeSpan, MakeSpan};
+use tracing::{Level, Span, debug};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::prelude::*;
-use tracing_subscriber::{EnvFilter, fmt};
+use tracing_subscriber::{filter::filter_fn, fmt, reload};
 
-use crate::config::{self, build_client_tls_config_from_env};
+use crate::config::{self, TracingConfig, build_client_tls_config_from_env};
+
+pub(crate) mod log_level;
+pub mod policy_logs;
+mod sampling;
+
+use log_level::{
+    LogLevelController, env_filter, set_log_level_controller, step_log_level_on_signals,
+};
+use policy_logs::policy_logs_layer;
+use sampling::{ErrorSamplingSpanProcessor, RecordUnsampled};
+
+/// The crates used to export the telemetry data
+const EXPORTER_TARGETS: [&str; 5] = ["h2", "hyper", "opentelemetry", "tonic", "tower"];
+
+/// The providers exporting the spans and the log records through OTLP
+pub struct OtlpProviders {
+    tracer_provider: SdkTracerProvider,
+    logger_provider: SdkLoggerProvider,
+}
+
+impl OtlpProviders {
+    /// Flushes the pending spans and log records, then shuts down the providers
+    pub fn shutdown(&self) -> Result<()> {
+        self.tracer_provider.shutdown()?;
+        self.logger_provider.shutdown()?;
+        Ok(())
+    }
+}
+
+/// Setup the logs of the commands not running the server, like `gc`. They are only printed
+/// on the standard output, using the `text` format
+pub fn setup_logging(log_level: &str, log_no_color: bool) -> Result<()> {
+    tracing_subscriber::registry()
+        .with(
+            fmt::layer()
+                .with_ansi(log_no_color)
+                .with_filter(env_filter(log_level)?),
+        )
+        .init();
+    Ok(())
+}
 
 // Setup the tracing system. This MUST be done inside of a tokio Runtime
 // because some collectors rely on it and would panic otherwise.
 //
-// The function returns an optional tracer provider that must be used to
+// The layer capturing the logs of the policies is installed only when `debug_mode`
+// is set: it's interested in the events of all the levels, hence it would disable
+// the filtering of the events done by their callsites.
+//
+// The function returns the optional OTLP providers that must be used to
 // shut down the tracing system.
 pub fn setup_tracing(
     log_level: &str,
     log_fmt: &str,
     log_no_color: bool,
-) -> Result<Option<opentelemetry_sdk::trace::SdkTracerProvider>> {
-    // setup logging
-    let filter_layer = EnvFilter::new(log_level)
-        // some of our dependencies generate trace events too, but we don't care about them ->
-        // let's filter them
-        .add_directive("cranelift_codegen=off".parse().unwrap())
-        .add_directive("cranelift_wasm=off".parse().unwrap())
-        .add_directive("h2=off".parse().unwrap())
-        .add_directive("hyper=off".parse().unwrap())
-        .add_directive("regalloc=off".parse().unwrap())
-        .add_directive("wasmtime_cranelift=off".parse().unwrap())
-        .add_directive("wasmtime_jit=off".parse().unwrap());
-
-    let tracer = match log_fmt {
+    log_no_stdout: bool,
+    tracing_config: &TracingConfig,
+    debug_mode: bool,
+) -> Result<Option<OtlpProviders>> {
+    // setup logging, the level can be changed at runtime through the reload handle.
+    // The filter applies to the layers producing the logs and the traces, not to the
+    // one capturing the logs of the policies evaluated in debug mode
+    let (filter_layer, reload_handle) = reload::Layer::new(env_filter(log_level)?);
+    set_log_level_controller(LogLevelController::new(reload_handle, log_level)?);
+    step_log_level_on_signals()?;
+
+    let otlp_providers = match log_fmt {
         "json" => {
             tracing_subscriber::registry()
-                .with(filter_layer)
-                .with(fmt::layer().json())
+                .with(fmt::layer().json().with_filter(filter_layer))
+                .with(debug_mode.then(policy_logs_layer))
                 .init();
             None
         }
@@ -42,8 +99,8 @@ pub fn setup_tracing(
             let fmt_layer = fmt::layer().with_ansi(log_no_color);
 
             tracing_subscriber::registry()
-                .with(filter_layer)
-                .with(fmt_layer)
+                .with(fmt_layer.with_filter(filter_layer))
+                .with(debug_mode.then(policy_logs_layer))
                 .init();
             None
         }
@@ -58,30 +115,137 @@ pub fn setup_tracing(
                 .with_tls_config(build_client_tls_config_from_env("OTLP")?)
                 .build()?;
 
-            let tracer_provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
-                .with_resource(
-                    Resource::builder()
-                        .with_service_name(config::SERVICE_NAME)
-                        .build(),
-                )
-                .with_batch_exporter(otlp_exporter)
-                .build();
+            let sampler = Sampler::TraceIdRatioBased(tracing_config.sampling_ratio);
+            let sampler = if tracing_config.parent_based_sampling {
+                Sampler::ParentBased(Box::new(sampler))
+            } else {
+                sampler
+            };
+            let resource = resource(tracing_config);
+            let tracer_provider_builder =
+                SdkTracerProvider::builder().with_resource(resource.clone());
+            let tracer_provider = if tracing_config.always_sample_errors {
+                tracer_provider_builder
+                    .with_sampler(RecordUnsampled(sampler))
+                    .with_span_processor(ErrorSamplingSpanProcessor::new(
+                        BatchSpanProcessor::builder(otlp_exporter).build(),
+                    ))
+                    .build()
+            } else {
+                tracer_provider_builder
+                    .with_sampler(sampler)
+                    .with_batch_exporter(otlp_exporter)
+                    .build()
+            };
 
             let tracer = tracer_provider.tracer(config::SERVICE_NAME);
 
+            // Continue the traces started by the callers, using the W3C
+            // `traceparent` and `tracestate` headers
+            global::set_text_map_propagator(TraceContextPropagator::new());
+
             // Create a tracing layer with the configured tracer
             let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
 
+            // Ship the log events as OpenTelemetry log records, correlated with
+            // the spans they are emitted within
+            let log_exporter = opentelemetry_otlp::LogExporter::builder()
+                .with_tonic()
+                .with_tls_config(build_client_tls_config_from_env("LOGS_")?)
+                .build()?;
+            let logger_provider = SdkLoggerProvider::builder()
+                .with_resource(resource)
+                .with_batch_exporter(log_exporter)
+                .build();
+            // The events of the exporters themselves are not exported, otherwise
+            // exporting a log record could produce more of them
+            let logs_layer = OpenTelemetryTracingBridge::new(&logger_provider).with_filter(
+                filter_fn(|metadata| {
+                    !EXPORTER_TARGETS
+                        .iter()
+                        .any(|target| metadata.target().starts_with(target))
+                }),
+            );
+
             tracing_subscriber::registry()
-                .with(filter_layer)
-                .with(telemetry)
-                .with(fmt::layer())
+                .with(
+                    telemetry
+                        .and_then(logs_layer)
+                        .and_then((!log_no_stdout).then(fmt::layer))
+                        .with_filter(filter_layer),
+                )
+                .with(debug_mode.then(policy_logs_layer))
                 .init();
-            Some(tracer_provider)
+            Some(OtlpProviders {
+                tracer_provider,
+                logger_provider,
+            })
         }
 
         _ => return Err(anyhow!("Unknown log message format")),
     };
 
-    Ok(tracer)
+    Ok(otlp_providers)
+}
+
+// The resource describing the policy server. The attributes set by the flags
+// override the ones of the OTEL_RESOURCE_ATTRIBUTES environment variable
+fn resource(tracing_config: &TracingConfig) -> Resource {
+    let mut attributes = vec![KeyValue::new("service.version", env!("CARGO_PKG_VERSION"))];
+    if let Some(cluster_name) = &tracing_config.cluster_name {
+        attributes.push(KeyValue::new("k8s.cluster.name", cluster_name.clone()));
+    }
+    if let Some(pod_name) = &tracing_config.pod_name {
+        attributes.push(KeyValue::new("k8s.pod.name", pod_name.clone()));
+    }
+
+    Resource::builder()
+        .with_service_name(config::SERVICE_NAME)
+        .with_attributes(attributes)
+        .build()
+}
+
+/// Creates the span of each HTTP request. The span continues the trace of the caller
+/// when the request has the W3C trace context headers, so that the spans of the
+/// policy evaluation are part of the caller's trace
+#[derive(Clone, Debug)]
+pub(crate) struct TraceContextMakeSpan {
+    inner: DefaultMakeSpan,
+}
+
+impl TraceContextMakeSpan {
+    pub(crate) fn new(level: Level) -> Self {
+        Self {
+            inner: DefaultMakeSpan::new().level(level),
+        }
+    }
+}
+
+impl<B> MakeSpan<B> for TraceContextMakeSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let span = self.inner.make_span(request);
+
+        let parent_context = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(request.headers()))
+        });
+        if parent_context.span().span_context().is_valid()
+            && let Err(e) = span.set_parent(parent_context)
+        {
+            debug!(error = %e, "cannot continue the trace of the caller");
+        }
+
+        span
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
 }
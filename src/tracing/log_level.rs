@@ -0,0 +1,330 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::{info, level_filters::LevelFilter, warn};
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+/// After how long a log level change is reverted, when not specified otherwise
+pub(crate) const DEFAULT_LOG_LEVEL_CHANGE_DURATION: Duration = Duration::from_secs(600);
+
+/// The levels SIGUSR1 and SIGUSR2 step through, from the least to the most verbose
+const LEVELS: [LevelFilter; 5] = [
+    LevelFilter::ERROR,
+    LevelFilter::WARN,
+    LevelFilter::INFO,
+    LevelFilter::DEBUG,
+    LevelFilter::TRACE,
+];
+
+static LOG_LEVEL_CONTROLLER: OnceLock<LogLevelController> = OnceLock::new();
+
+/// Builds the filter of the log events and spans from the given directives.
+/// Some of our dependencies generate trace events too, but we don't care about them,
+/// they are always filtered out
+pub(crate) fn env_filter(directives: &str) -> Result<EnvFilter> {
+    let filter = EnvFilter::try_new(directives)
+        .map_err(|e| anyhow!("invalid log level directives '{directives}': {e}"))?
+        .add_directive("cranelift_codegen=off".parse().unwrap())
+        .add_directive("cranelift_wasm=off".parse().unwrap())
+        .add_directive("h2=off".parse().unwrap())
+        .add_directive("hyper=off".parse().unwrap())
+        .add_directive("regalloc=off".parse().unwrap())
+        .add_directive("wasmtime_cranelift=off".parse().unwrap())
+        .add_directive("wasmtime_jit=off".parse().unwrap());
+    Ok(filter)
+}
+
+/// A temporary change of the log level, as requested to the admin endpoint
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LogLevelChange {
+    /// The level to be used, like `debug`
+    pub level: String,
+    /// Restrict the change to the events of this module, like `policy_server::api`
+    pub target: Option<String>,
+    /// Restrict the change to the evaluations of this policy
+    pub policy_id: Option<String>,
+    /// After how many seconds the change is reverted
+    pub duration_seconds: Option<u64>,
+}
+
+impl LogLevelChange {
+    /// The directive to be added to the default ones
+    fn directive(&self) -> String {
+        let target = self.target.as_deref().unwrap_or_default();
+        match &self.policy_id {
+            Some(policy_id) => format!(
+                "{target}[{{policy_id={}}}]={}",
+                regex::escape(policy_id),
+                self.level
+            ),
+            None if target.is_empty() => self.level.clone(),
+            None => format!("{target}={}", self.level),
+        }
+    }
+}
+
+/// The log level directives currently in use
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct LogLevelStatus {
+    /// The directives set at startup by `--log-level`
+    pub default_directives: String,
+    /// The directives in use
+    pub directives: String,
+    /// In how many seconds the directives in use are reverted to the default ones
+    pub revert_in_seconds: Option<u64>,
+}
+
+/// Changes the log level at runtime, reverting the changes once they expire
+pub(crate) struct LogLevelController {
+    handle: reload::Handle<EnvFilter, Registry>,
+    default_directives: String,
+    state: Mutex<LogLevelState>,
+}
+
+struct LogLevelState {
+    directives: String,
+    /// The level SIGUSR1 and SIGUSR2 step from
+    level: LevelFilter,
+    revert_at: Option<Instant>,
+    revert_task: Option<JoinHandle<()>>,
+}
+
+impl LogLevelController {
+    pub(crate) fn new(
+        handle: reload::Handle<EnvFilter, Registry>,
+        default_directives: &str,
+    ) -> Result<Self> {
+        let level = env_filter(default_directives)?
+            .max_level_hint()
+            .unwrap_or(LevelFilter::INFO);
+
+        Ok(Self {
+            handle,
+            default_directives: default_directives.to_owned(),
+            state: Mutex::new(LogLevelState {
+                directives: default_directives.to_owned(),
+                level,
+                revert_at: None,
+                revert_task: None,
+            }),
+        })
+    }
+
+    pub(crate) fn status(&self) -> LogLevelStatus {
+        let state = self.state.lock().expect("cannot lock the log level state");
+        LogLevelStatus {
+            default_directives: self.default_directives.clone(),
+            directives: state.directives.clone(),
+            revert_in_seconds: state.revert_at.map(|revert_at| {
+                revert_at
+                    .saturating_duration_since(Instant::now())
+                    .as_secs()
+            }),
+        }
+    }
+
+    /// Adds the directive of `change` to the default ones, replacing the previous change
+    pub(crate) fn change(&self, change: &LogLevelChange) -> Result<()> {
+        let duration = change
+            .duration_seconds
+            .map_or(DEFAULT_LOG_LEVEL_CHANGE_DURATION, Duration::from_secs);
+        self.apply(&change.directive(), None, duration)
+    }
+
+    /// Makes the logs one level more verbose when `more_verbose` is set, one level less
+    /// verbose otherwise. The changes made by `change` are discarded
+    pub(crate) fn step(&self, more_verbose: bool) -> Result<()> {
+        let level = self
+            .state
+            .lock()
+            .expect("cannot lock the log level state")
+            .level;
+        let index = LEVELS
+            .iter()
+            .position(|l| *l == level)
+            .unwrap_or(LEVELS.len() - 1);
+        let index = if more_verbose {
+            (index + 1).min(LEVELS.len() - 1)
+        } else {
+            index.saturating_sub(1)
+        };
+        let level = LEVELS[index];
+
+        self.apply(
+            &level.to_string(),
+            Some(level),
+            DEFAULT_LOG_LEVEL_CHANGE_DURATION,
+        )
+    }
+
+    /// Reverts to the directives set by `--log-level`
+    pub(crate) fn reset(&self) -> Result<()> {
+        let filter = env_filter(&self.default_directives)?;
+        let mut state = self.state.lock().expect("cannot lock the log level state");
+        self.handle.reload(filter)?;
+
+        if let Some(revert_task) = state.revert_task.take() {
+            revert_task.abort();
+        }
+        state.directives = self.default_directives.clone();
+        state.level = filter_level(&self.default_directives);
+        state.revert_at = None;
+        info!(
+            directives = self.default_directives.as_str(),
+            "log level reset"
+        );
+
+        Ok(())
+    }
+
+    fn apply(&self, directive: &str, level: Option<LevelFilter>, duration: Duration) -> Result<()> {
+        // The later directives replace the ones of the same target
+        let directives = format!("{},{directive}", self.default_directives);
+        let filter = env_filter(&directives)?;
+        let mut state = self.state.lock().expect("cannot lock the log level state");
+        self.handle.reload(filter)?;
+
+        if let Some(revert_task) = state.revert_task.take() {
+            revert_task.abort();
+        }
+        state.revert_task = tokio::runtime::Handle::try_current()
+            .map(|runtime| {
+                runtime.spawn(async move {
+                    tokio::time::sleep(duration).await;
+                    if let Some(controller) = LOG_LEVEL_CONTROLLER.get()
+                        && let Err(e) = controller.reset()
+                    {
+                        warn!(error = %e, "cannot reset the log level");
+                    }
+                })
+            })
+            .ok();
+        state.revert_at = state
+            .revert_task
+            .as_ref()
+            .map(|_| Instant::now() + duration);
+        state.directives = directives;
+        state.level = level.unwrap_or(filter_level(&self.default_directives));
+        info!(
+            directives = state.directives.as_str(),
+            revert_in_seconds = duration.as_secs(),
+            "log level changed"
+        );
+
+        Ok(())
+    }
+}
+
+fn filter_level(directives: &str) -> LevelFilter {
+    env_filter(directives)
+        .ok()
+        .and_then(|filter| filter.max_level_hint())
+        .unwrap_or(LevelFilter::INFO)
+}
+
+/// Sets the controller used to change the log level at runtime
+pub(crate) fn set_log_level_controller(controller: LogLevelController) {
+    if LOG_LEVEL_CONTROLLER.set(controller).is_err() {
+        warn!("the log level controller is already set");
+    }
+}
+
+/// The controller used to change the log level at runtime, `None` when the tracing
+/// system is not set up
+pub(crate) fn log_level_controller() -> Option<&'static LogLevelController> {
+    LOG_LEVEL_CONTROLLER.get()
+}
+
+/// Steps the log level on SIGUSR1 (more verbose) and SIGUSR2 (less verbose). Each
+/// change is reverted after `DEFAULT_LOG_LEVEL_CHANGE_DURATION`
+pub(crate) fn step_log_level_on_signals() -> Result<()> {
+    let mut sigusr1 = signal(SignalKind::user_defined1())
+        .map_err(|e| anyhow!("cannot install SIGUSR1 handler: {}", e))?;
+    let mut sigusr2 = signal(SignalKind::user_defined2())
+        .map_err(|e| anyhow!("cannot install SIGUSR2 handler: {}", e))?;
+
+    tokio::spawn(async move {
+        loop {
+            let more_verbose = tokio::select! {
+                _ = sigusr1.recv() => true,
+                _ = sigusr2.recv() => false,
+            };
+            if let Some(controller) = LOG_LEVEL_CONTROLLER.get()
+                && let Err(e) = controller.step(more_verbose)
+            {
+                warn!(error = %e, "cannot change the log level");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(None, None, "debug")]
+    #[case(Some("policy_server::api"), None, "policy_server::api=debug")]
+    #[case(
+        None,
+        Some("psp-capabilities"),
+        "[{policy_id=psp\\-capabilities}]=debug"
+    )]
+    #[case(
+        Some("policy_evaluator"),
+        Some("group/member"),
+        "policy_evaluator[{policy_id=group/member}]=debug"
+    )]
+    fn log_level_change_directive(
+        #[case] target: Option<&str>,
+        #[case] policy_id: Option<&str>,
+        #[case] expected: &str,
+    ) {
+        let change = LogLevelChange {
+            level: "debug".to_owned(),
+            target: target.map(str::to_owned),
+            policy_id: policy_id.map(str::to_owned),
+            duration_seconds: None,
+        };
+
+        assert_eq!(expected, change.directive());
+        assert!(env_filter(&format!("info,{expected}")).is_ok());
+    }
+
+    #[test]
+    fn step_log_level() {
+        // the filter can be reloaded only while the layer is alive
+        let (_layer, handle) =
+            reload::Layer::<EnvFilter, Registry>::new(env_filter("info").unwrap());
+        let controller = LogLevelController::new(handle, "info").unwrap();
+
+        for (more_verbose, expected) in [
+            (true, "info,debug"),
+            (true, "info,trace"),
+            (true, "info,trace"),
+            (false, "info,debug"),
+            (false, "info,info"),
+            (false, "info,warn"),
+        ] {
+            controller.step(more_verbose).unwrap();
+            assert_eq!(expected, controller.status().directives);
+        }
+
+        controller.reset().unwrap();
+        let status = controller.status();
+        assert_eq!("info", status.directives);
+        assert_eq!(None, status.revert_in_seconds);
+    }
+}
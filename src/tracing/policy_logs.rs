@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::BTreeMap, fmt};
+use tracing::{
+    Event, Metadata, Subscriber,
+    field::{Field, Visit},
+    subscriber::Interest,
+};
+use tracing_subscriber::{
+    Layer,
+    layer::{Context, Filter},
+};
+
+/// The crates whose events are never captured, they are too verbose to be useful
+const IGNORED_TARGETS: [&str; 5] = ["cranelift", "h2", "hyper", "regalloc", "wasmtime"];
+
+thread_local! {
+    /// The log entries captured on this thread, `None` when the capture is not active
+    static CAPTURED_LOGS: RefCell<Option<Vec<PolicyLogEntry>>> = const { RefCell::new(None) };
+}
+
+/// A log event emitted during the evaluation of a policy, like the messages the policy
+/// logs through the `log` host capability
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PolicyLogEntry {
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Runs `f` collecting all the log events it emits on the current thread, regardless of
+/// the log level in use. The policies are evaluated synchronously, hence this captures
+/// the logs of the policy together with the ones of the evaluation.
+pub(crate) fn capture_policy_logs<T>(f: impl FnOnce() -> T) -> (T, Vec<PolicyLogEntry>) {
+    let previous = CAPTURED_LOGS.with(|logs| logs.borrow_mut().replace(Vec::new()));
+    let result = f();
+    let captured = CAPTURED_LOGS.with(|logs| std::mem::replace(&mut *logs.borrow_mut(), previous));
+
+    (result, captured.unwrap_or_default())
+}
+
+fn is_capturing() -> bool {
+    CAPTURED_LOGS.with(|logs| logs.borrow().is_some())
+}
+
+/// Returns the layer storing the events captured by `capture_policy_logs`. The events are
+/// captured even when the log level filters them out
+pub(crate) fn policy_logs_layer<S: Subscriber>() -> impl Layer<S> {
+    PolicyLogsLayer.with_filter(CapturingFilter)
+}
+
+struct PolicyLogsLayer;
+
+impl<S: Subscriber> Layer<S> for PolicyLogsLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = PolicyLogVisitor::default();
+        event.record(&mut visitor);
+        let entry = PolicyLogEntry {
+            level: metadata.level().to_string().to_lowercase(),
+            target: metadata.target().to_owned(),
+            message: visitor.message,
+            fields: visitor.fields,
+        };
+
+        CAPTURED_LOGS.with(|logs| {
+            if let Some(logs) = logs.borrow_mut().as_mut() {
+                logs.push(entry);
+            }
+        });
+    }
+}
+
+/// Enables the events only while they are being captured. This cannot be cached per
+/// callsite, the same event is captured or not depending on the thread emitting it
+struct CapturingFilter;
+
+impl<S> Filter<S> for CapturingFilter {
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: &Context<'_, S>) -> bool {
+        metadata.is_event() && is_capturing()
+    }
+
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if metadata.is_event()
+            && !IGNORED_TARGETS
+                .iter()
+                .any(|target| metadata.target().starts_with(target))
+        {
+            Interest::sometimes()
+        } else {
+            Interest::never()
+        }
+    }
+}
+
+#[derive(Default)]
+struct PolicyLogVisitor {
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+impl Visit for PolicyLogVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.fields
+                .insert(field.name().to_owned(), value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields
+                .insert(field.name().to_owned(), format!("{value:?}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::{debug, info, trace};
+    use tracing_subscriber::{filter::LevelFilter, prelude::*};
+
+    #[test]
+    fn capture_events_filtered_out() {
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer().with_filter(LevelFilter::INFO))
+            .with(policy_logs_layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            info!("not captured");
+
+            let (result, logs) = capture_policy_logs(|| {
+                debug!(policy_id = "happy-policy", "evaluating");
+                trace!(response = ?Some(true), "evaluated");
+                42
+            });
+
+            assert_eq!(42, result);
+            assert_eq!(
+                vec![
+                    PolicyLogEntry {
+                        level: "debug".to_owned(),
+                        target: module_path!().to_owned(),
+                        message: "evaluating".to_owned(),
+                        fields: BTreeMap::from([(
+                            "policy_id".to_owned(),
+                            "happy-policy".to_owned()
+                        )]),
+                    },
+                    PolicyLogEntry {
+                        level: "trace".to_owned(),
+                        target: module_path!().to_owned(),
+                        message: "evaluated".to_owned(),
+                        fields: BTreeMap::from([("response".to_owned(), "Some(true)".to_owned())]),
+                    },
+                ],
+                logs
+            );
+            assert!(!is_capturing());
+        });
+    }
+}
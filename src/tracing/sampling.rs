@@ -0,0 +1,253 @@
+use opentelemetry::{
+    Context, KeyValue, Value,
+    trace::{
+        Link, SamplingDecision, SamplingResult, SpanContext, SpanId, SpanKind, Status, TraceId,
+    },
+};
+use opentelemetry_sdk::{
+    Resource,
+    error::OTelSdkResult,
+    trace::{Sampler, ShouldSample, Span, SpanData, SpanProcessor},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// Maximum number of unsampled traces kept in memory while waiting for their
+/// local root span to end. The oldest traces are discarded once it is reached
+const MAX_UNSAMPLED_TRACES: usize = 1024;
+
+/// Records the spans the wrapped sampler drops, without sampling them. This gives
+/// `ErrorSamplingSpanProcessor` the chance to export them later
+#[derive(Clone, Debug)]
+pub(crate) struct RecordUnsampled(pub(crate) Sampler);
+
+impl ShouldSample for RecordUnsampled {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let mut result =
+            self.0
+                .should_sample(parent_context, trace_id, name, span_kind, attributes, links);
+        if result.decision == SamplingDecision::Drop {
+            result.decision = SamplingDecision::RecordOnly;
+        }
+        result
+    }
+}
+
+/// Forwards the sampled spans to the wrapped processor. The spans of the unsampled
+/// traces are kept until their local root span ends, and are forwarded only when
+/// one of them is a rejected or failed evaluation
+#[derive(Debug)]
+pub(crate) struct ErrorSamplingSpanProcessor<P: SpanProcessor> {
+    inner: P,
+    unsampled_traces: Mutex<UnsampledTraces>,
+}
+
+#[derive(Debug, Default)]
+struct UnsampledTraces {
+    traces: HashMap<TraceId, UnsampledTrace>,
+    /// The trace IDs, oldest first
+    order: VecDeque<TraceId>,
+}
+
+#[derive(Debug, Default)]
+struct UnsampledTrace {
+    spans: Vec<SpanData>,
+    rejected_or_failed: bool,
+}
+
+impl<P: SpanProcessor> ErrorSamplingSpanProcessor<P> {
+    pub(crate) fn new(inner: P) -> Self {
+        Self {
+            inner,
+            unsampled_traces: Mutex::new(UnsampledTraces::default()),
+        }
+    }
+}
+
+impl UnsampledTraces {
+    /// Adds the span to its trace. Returns the trace once its local root span ends
+    fn push(&mut self, span: SpanData) -> Option<UnsampledTrace> {
+        let trace_id = span.span_context.trace_id();
+        let local_root = span.parent_span_id == SpanId::INVALID || span.parent_span_is_remote;
+
+        if !self.traces.contains_key(&trace_id) {
+            if self.order.len() >= MAX_UNSAMPLED_TRACES
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.traces.remove(&oldest);
+            }
+            self.order.push_back(trace_id);
+        }
+        let trace = self.traces.entry(trace_id).or_default();
+        trace.rejected_or_failed |= is_rejected_or_failed(&span);
+        trace.spans.push(span);
+
+        if !local_root {
+            return None;
+        }
+        self.order.retain(|id| *id != trace_id);
+        self.traces.remove(&trace_id)
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for ErrorSamplingSpanProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if span.span_context.is_sampled() {
+            self.inner.on_end(span);
+            return;
+        }
+
+        let finished_trace = self
+            .unsampled_traces
+            .lock()
+            .expect("cannot lock the unsampled traces")
+            .push(span);
+        if let Some(trace) = finished_trace
+            && trace.rejected_or_failed
+        {
+            for span in trace.spans {
+                self.inner.on_end(sampled(span));
+            }
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Whether the span is an evaluation that rejected the request, or that failed
+fn is_rejected_or_failed(span: &SpanData) -> bool {
+    matches!(span.status, Status::Error { .. })
+        || span
+            .attributes
+            .iter()
+            .any(|kv| kv.key.as_str() == "allowed" && kv.value == Value::Bool(false))
+}
+
+fn sampled(mut span: SpanData) -> SpanData {
+    let span_context = &span.span_context;
+    span.span_context = SpanContext::new(
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags().with_sampled(true),
+        span_context.is_remote(),
+        span_context.trace_state().clone(),
+    );
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::{InstrumentationScope, trace::TraceFlags};
+    use opentelemetry_sdk::trace::{SpanEvents, SpanLinks};
+    use std::{borrow::Cow, sync::Arc, time::SystemTime};
+
+    #[derive(Debug, Default)]
+    struct RecordingProcessor {
+        spans: Arc<Mutex<Vec<SpanData>>>,
+    }
+
+    impl SpanProcessor for RecordingProcessor {
+        fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+        fn on_end(&self, span: SpanData) {
+            self.spans.lock().unwrap().push(span);
+        }
+
+        fn force_flush(&self) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+            Ok(())
+        }
+    }
+
+    fn span(
+        trace_id: u128,
+        span_id: u64,
+        parent_span_id: u64,
+        sampled: bool,
+        allowed: bool,
+    ) -> SpanData {
+        SpanData {
+            span_context: SpanContext::new(
+                TraceId::from(trace_id),
+                SpanId::from(span_id),
+                TraceFlags::default().with_sampled(sampled),
+                false,
+                Default::default(),
+            ),
+            parent_span_id: SpanId::from(parent_span_id),
+            parent_span_is_remote: false,
+            span_kind: SpanKind::Internal,
+            name: Cow::Borrowed("validation"),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes: vec![KeyValue::new("allowed", allowed)],
+            dropped_attributes_count: 0,
+            events: SpanEvents::default(),
+            links: SpanLinks::default(),
+            status: Status::Unset,
+            instrumentation_scope: InstrumentationScope::builder("test").build(),
+        }
+    }
+
+    #[test]
+    fn export_only_sampled_and_rejected_traces() {
+        let exported = Arc::new(Mutex::new(Vec::new()));
+        let processor = ErrorSamplingSpanProcessor::new(RecordingProcessor {
+            spans: exported.clone(),
+        });
+
+        // sampled trace
+        processor.on_end(span(1, 1, 0, true, true));
+        // unsampled trace, accepted
+        processor.on_end(span(2, 2, 1, false, true));
+        processor.on_end(span(2, 1, 0, false, true));
+        // unsampled trace, rejected by a child span
+        processor.on_end(span(3, 2, 1, false, false));
+        processor.on_end(span(3, 1, 0, false, true));
+
+        let exported = exported.lock().unwrap();
+        let exported_spans: Vec<(TraceId, SpanId)> = exported
+            .iter()
+            .map(|span| (span.span_context.trace_id(), span.span_context.span_id()))
+            .collect();
+        assert_eq!(
+            vec![
+                (TraceId::from(1), SpanId::from(1)),
+                (TraceId::from(3), SpanId::from(2)),
+                (TraceId::from(3), SpanId::from(1)),
+            ],
+            exported_spans
+        );
+        assert!(exported.iter().all(|span| span.span_context.is_sampled()));
+        assert!(processor.unsampled_traces.lock().unwrap().traces.is_empty());
+    }
+}
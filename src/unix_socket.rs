@@ -0,0 +1,98 @@
+use anyhow::{Result, anyhow};
+use std::{
+    fs, io,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+};
+use tokio::net::UnixListener;
+
+use crate::config::UnixSocketConfig;
+
+/// Bind the Unix domain socket, replacing the socket file left behind by a previous run
+pub(crate) fn bind_unix_socket(config: &UnixSocketConfig) -> Result<UnixListener> {
+    match fs::symlink_metadata(&config.path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            fs::remove_file(&config.path).map_err(|e| {
+                anyhow!(
+                    "Cannot remove stale Unix socket {}: {e}",
+                    config.path.display()
+                )
+            })?;
+        }
+        Ok(_) => {
+            return Err(anyhow!(
+                "Cannot bind Unix socket {}: the file exists and it is not a socket",
+                config.path.display()
+            ));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(anyhow!(
+                "Cannot access Unix socket {}: {e}",
+                config.path.display()
+            ));
+        }
+    }
+
+    let listener = UnixListener::bind(&config.path)
+        .map_err(|e| anyhow!("Cannot bind Unix socket {}: {e}", config.path.display()))?;
+
+    if let Some(mode) = config.mode {
+        fs::set_permissions(&config.path, fs::Permissions::from_mode(mode)).map_err(|e| {
+            anyhow!(
+                "Cannot set permissions of Unix socket {}: {e}",
+                config.path.display()
+            )
+        })?;
+    }
+    if config.owner.is_some() || config.group.is_some() {
+        std::os::unix::fs::chown(&config.path, config.owner, config.group).map_err(|e| {
+            anyhow!(
+                "Cannot set owner of Unix socket {}: {e}",
+                config.path.display()
+            )
+        })?;
+    }
+
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn unix_socket_config(path: PathBuf, mode: Option<u32>) -> UnixSocketConfig {
+        UnixSocketConfig {
+            path,
+            mode,
+            owner: None,
+            group: None,
+            exclusive: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn bind_replacing_stale_socket() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("policy-server.sock");
+
+        let listener = bind_unix_socket(&unix_socket_config(path.clone(), None)).unwrap();
+        drop(listener);
+
+        bind_unix_socket(&unix_socket_config(path.clone(), Some(0o600))).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(0o600, metadata.permissions().mode() & 0o777);
+    }
+
+    #[tokio::test]
+    async fn do_not_replace_regular_files() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("policy-server.sock");
+        fs::write(&path, "not a socket").unwrap();
+
+        assert!(bind_unix_socket(&unix_socket_config(path.clone(), None)).is_err());
+        assert_eq!("not a socket", fs::read_to_string(&path).unwrap());
+    }
+}
@@ -2,6 +2,7 @@ use std::{
     collections::{BTreeSet, HashMap},
     net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener},
     sync::Once,
+    time::Duration,
 };
 
 use axum::Router;
@@ -9,7 +10,9 @@ use policy_evaluator::admission_response_handler::policy_mode::PolicyMode;
 use policy_evaluator::policy_evaluator::PolicySettings;
 use policy_server::{
     PolicyServer,
-    config::{Config, PolicyGroupMember, PolicyOrPolicyGroup},
+    config::{
+        Config, MetricsHistogramConfig, MetricsLabelsConfig, PolicyGroupMember, PolicyOrPolicyGroup,
+    },
 };
 use serde_json::json;
 use tempfile::tempdir;
@@ -42,6 +45,8 @@ pub(crate) fn default_test_config() -> Config {
                 context_aware_resources: BTreeSet::new(),
                 message: None,
                 timeout_eval_seconds: None,
+                verify: None,
+                debug: false,
             },
         ),
         (
@@ -60,6 +65,8 @@ pub(crate) fn default_test_config() -> Config {
                 context_aware_resources: BTreeSet::new(),
                 message: None,
                 timeout_eval_seconds: None,
+                verify: None,
+                debug: false,
             },
         ),
         (
@@ -69,6 +76,7 @@ pub(crate) fn default_test_config() -> Config {
                 policy_mode: PolicyMode::Protect,
                 allowed_to_mutate: None,
                 timeout_eval_seconds: None,
+                verify: None,
                 settings: Some(
                     PolicySettings::try_from(&json!({
                         "sleepMilliseconds": 2
@@ -77,6 +85,7 @@ pub(crate) fn default_test_config() -> Config {
                 ),
                 context_aware_resources: BTreeSet::new(),
                 message: None,
+                debug: false,
             },
         ),
         (
@@ -92,8 +101,10 @@ pub(crate) fn default_test_config() -> Config {
                         settings: None,
                         context_aware_resources: BTreeSet::new(),
                         timeout_eval_seconds: None,
+                        verify: None,
                     },
                 )]),
+                debug: false,
             },
         ),
         (
@@ -115,8 +126,10 @@ pub(crate) fn default_test_config() -> Config {
                         ),
                         context_aware_resources: BTreeSet::new(),
                         timeout_eval_seconds: None,
+                        verify: None,
                     },
                 )]),
+                debug: false,
             },
         ),
         (
@@ -126,6 +139,7 @@ pub(crate) fn default_test_config() -> Config {
                 policy_mode: PolicyMode::Protect,
                 allowed_to_mutate: None,
                 timeout_eval_seconds: Some(1),
+                verify: None,
                 settings: Some(
                     PolicySettings::try_from(&json!({
                         "sleepMilliseconds": 2
@@ -134,12 +148,16 @@ pub(crate) fn default_test_config() -> Config {
                 ),
                 context_aware_resources: BTreeSet::new(),
                 message: None,
+                debug: false,
             },
         ),
     ]);
 
     Config {
         addr: get_available_address_with_port(),
+        unix_socket: None,
+        audit_listener: None,
+        pprof_addr: None,
         readiness_probe_addr: get_available_address_with_port(),
         sources: None,
         policies,
@@ -150,23 +168,35 @@ pub(crate) fn default_test_config() -> Config {
         tls_config: None,
         pool_size: 2,
         metrics_enabled: false,
+        metrics_histograms: MetricsHistogramConfig::default(),
+        metrics_labels: MetricsLabelsConfig::default(),
         sigstore_cache_dir: tempdir().unwrap().keep(),
         verification_config: None,
         log_level: "info".to_owned(),
         log_fmt: "json".to_owned(),
         log_no_color: false,
+        log_no_stdout: false,
+        tracing_config: TracingConfig::default(),
         daemon: false,
         daemon_pid_file: "policy_server.pid".to_owned(),
         daemon_stdout_file: None,
         daemon_stderr_file: None,
         enable_pprof: false,
+        enable_log_level_endpoint: false,
         continue_on_errors: false,
+        policies_gc: None,
+        policies_integrity_check_interval: None,
+        attestation_requirements: None,
+        client_authorization: None,
+        bearer_token_auth: None,
+        shutdown_delay: Duration::ZERO,
+        shutdown_timeout: Duration::from_secs(5),
     }
 }
 
 /// Returns a random address with an available port to use with policy server. Therefore, we can
 /// have multiple policy server running at the same time in async tests
-fn get_available_address_with_port() -> SocketAddr {
+pub(crate) fn get_available_address_with_port() -> SocketAddr {
     TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
         .expect("failed to bind to available port")
         .local_addr()
@@ -29,7 +29,7 @@ use serde_json::json;
 use tokio::fs;
 use tower::ServiceExt;
 
-use crate::common::default_test_config;
+use crate::common::{default_test_config, get_available_address_with_port};
 
 #[tokio::test]
 async fn test_validate() {
@@ -82,6 +82,8 @@ async fn test_validate_custom_rejection_message() {
             context_aware_resources: BTreeSet::new(),
             message: Some("Custom error message".to_owned()),
             timeout_eval_seconds: None,
+            verify: None,
+            debug: false,
         },
     );
     let app = app(config).await;
@@ -121,6 +123,54 @@ async fn test_validate_custom_rejection_message() {
     );
 }
 
+// The logs of the policies setting `debug: true` are returned only to the mTLS clients
+// allowed to use the debug mode
+#[tokio::test]
+async fn test_validate_debug_mode_unauthenticated_client() {
+    setup();
+
+    let mut config = default_test_config();
+    config.policies.insert(
+        "pod-privileged".to_owned(),
+        PolicyOrPolicyGroup::Policy {
+            module: "ghcr.io/kubewarden/tests/pod-privileged:v0.2.1".to_owned(),
+            policy_mode: PolicyMode::Protect,
+            allowed_to_mutate: None,
+            settings: None,
+            context_aware_resources: BTreeSet::new(),
+            message: None,
+            timeout_eval_seconds: None,
+            verify: None,
+            debug: true,
+        },
+    );
+    let app = app(config).await;
+
+    for uri in [
+        "/validate/pod-privileged",
+        "/validate/pod-privileged?debug=true",
+    ] {
+        let request = Request::builder()
+            .method(http::Method::POST)
+            .header(header::CONTENT_TYPE, "application/json")
+            .uri(uri)
+            .body(Body::from(include_str!(
+                "data/pod_with_privileged_containers.json"
+            )))
+            .unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+
+        let admission_review_response: AdmissionReviewResponse =
+            serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes())
+                .unwrap();
+
+        assert!(admission_review_response.policy_logs.is_none());
+    }
+}
+
 #[tokio::test]
 #[rstest]
 #[case::pod_with_privileged_containers(
@@ -417,6 +467,67 @@ async fn test_audit_invalid_payload() {
     assert_eq!(response.status(), 422);
 }
 
+#[tokio::test]
+async fn test_audit_listener() {
+    setup();
+
+    let mut config = default_test_config();
+    let audit_addr = get_available_address_with_port();
+    config.audit_listener = Some(policy_server::config::AuditListenerConfig {
+        addr: audit_addr,
+        client_ca_file: vec![],
+        pool_size: 1,
+    });
+    let addr = config.addr;
+    let readiness_probe_addr = config.readiness_probe_addr;
+
+    tokio::spawn(async move {
+        let api_server = policy_server::PolicyServer::new_from_config(config)
+            .await
+            .unwrap();
+        api_server.run().await.unwrap();
+    });
+
+    let client = reqwest::Client::new();
+    (|| async {
+        client
+            .get(format!("http://{readiness_probe_addr}/readiness"))
+            .send()
+            .await?
+            .error_for_status()
+    })
+    .retry(ExponentialBuilder::default().with_max_times(10))
+    .await
+    .expect("policy server is not ready");
+
+    let send_audit_request = |addr| {
+        client
+            .post(format!("http://{addr}/audit/pod-privileged"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(include_str!("data/pod_with_privileged_containers.json"))
+            .send()
+    };
+    let response = (|| send_audit_request(audit_addr))
+        .retry(ExponentialBuilder::default().with_max_times(10))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // The audit endpoint is no longer served by the API listener
+    let response = send_audit_request(addr).await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    // The audit listener serves the audit endpoint only
+    let response = client
+        .post(format!("http://{audit_addr}/validate/pod-privileged"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(include_str!("data/pod_with_privileged_containers.json"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
 #[tokio::test]
 async fn test_timeout_protection_accept() {
     setup();
@@ -556,6 +667,8 @@ async fn test_verified_policy() {
             context_aware_resources: BTreeSet::new(),
             message: None,
             timeout_eval_seconds: None,
+            verify: None,
+            debug: false,
         },
     )]);
     config.verification_config = Some(verification_config);
@@ -595,6 +708,8 @@ async fn test_policy_with_invalid_settings() {
             context_aware_resources: BTreeSet::new(),
             message: None,
             timeout_eval_seconds: None,
+            verify: None,
+            debug: false,
         },
     );
     config.continue_on_errors = true;
@@ -642,6 +757,8 @@ async fn test_policy_with_wrong_url() {
             context_aware_resources: BTreeSet::new(),
             message: None,
             timeout_eval_seconds: None,
+            verify: None,
+            debug: false,
         },
     );
     config.continue_on_errors = true;
@@ -787,6 +904,12 @@ async fn test_detect_certificate_rotation() {
         cert_file: cert_file.clone(),
         key_file: key_file.clone(),
         client_ca_file: vec![first_client_ca.clone(), second_client_ca.clone()],
+        client_crl_file: vec![],
+        sni_certs: vec![],
+        min_version: policy_server::config::TlsVersion::default(),
+        cipher_suites: vec![],
+        kx_groups: vec![],
+        reload_poll_interval: None,
     });
 
     let host = config.addr.ip().to_string();
@@ -944,11 +1067,13 @@ async fn test_otel() {
 
     let metrics_output_file = NamedTempFile::new().unwrap();
     let traces_output_file = NamedTempFile::new().unwrap();
+    let logs_output_file = NamedTempFile::new().unwrap();
 
     let permissions = Permissions::from_mode(0o666);
     let files_to_set_permissions = [
         metrics_output_file.path(),
         traces_output_file.path(),
+        logs_output_file.path(),
         server_ca_file.path(),
         server_cert_file.path(),
         server_key_file.path(),
@@ -975,6 +1100,10 @@ async fn test_otel() {
             traces_output_file.path().to_str().unwrap(),
             "/tmp/traces.json",
         ))
+        .with_mount(Mount::bind_mount(
+            logs_output_file.path().to_str().unwrap(),
+            "/tmp/logs.json",
+        ))
         .with_mount(Mount::bind_mount(
             server_ca_file.path().to_str().unwrap(),
             "/certs/server-ca.pem",
@@ -1018,15 +1147,28 @@ async fn test_otel() {
     config.metrics_enabled = true;
     config.log_fmt = "otlp".to_string();
 
-    setup_metrics().unwrap();
-    setup_tracing(&config.log_level, &config.log_fmt, config.log_no_color).unwrap();
+    setup_metrics(&config.metrics_histograms, &config.metrics_labels).unwrap();
+    setup_tracing(
+        &config.log_level,
+        &config.log_fmt,
+        config.log_no_color,
+        config.log_no_stdout,
+        &config.tracing_config,
+        config.debug_mode_enabled(),
+    )
+    .unwrap();
 
     let app = app(config).await;
 
-    // one successful request
+    // one successful request, continuing the trace of the caller
+    let caller_trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
     let request = Request::builder()
         .method(http::Method::POST)
         .header(header::CONTENT_TYPE, "application/json")
+        .header(
+            "traceparent",
+            format!("00-{caller_trace_id}-00f067aa0ba902b7-01"),
+        )
         .uri("/validate/pod-privileged")
         .body(Body::from(include_str!(
             "data/pod_without_privileged_containers.json"
@@ -1047,24 +1189,26 @@ async fn test_otel() {
             .unwrap();
     let metrics = &metrics_output_json["resourceMetrics"][0]["scopeMetrics"][0];
     assert_eq!(metrics["scope"]["name"], "kubewarden");
-    assert!(
-        metrics["metrics"]
-            .as_array()
-            .unwrap()
-            .iter()
-            .any(|m| { m["name"] == "kubewarden_policy_evaluation_latency_milliseconds" }),
-        "metrics_output_json: {}",
-        serde_json::to_string_pretty(&metrics_output_json).unwrap()
-    );
-    assert!(
-        metrics["metrics"]
-            .as_array()
-            .unwrap()
-            .iter()
-            .any(|m| { m["name"] == "kubewarden_policy_evaluations_total" }),
-        "metrics_output_json: {}",
-        serde_json::to_string_pretty(&metrics_output_json).unwrap()
-    );
+    for metric_name in [
+        "kubewarden_policy_evaluation_latency_milliseconds",
+        "kubewarden_policy_evaluations_total",
+        "kubewarden_policy_evaluation_queue_wait_milliseconds",
+        "kubewarden_policy_instantiation_latency_milliseconds",
+        "kubewarden_policy_execution_latency_milliseconds",
+        "kubewarden_http_request_latency_milliseconds",
+        "kubewarden_policy_evaluations_in_flight",
+        "kubewarden_policy_evaluation_permits_available",
+    ] {
+        assert!(
+            metrics["metrics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|m| { m["name"] == metric_name }),
+            "{metric_name} not found, metrics_output_json: {}",
+            serde_json::to_string_pretty(&metrics_output_json).unwrap()
+        );
+    }
 
     let traces_output_json =
         (|| async { parse_exporter_output(traces_output_file.as_file()).await })
@@ -1073,6 +1217,26 @@ async fn test_otel() {
             .unwrap();
     let spans = &traces_output_json["resourceSpans"][0]["scopeSpans"][0];
     assert_eq!(spans["scope"]["name"], "kubewarden-policy-server");
+    assert!(
+        spans["spans"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|span| span["name"] == "validation" && span["traceId"] == caller_trace_id),
+        "the validation span is not part of the caller trace, traces_output_json: {}",
+        serde_json::to_string_pretty(&traces_output_json).unwrap()
+    );
+
+    let logs_output_json = (|| async { parse_exporter_output(logs_output_file.as_file()).await })
+        .retry(exponential_backoff)
+        .await
+        .unwrap();
+    let log_records = &logs_output_json["resourceLogs"][0]["scopeLogs"][0]["logRecords"];
+    assert!(
+        !log_records.as_array().unwrap().is_empty(),
+        "no log records found, logs_output_json: {}",
+        serde_json::to_string_pretty(&logs_output_json).unwrap()
+    );
 
     otelc.stop().await.unwrap();
 }
@@ -1181,11 +1345,23 @@ async fn test_tls(
                 .into_iter()
                 .map(|it| it.0)
                 .collect(),
+            client_crl_file: vec![],
+            sni_certs: vec![],
+            min_version: policy_server::config::TlsVersion::default(),
+            cipher_suites: vec![],
+            kx_groups: vec![],
+            reload_poll_interval: None,
         }),
         (Some(_), None) => Some(policy_server::config::TlsConfig {
             cert_file: cert_file.clone(),
             key_file: key_file.clone(),
             client_ca_file: vec![],
+            client_crl_file: vec![],
+            sni_certs: vec![],
+            min_version: policy_server::config::TlsVersion::default(),
+            cipher_suites: vec![],
+            kx_groups: vec![],
+            reload_poll_interval: None,
         }),
         _ => {
             panic!("Invalid test case")